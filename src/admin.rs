@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{error, info};
+use crate::config::AdminConfig;
+use crate::endpoint::EndpointService;
+use crate::nodes_init::{refresh_bucket, request_shutdown, started};
+use crate::proto::admin_server::{Admin, AdminServer};
+use crate::proto::{BanPeerRequest, BanPeerResponse, ChainRequest, ChainResponse, MempoolRequest, MempoolResponse, PeerScore, PeersRequest, PeersResponse,
+    RefreshBucketsRequest, RefreshBucketsResponse, RoutingTableRequest, RoutingTableResponse, ShutdownRequest, ShutdownResponse, UnbanPeerRequest, UnbanPeerResponse};
+use crate::signatures::{open, ReplayCache, Signed};
+use crate::util::*;
+
+//Introspection and control of the nodes running in this process, on a listener of its own apart from the Endpoint service.
+//With an admin key every request must be signed by it, addressed to node_id; without one the listener is only allowed on loopback.
+
+#[derive(Debug,Default)]
+pub struct AdminService{
+    key: Option<String>, //Fingerprint of the admin public key
+    replay: Arc<ReplayCache>,
+}
+impl AdminService{
+    pub fn new(key:Option<String>) -> AdminService{
+        AdminService{ key, replay: Arc::new(ReplayCache::default()) }
+    }
+    fn authorize<T: Signed>(&self, message:&T, recipient:&str) -> Result<(), Status>{
+        let Some(key) = self.key.as_ref() else { return Ok(()) };
+        let sign = open(message, recipient, &self.replay)?;
+        if key_fingerprint(&sign.pkey) != *key{ return Err(Status::permission_denied("NOT THE ADMIN KEY")) }
+        Ok(())
+    }
+    //The node addressed by the request, the first one started when node_id is empty
+    async fn service<T: Signed>(&self, message:&T, node_id:&str) -> Result<EndpointService, Status>{
+        self.authorize(message, node_id)?;
+        let services = started();
+        if node_id.is_empty(){ return services.into_iter().next().ok_or_else(|| Status::unavailable("NO NODE STARTED")) }
+        for service in services{
+            if service_id(&service).await == node_id{ return Ok(service) }
+        }
+        Err(Status::not_found("NO SUCH NODE"))
+    }
+}
+async fn service_id(service:&EndpointService) -> String{
+    service.node().await.info.unwrap().id
+}
+//A contact in the routing table is banned by its key, anything else must already be a key fingerprint
+async fn fingerprint(service:&EndpointService, peer:&str) -> Result<String, Status>{
+    let hex = !peer.is_empty() && peer.chars().all(|i| i.is_ascii_hexdigit());
+    if hex && peer.len() == service.config().dht.id_size{
+        if let Some(info) = service.node().await.get_neighbour(peer.to_string()){ return Ok(key_fingerprint(&info.pkey)) }
+    }
+    if hex && peer.len() == 64{ return Ok(peer.to_lowercase()) }
+    Err(Status::invalid_argument(format!("{} IS NEITHER A KNOWN CONTACT NOR A KEY FINGERPRINT", peer)))
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService{
+    async fn routing_table(&self, request:Request<RoutingTableRequest>) -> Result<Response<RoutingTableResponse>, Status>{
+        let request = request.get_ref();
+        let node = self.service(request, &request.node_id).await?.node().await;
+        Ok(Response::new(RoutingTableResponse{ node: node.info, buckets: node.kbuckets }))
+    }
+    async fn chain(&self, request:Request<ChainRequest>) -> Result<Response<ChainResponse>, Status>{
+        let request = request.get_ref();
+        let service = self.service(request, &request.node_id).await?;
+        Ok(Response::new(ChainResponse{ state: Some(service.chain_state().await) }))
+    }
+    async fn mempool(&self, request:Request<MempoolRequest>) -> Result<Response<MempoolResponse>, Status>{
+        let request = request.get_ref();
+        let service = self.service(request, &request.node_id).await?;
+        Ok(Response::new(MempoolResponse{ transactions: service.mempool().await, auctions: service.auctions().await }))
+    }
+    async fn peers(&self, request:Request<PeersRequest>) -> Result<Response<PeersResponse>, Status>{
+        let request = request.get_ref();
+        let reputation = self.service(request, &request.node_id).await?.reputation();
+        let scores = reputation.scores().into_iter().map(|(fingerprint, score)| PeerScore{ fingerprint, score }).collect();
+        Ok(Response::new(PeersResponse{ scores, bans: reputation.bans().bans }))
+    }
+    async fn refresh_buckets(&self, request:Request<RefreshBucketsRequest>) -> Result<Response<RefreshBucketsResponse>, Status>{
+        let request = request.get_ref();
+        let service = self.service(request, &request.node_id).await?;
+        let dht = service.config().dht.clone();
+        let buckets = match request.buckets.is_empty(){
+            true => (0..dht.buckets()).collect(),
+            false => request.buckets.iter().map(|i| *i as usize).collect::<Vec<_>>(),
+        };
+        if let Some(i) = buckets.iter().find(|i| **i >= dht.buckets()){
+            return Err(Status::invalid_argument(format!("NO BUCKET {}, THERE ARE {}", i, dht.buckets())))
+        }
+        let mut learned = 0;
+        for index in buckets{ learned += refresh_bucket(&service, index, &dht).await }
+        let node = service_id(&service).await;
+        info!(node, learned, "ADMIN REFRESHED BUCKETS");
+        Ok(Response::new(RefreshBucketsResponse{ learned: learned as u32 }))
+    }
+    async fn ban_peer(&self, request:Request<BanPeerRequest>) -> Result<Response<BanPeerResponse>, Status>{
+        let request = request.get_ref();
+        let service = self.service(request, &request.node_id).await?;
+        let fingerprint = fingerprint(&service, &request.peer).await?;
+        let reputation = service.reputation();
+        let seconds = match request.seconds{ 0 => reputation.ban_duration(), i => i };
+        reputation.ban(&fingerprint, seconds);
+        // a banned contact is dropped at once rather than when it next misbehaves
+        for i in service.node().await.get_neighbours(){
+            if key_fingerprint(&i.pkey) == fingerprint{ service.forget(i.id).await }
+        }
+        let node = service_id(&service).await;
+        info!(node, peer = %fingerprint, seconds, "ADMIN BANNED PEER");
+        Ok(Response::new(BanPeerResponse{ fingerprint }))
+    }
+    async fn unban_peer(&self, request:Request<UnbanPeerRequest>) -> Result<Response<UnbanPeerResponse>, Status>{
+        let request = request.get_ref();
+        let service = self.service(request, &request.node_id).await?;
+        let fingerprint = fingerprint(&service, &request.peer).await?;
+        let lifted = service.reputation().unban(&fingerprint);
+        let node = service_id(&service).await;
+        info!(node, peer = %fingerprint, lifted, "ADMIN UNBANNED PEER");
+        Ok(Response::new(UnbanPeerResponse{ lifted }))
+    }
+    async fn shutdown(&self, request:Request<ShutdownRequest>) -> Result<Response<ShutdownResponse>, Status>{
+        let request = request.get_ref();
+        self.authorize(request, &request.node_id)?;
+        info!("ADMIN REQUESTED SHUTDOWN");
+        // the reply goes out before the process starts stopping
+        tokio::spawn(async{
+            tokio::time::sleep(Duration::from_millis(ADMIN_SHUTDOWN_GRACE_MS)).await;
+            request_shutdown();
+        });
+        Ok(Response::new(ShutdownResponse{}))
+    }
+}
+
+pub async fn serve(listener:TcpListener, key:Option<String>) -> Result<(), tonic::transport::Error>{
+    Server::builder().add_service(AdminServer::new(AdminService::new(key))).serve_with_incoming(TcpListenerStream::new(listener)).await
+}
+//Starts the admin service when the configuration gives it an address
+pub async fn expose(config:&AdminConfig){
+    if config.listen.is_empty(){ return }
+    let listener = TcpListener::bind(&config.listen).await.expect("FAILURE BINDING ADMIN LISTENER");
+    let key = match config.key.as_str(){ "" => None, key => Some(key.to_lowercase()) };
+    info!(addr = %listener.local_addr().unwrap(), authenticated = key.is_some(), "SERVING ADMIN");
+    tokio::spawn(async move{
+        if let Err(e) = serve(listener, key).await{ error!(error = %e, "FAILURE SERVING ADMIN") }
+    });
+}
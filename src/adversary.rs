@@ -0,0 +1,48 @@
+use crate::keystore::Identity;
+use crate::proto::Node;
+
+//Misbehaviours a node can be given for testing the attacks in ThreatDragonModels. These change how the node
+//answers requests; the attacks a node starts itself are driven from scenarios.rs through the usual request helpers.
+
+#[derive(Clone,Debug)]
+pub enum Behaviour{
+    ForgeFindNode, //Answers every FindNode with its own address and key under the target's ID
+    WrongKey(Identity), //Signs its answers with this key instead of its own
+    WithholdBlocks, //Stores the blocks it receives but never relays them, and serves an empty chain
+    Aliases(Vec<String>), //Also answers requests addressed to these IDs, the Sybil identities it advertises with its own key and address
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct Persona{
+    behaviours: Vec<Behaviour>,
+}
+impl Persona{
+    pub fn honest() -> Persona{
+        Persona::default()
+    }
+    pub fn with(mut self, behaviour:Behaviour) -> Persona{
+        self.behaviours.push(behaviour);
+        self
+    }
+    pub fn forges_find_node(&self) -> bool{
+        self.behaviours.iter().any(|i| matches!(i, Behaviour::ForgeFindNode))
+    }
+    pub fn withholds_blocks(&self) -> bool{
+        self.behaviours.iter().any(|i| matches!(i, Behaviour::WithholdBlocks))
+    }
+    pub fn answers_for(&self, id:&str) -> bool{
+        self.behaviours.iter().any(|i| matches!(i, Behaviour::Aliases(ids) if ids.iter().any(|k| k == id)))
+    }
+    //The node whose key signs the answers
+    pub fn signer(&self, own:&Node) -> Node{
+        for i in self.behaviours.iter(){
+            if let Behaviour::WrongKey(identity) = i{
+                let mut info = own.info.clone().unwrap();
+                info.pkey = identity.pkey.clone();
+                info.algorithm = identity.algorithm as i32;
+                return Node{ info: Some(info), skey: identity.skey.clone(), ..own.public() }
+            }
+        }
+        own.clone()
+    }
+}
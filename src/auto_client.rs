@@ -1,34 +1,9 @@
-use endpoint::*;
 use util::*;
-use rand::Rng;
-use requests::*;
-use std::error::Error;
-use std::fmt::format;
 use std::io;
-use core::net::SocketAddr;
 use std::time::Duration;
-use tokio::task;
-use tokio::signal;
-use tonic::{Response, Status};
-use tonic::transport::{Endpoint, Server};
-use node::*;
-use proto::miner_server::*;
-use crate::proto::{NodeInfo, BucketNode, KBucket, Node, JoinResponse};
-use crate::proto::endpoint_server::EndpointServer;
-use crate::signatures::*;
 use tokio_util::sync::CancellationToken;
-use blockchain::*;
 use nodes_init::*;
-mod endpoint;
-mod util;
-mod node;
-mod proto {
-    tonic::include_proto!("kademlia");
-}
-mod blockchain;
-mod requests;
-mod signatures;
-mod nodes_init;
+use ssd::{util, nodes_init, keystore, storage, bootstrap, config, metrics, admin, logging};
 fn entry(n:i32) -> String{
     loop{
         let mut number = String::new();
@@ -46,29 +21,32 @@ fn entry(n:i32) -> String{
 
 #[tokio::main]
 async fn main(){
+    let config = std::sync::Arc::new(config::Config::from_args());
+    logging::init(&config.log);
+    bootstrap::configure(config.bootstrap_peers().unwrap()).expect("FAILURE CONFIGURING BOOTSTRAPS");
+    metrics::expose(&config.metrics).await;
+    admin::expose(&config.admin).await;
     let token = CancellationToken::new();
     let cloned_token = token.clone();
-    let mut miner_number = entry(0);
-    let mut client_number = entry(1);
+    let miner_number = entry(0);
+    let client_number = entry(1);
     tokio::select! {
         _ = cloned_token.cancelled() => {
             println!("CLOSING");
         }
         _ = tokio::time::sleep(Duration::from_secs(0)) => {tokio::spawn(async move{
             let mut res= Vec::new();
-            for i in 0..miner_number.trim().parse::<i32>().unwrap(){
-                res.push(create_client(None, true).await.expect("FAILURE INITIALIZING CLIENT"));
+            for _i in 0..miner_number.trim().parse::<i32>().unwrap(){
+                res.push(create_client(config.clone(), None, true, keystore::Identity::generate(KEY_ALGORITHM, config.dht.id_size), Box::new(storage::MemoryStore::default()), None).await.expect("FAILURE INITIALIZING CLIENT"));
             };
-            for i in 0..client_number.trim().parse::<i32>().unwrap(){
-                res.push(create_client(None, false).await.expect("FAILURE INITIALIZING CLIENT"));
+            for _i in 0..client_number.trim().parse::<i32>().unwrap(){
+                res.push(create_client(config.clone(), None, false, keystore::Identity::generate(KEY_ALGORITHM, config.dht.id_size), Box::new(storage::MemoryStore::default()), None).await.expect("FAILURE INITIALIZING CLIENT"));
             };
         });}
     }
     let test = tokio::spawn(async move {
-        match signal::ctrl_c().await {
-            Ok(()) => {token.cancel();},
-            Err(err) => { eprintln!("Unable to listen for shutdown signal: {}", err); },
-        }
+        shutdown_requested().await;
+        token.cancel();
     });
     tokio::try_join!(test).expect("FAILURE INITIALIZING CLIENTS");
 }
\ No newline at end of file
@@ -1,33 +1,34 @@
 use crate::proto::Bid;
-use std::fmt::format;
-use std::net::SocketAddr;
 use tonic::{Request, Response, Status};
-use crate::proto::endpoint_server::Endpoint;
-use crate::requests::{find_node, update_request};
-use crate::signatures::*;
 use crate::util::*;
 use sha256::{digest};
-use crate::endpoint::*;
-use crate::proto::{AbortRequest, AbortResponse, Block, MineRequest, MineResponse, Node, NodeInfo, RetrieveBlockchainRequest, RetrieveBlockchainResponse, Signature, TransactionRequest, TransactionResponse};
+use crate::config::ChainConfig;
+use crate::proto::{AbortRequest, AbortResponse, Block, MerkleStep, MineRequest, MineResponse, TransactionRequest};
 use crate::proto::miner_server::Miner;
+use crate::metrics::metrics;
 
 #[derive(Clone,Debug,Default)]
-pub struct MinerService{ source:std::sync::Arc<tokio::sync::RwLock<String>>, }
+pub struct MinerService{ source:std::sync::Arc<tokio::sync::RwLock<String>>, chain:ChainConfig, }
 impl MinerService{
     pub async fn generate_block(&self, prev: Option<Block>, transactions:Vec<String>) -> Block{
         //verify_blockchain(blockchain.clone());
         //let most_recent=blockchain.get(blockchain.len()-1).unwrap();
         let prev_hash= hash_block(prev.clone().expect("REASON"));
         let mut nonce=0;
+        let started = std::time::Instant::now();
         loop {
-            let cur=Block{prev_hash:prev_hash.clone(), nonce:nonce.clone(), merkle_root: hash_transactions(transactions.clone())};
-            let prefix = create_prefix();
-            if String::from_utf8(hash_block(cur.clone())).unwrap()[0..2]==prefix{return cur;};
+            let cur=Block{prev_hash:prev_hash.clone(), nonce, merkle_root: hash_transactions(transactions.clone())};
+            let prefix = create_prefix(self.chain.difficulty);
+            if String::from_utf8(hash_block(cur.clone())).unwrap().starts_with(&prefix){
+                metrics(&self.source.read().await).mined(nonce as u64 + 1, started.elapsed());
+                return cur;
+            };
             nonce+=1;
         }
     }
-    pub async fn init_source(&mut self, source_id:String){
+    pub async fn init_source(&mut self, source_id:String, chain:ChainConfig){
         self.source = std::sync::Arc::from(tokio::sync::RwLock::from(source_id));
+        self.chain = chain;
     }
 }
 #[tonic::async_trait]
@@ -38,7 +39,7 @@ impl Miner for MinerService{
         Ok(Response::new(MineResponse{source_id:request.get_ref().clone().source_id, new:Option::from(new)}))
     }
 
-    async fn abort(&self, request: Request<AbortRequest>) -> Result<Response<AbortResponse>, Status> {
+    async fn abort(&self, _request: Request<AbortRequest>) -> Result<Response<AbortResponse>, Status> {
         todo!()
     }
 }
@@ -48,55 +49,124 @@ pub struct MinerInfo{
     pub current_transactions: std::sync::Arc<tokio::sync::RwLock<Vec<String>>>,
     pub active_bids: std::sync::Arc<tokio::sync::RwLock<Vec<Bid>>>,
     pub queued: bool,
-    pub queued_transactions:std::sync::Arc<tokio::sync::RwLock<Vec<String>>>,
     pub miner_ip:String,
     pub miner_port:String,
 }
 impl MinerInfo {
     pub fn new() -> MinerInfo{
-        let mut current_transactions = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
-        let mut queued_transactions = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
-        let mut active_bids = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
-        MinerInfo{current_transactions, active_bids, queued:false, queued_transactions, miner_ip:String::new(), miner_port:String::new() }
+        let current_transactions = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
+        let active_bids = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
+        MinerInfo{current_transactions, active_bids, queued:false, miner_ip:String::new(), miner_port:String::new() }
     }
-    pub async fn write_transaction(&mut self, transaction:String) -> String{
-        let mut transaction_list = self.current_transactions.write().await.clone();
-        return if transaction_list.len() < TRANSACTION_NUMBER as usize {
-            transaction_list.push(transaction);
+    pub async fn write_transaction(&mut self, transaction:String, block_size:usize) -> String{
+        let mut transaction_list = self.current_transactions.write().await;
+        transaction_list.push(transaction);
+        if transaction_list.len() < block_size {
             "processed".to_string()
         } else {
-            self.queued_transactions.write().await.clone().push(transaction);
             self.queued = true;
             "queued".to_string()
         }
     }
-    pub fn reserve_address(&mut self, ip:String){
+    pub fn reserve_address(&mut self, ip:String, port:u32){
         self.miner_ip = ip.clone();
-        let addr = format!("{}:0", ip);
+        let addr = format!("{}:{}", format_host(&ip), port);
         let addr = bind(addr).unwrap().expect("FAILURE BINDING SOCKET");
         self.miner_port = addr.local_addr().unwrap().clone().port().to_string();
     }
 }
 
+//Implicit first block every chain extends, it is never stored nor sent
+pub fn genesis() -> Block{
+    Block{ prev_hash: GENESIS.as_bytes().to_vec(), nonce: 0, merkle_root: Vec::new() }
+}
 pub fn hash_block(b:Block) -> Vec<u8>{
     let v=format!("{}{}{}", String::from_utf8(b.clone().prev_hash).unwrap(), b.clone().nonce, String::from_utf8(b.clone().merkle_root).unwrap());
     digest(v).as_bytes().to_vec()
 }
 
-pub fn create_hashes(vals:Vec<String>) -> Vec<String>{
-    let mut res= Vec::new();
-    for i in 1..vals.len(){
-        if i<vals.len() && i%2==0{ res.push(format!("{}{}", digest(vals.get(i-1).unwrap()), digest(vals.get(i-1).unwrap()))) }
-        else{res.push(digest(vals.get(i).unwrap()))}
+//Transactions are gossiped, mined and proven as "sender->value->destination->nonce->key->signature" lines: the transfer,
+//then the sender's public key and its signature over the transfer, both in hex. Rewards are signed by no one, their last two are empty.
+pub fn transaction_line(sender:&str, value:u32, destination:&str, nonce:u64) -> String{
+    format!("{}->{}->{}->{}", sender, value, destination, nonce)
+}
+pub fn signed_line(transfer:&str, key:&[u8], sign:&[u8]) -> String{
+    format!("{}->{}->{}", transfer, hex::encode(key), hex::encode(sign))
+}
+pub fn request_line(request:&TransactionRequest) -> String{
+    signed_line(&transaction_line(&request.sender, request.value, &request.destination, request.nonce), &request.sender_key, &request.sender_sign)
+}
+//The transfer a line carries, which is what identifies it whoever signed it
+pub fn transfer_of(line:&str) -> &str{
+    match line.match_indices("->").nth(3){
+        Some((end, _)) => &line[..end],
+        None => line,
     }
-    res
 }
+#[derive(Clone,Debug,PartialEq)]
+pub struct Transaction{
+    pub sender: String,
+    pub value: u32,
+    pub destination: String,
+    pub nonce: u64,
+    pub key: Vec<u8>,
+    pub sign: Vec<u8>,
+}
+pub fn parse_transaction(line:&str) -> Option<Transaction>{
+    let mut parts = line.split("->");
+    let (sender, value, destination, nonce) = (parts.next()?, parts.next()?.parse().ok()?, parts.next()?, parts.next()?.parse().ok()?);
+    let (key, sign) = (hex::decode(parts.next()?).ok()?, hex::decode(parts.next()?).ok()?);
+    if parts.next().is_some(){ return None }
+    Some(Transaction{ sender: sender.to_string(), value, destination: destination.to_string(), nonce, key, sign })
+}
+
+//One level of the Merkle tree up: each pair of hashes is hashed together, the last one of an odd level with itself
+fn merkle_level(level:&[String]) -> Vec<String>{
+    level.chunks(2).map(|i| digest(format!("{}{}", i[0], i.get(1).unwrap_or(&i[0])))).collect()
+}
+fn merkle_leaves(transactions:&[String]) -> Vec<String>{
+    transactions.iter().map(|i| digest(i.as_str())).collect()
+}
+//Merkle root of the transactions, the one a block header commits its body to
 pub fn hash_transactions(transactions:Vec<String>) -> Vec<u8>{
-    let mut to_hash=transactions.clone();
-    while to_hash.len()>1{
-      to_hash=create_hashes(to_hash.clone());
+    let mut level = merkle_leaves(&transactions);
+    while level.len()>1{
+        level = merkle_level(&level);
     };
-    to_hash.get(0).unwrap().as_bytes().to_vec()
+    level.first().cloned().unwrap_or_default().into_bytes()
+}
+//Siblings on the path from the transaction at index up to the root, empty when there is no such transaction
+pub fn merkle_proof(transactions:&[String], index:usize) -> Vec<MerkleStep>{
+    let mut level = merkle_leaves(transactions);
+    let mut index = index;
+    let mut steps = Vec::new();
+    if index >= level.len(){ return steps }
+    while level.len()>1{
+        let sibling = level.get(index ^ 1).unwrap_or(&level[index]).clone();
+        steps.push(MerkleStep{ hash: sibling.into_bytes(), left: index % 2 == 1 });
+        level = merkle_level(&level);
+        index /= 2;
+    }
+    steps
+}
+//Whether the proof leads from the transaction to the root
+pub fn verify_proof(transaction:&str, steps:&[MerkleStep], root:&[u8]) -> bool{
+    let hash = steps.iter().fold(digest(transaction), |hash, step| {
+        let sibling = String::from_utf8_lossy(&step.hash);
+        if step.left{ digest(format!("{}{}", sibling, hash)) } else { digest(format!("{}{}", hash, sibling)) }
+    });
+    hash.as_bytes() == root
+}
+//Whether each header extends the one before it, the first one genesis, and carries the proof of work
+pub fn verify_headers(blocks:&[Block], difficulty:usize) -> bool{
+    let prefix = create_prefix(difficulty);
+    let mut prev = hash_block(genesis());
+    for i in blocks{
+        let hash = hash_block(i.clone());
+        if i.prev_hash != prev || !String::from_utf8_lossy(&hash).starts_with(&prefix){ return false }
+        prev = hash;
+    }
+    true
 }
 pub fn verify_blockchain(blockchain:Vec<Block>){
     for i in 1..blockchain.len(){
@@ -107,11 +177,7 @@ pub fn verify_blockchain(blockchain:Vec<Block>){
     }
 }
 
-pub fn create_prefix() -> String{
-    let res=String::new();
-    for i in 0..PREFIX_LENGTH{
-        format!("{}{}", res, 0);
-    };
-    res
+pub fn create_prefix(difficulty:usize) -> String{
+    "0".repeat(difficulty)
 }
 
@@ -0,0 +1,87 @@
+use std::fs;
+use std::sync::OnceLock;
+use crate::proto::NodeInfo;
+use crate::util::*;
+
+//A bootstrap peer as configured, "host:port" optionally followed by "@<fingerprint>" to pin its key
+#[derive(Clone,Debug,PartialEq)]
+pub struct BootstrapPeer{
+    pub host: String,
+    pub port: u32,
+    pub fingerprint: Option<String>,
+}
+impl BootstrapPeer{
+    pub fn parse(entry:&str) -> Result<BootstrapPeer, String>{
+        let (address, fingerprint) = match entry.trim().split_once('@'){
+            Some((address, fingerprint)) => (address, Some(fingerprint.trim().to_lowercase())),
+            None => (entry.trim(), None),
+        };
+        let (host, port) = address.rsplit_once(':').ok_or(format!("MISSING PORT IN BOOTSTRAP {}", entry))?;
+        let port = port.parse::<u32>().map_err(|_| format!("INVALID PORT IN BOOTSTRAP {}", entry))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty(){ return Err(format!("MISSING HOST IN BOOTSTRAP {}", entry)) }
+        Ok(BootstrapPeer{ host: host.to_string(), port, fingerprint })
+    }
+    pub fn url(&self) -> String{
+        format_url(self.host.clone(), self.port.to_string())
+    }
+    //Unpinned peers accept any key, pinned ones only the key they were configured with
+    pub fn accepts(&self, pkey:&[u8]) -> bool{
+        match &self.fingerprint{
+            Some(fingerprint) => *fingerprint == key_fingerprint(pkey),
+            None => true,
+        }
+    }
+    //Whether info is the node configured here, by one of its addresses and its port
+    pub fn lists(&self, info:&NodeInfo) -> bool{
+        (self.host == info.ip || info.addresses.contains(&self.host)) && self.port == info.port
+    }
+    pub async fn is_local(&self) -> bool{
+        ["localhost", "127.0.0.1", "0.0.0.0", "::1", "::"].contains(&self.host.as_str()) || get_ip_addresses().await.contains(&self.host)
+    }
+}
+
+fn parse_list(entries:&str) -> Vec<BootstrapPeer>{
+    entries.split([',', '\n'])
+        .map(|i| i.split('#').next().unwrap().trim())
+        .filter(|i| !i.is_empty())
+        .map(|i| BootstrapPeer::parse(i).expect("FAILURE PARSING BOOTSTRAP CONFIGURATION"))
+        .collect()
+}
+async fn default_peers() -> Vec<BootstrapPeer>{
+    let host = get_ip_address().await;
+    BOOTSTRAP_PORTS.iter().map(|i| BootstrapPeer{ host: host.clone(), port: i.parse().unwrap(), fingerprint: None }).collect()
+}
+
+static PEERS: OnceLock<Vec<BootstrapPeer>> = OnceLock::new();
+
+//Overrides the environment and configuration file, e.g. with peers given on the command line
+pub fn configure(peers:Vec<BootstrapPeer>) -> Result<(), String>{
+    if peers.is_empty(){ return Ok(()) }
+    PEERS.set(peers).map_err(|_| "BOOTSTRAPS ALREADY CONFIGURED".to_string())
+}
+//Configured peers, taken from the environment, then the configuration file, then the local default ports
+pub async fn bootstrap_peers() -> Vec<BootstrapPeer>{
+    if let Some(peers) = PEERS.get(){ return peers.clone() }
+    let mut peers = match std::env::var(BOOTSTRAPS_ENV){
+        Ok(entries) => parse_list(&entries),
+        Err(_) => fs::read_to_string(BOOTSTRAP_FILE).map(|i| parse_list(&i)).unwrap_or_default(),
+    };
+    if peers.is_empty(){ peers = default_peers().await }
+    PEERS.get_or_init(|| peers).clone()
+}
+//Whether a node announced as a bootstrap is trusted as one: a configured address needs its pinned key,
+//any other address one of the pinned keys as soon as some are configured
+pub fn trusts(peers:&[BootstrapPeer], info:&NodeInfo) -> bool{
+    let listed: Vec<_> = peers.iter().filter(|i| i.lists(info)).collect();
+    if !listed.is_empty(){ return listed.iter().all(|i| i.accepts(&info.pkey)) }
+    let fingerprint = key_fingerprint(&info.pkey);
+    let mut pins = peers.iter().filter_map(|i| i.fingerprint.as_ref()).peekable();
+    pins.peek().is_none() || pins.any(|i| *i == fingerprint)
+}
+pub async fn is_first_bootstrap(info:&NodeInfo, peers:&[BootstrapPeer]) -> bool{
+    match peers.first(){
+        Some(first) => info.bootstrap && first.port == info.port && first.is_local().await,
+        None => false,
+    }
+}
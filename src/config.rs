@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use crate::bootstrap::BootstrapPeer;
+
+pub const CONFIG_FILE: &str = "node.toml"; //Read when present and no other file is given with --config
+
+//Tunables shared by every binary, loaded from a TOML file and then overridden from the command line
+#[derive(Clone,Debug,Default,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config{
+    pub network: NetworkConfig,
+    pub dht: DhtConfig,
+    pub chain: ChainConfig,
+    pub miner: MinerConfig,
+    pub reputation: ReputationConfig,
+    pub limits: LimitsConfig,
+    pub metrics: MetricsConfig,
+    pub admin: AdminConfig,
+    pub log: LogConfig,
+}
+#[derive(Clone,Debug,Default,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig{
+    pub listen: Vec<String>, //IPs the node binds to and advertises in this order, the detected local IPv4 and IPv6 addresses when empty
+    pub bootstraps: Vec<String>, //"host:port[@fingerprint]" entries, see bootstrap.rs
+}
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DhtConfig{
+    pub id_size: usize, //Hex digits of a NODE_ID, one bucket per bit
+    pub k: usize, //How many nodes per bucket
+    pub alpha: usize, //Peers asked in parallel when a lookup is forwarded
+    pub refresh_period: u64, //Seconds between neighbour liveness checks
+    pub bucket_refresh: u64, //Seconds without a lookup before a bucket is refreshed, also how often the node republishes its contact
+}
+impl Default for DhtConfig{
+    fn default() -> Self {
+        DhtConfig{ id_size: 10, k: 5, alpha: 3, refresh_period: 5, bucket_refresh: 60 }
+    }
+}
+impl DhtConfig{
+    pub fn buckets(&self) -> usize{
+        self.id_size * 4
+    }
+}
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig{
+    pub difficulty: usize, //Leading zeros required in a block hash
+    pub block_size: usize, //Transactions collected before a block is mined
+    pub reward: u32, //Credited to the miner of every block
+    pub light: bool, //Clients keep only block headers and prove their own transactions with full nodes, miners and bootstraps ignore it
+    pub allocations: BTreeMap<String, u32>, //Balance of each account before the first block, the same on every node of a chain
+}
+impl Default for ChainConfig{
+    fn default() -> Self {
+        ChainConfig{ difficulty: 2, block_size: 10, reward: 0, light: false, allocations: BTreeMap::new() }
+    }
+}
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReputationConfig{
+    pub half_life: u64, //Seconds for a peer's score to decay halfway back to neutral
+    pub ban_threshold: i64, //Peers whose score drops below this are banned
+    pub ban_duration: u64, //Seconds a ban lasts
+}
+impl Default for ReputationConfig{
+    fn default() -> Self {
+        ReputationConfig{ half_life: 600, ban_threshold: -20, ban_duration: 3600 }
+    }
+}
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig{
+    pub peer_rate: u32, //Calls per second one peer may make to each RPC
+    pub peer_burst: u32, //Calls a peer may make at once before peer_rate applies
+    pub global_rate: u32, //Calls per second all peers together may make to each RPC
+    pub global_burst: u32,
+    pub max_message_bytes: usize, //Larger requests are refused by tonic with OUT_OF_RANGE before they are read
+    pub max_lookups: usize, //FindNode requests forwarded to other nodes at once
+    pub max_fanout: usize, //Most peers one request is forwarded or announced to, which also caps the subscribers
+}
+impl Default for LimitsConfig{
+    fn default() -> Self {
+        LimitsConfig{ peer_rate: 20, peer_burst: 40, global_rate: 500, global_burst: 1000, max_message_bytes: 4 * 1024 * 1024, max_lookups: 16, max_fanout: 64 }
+    }
+}
+#[derive(Clone,Debug,Default,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig{
+    pub listen: String, //"ip:port" the Prometheus metrics are served on, not served when empty
+}
+#[derive(Clone,Debug,Default,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig{
+    pub listen: String, //"ip:port" of the Admin service, not served when empty
+    pub key: String, //Fingerprint of the key admin requests must be signed with, listen must be a loopback address when empty
+}
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig{
+    pub level: String, //tracing filter, a level such as "debug" or per target directives such as "debug,h2=info"
+    pub format: String, //"text" or "json"
+    pub file: String, //Appended to instead of writing to stderr when set
+}
+impl Default for LogConfig{
+    fn default() -> Self {
+        LogConfig{ level: "info".to_string(), format: "text".to_string(), file: String::new() }
+    }
+}
+#[derive(Clone,Debug,Default,PartialEq,Serialize,Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MinerConfig{
+    pub port: u32, //Port of the local mining service, any free one when 0
+}
+
+#[derive(Debug,Parser)]
+#[command(about = "bloccChainz node")]
+pub struct Cli{
+    #[arg(long, help = "TOML configuration file")]
+    pub config: Option<String>,
+    #[arg(long, help = "IP address to listen on, may be repeated")]
+    pub listen: Vec<String>,
+    #[arg(long = "bootstrap", help = "Bootstrap peer as host:port[@fingerprint], may be repeated")]
+    pub bootstraps: Vec<String>,
+    #[arg(long, help = "Hex digits of a NODE_ID")]
+    pub id_size: Option<usize>,
+    #[arg(long, help = "Nodes per bucket")]
+    pub k: Option<usize>,
+    #[arg(long, help = "Peers asked in parallel by lookups")]
+    pub alpha: Option<usize>,
+    #[arg(long, help = "Seconds between neighbour liveness checks")]
+    pub refresh_period: Option<u64>,
+    #[arg(long, help = "Seconds without a lookup before a bucket is refreshed")]
+    pub bucket_refresh: Option<u64>,
+    #[arg(long, help = "Leading zeros required in a block hash")]
+    pub difficulty: Option<usize>,
+    #[arg(long, help = "Transactions per block")]
+    pub block_size: Option<usize>,
+    #[arg(long, help = "Reward credited to the miner of a block")]
+    pub reward: Option<u32>,
+    #[arg(long, help = "Run a client as a light client, keeping only block headers")]
+    pub light: bool,
+    #[arg(long, help = "Port of the mining service, any free one when 0")]
+    pub miner_port: Option<u32>,
+    #[arg(long, help = "Seconds for a peer's reputation to decay halfway back to neutral")]
+    pub half_life: Option<u64>,
+    #[arg(long, help = "Reputation below which a peer is banned")]
+    pub ban_threshold: Option<i64>,
+    #[arg(long, help = "Seconds a ban lasts")]
+    pub ban_duration: Option<u64>,
+    #[arg(long, help = "Calls per second a peer may make to each RPC")]
+    pub peer_rate: Option<u32>,
+    #[arg(long, help = "Calls a peer may burst to each RPC")]
+    pub peer_burst: Option<u32>,
+    #[arg(long, help = "Calls per second all peers together may make to each RPC")]
+    pub global_rate: Option<u32>,
+    #[arg(long, help = "Calls all peers together may burst to each RPC")]
+    pub global_burst: Option<u32>,
+    #[arg(long, help = "Largest request accepted, in bytes")]
+    pub max_message_bytes: Option<usize>,
+    #[arg(long, help = "FindNode requests forwarded at once")]
+    pub max_lookups: Option<usize>,
+    #[arg(long, help = "Most peers one request is forwarded or announced to")]
+    pub max_fanout: Option<usize>,
+    #[arg(long, help = "ip:port to serve Prometheus metrics on")]
+    pub metrics_listen: Option<String>,
+    #[arg(long, help = "ip:port to serve the Admin service on")]
+    pub admin_listen: Option<String>,
+    #[arg(long, help = "Fingerprint of the key admin requests must be signed with")]
+    pub admin_key: Option<String>,
+    #[arg(long, help = "Log filter, such as info or debug,h2=info")]
+    pub log_level: Option<String>,
+    #[arg(long, help = "Log format, text or json")]
+    pub log_format: Option<String>,
+    #[arg(long, help = "File to append the log to instead of stderr")]
+    pub log_file: Option<String>,
+}
+
+impl Config{
+    pub fn load(path:&str) -> Result<Config, String>{
+        let contents = fs::read_to_string(path).map_err(|e| format!("FAILURE READING CONFIGURATION {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("FAILURE PARSING CONFIGURATION {}: {}", path, e))
+    }
+    pub fn apply(&mut self, cli:Cli){
+        if !cli.listen.is_empty(){ self.network.listen = cli.listen }
+        if !cli.bootstraps.is_empty(){ self.network.bootstraps = cli.bootstraps }
+        if let Some(i) = cli.id_size{ self.dht.id_size = i }
+        if let Some(i) = cli.k{ self.dht.k = i }
+        if let Some(i) = cli.alpha{ self.dht.alpha = i }
+        if let Some(i) = cli.refresh_period{ self.dht.refresh_period = i }
+        if let Some(i) = cli.bucket_refresh{ self.dht.bucket_refresh = i }
+        if let Some(i) = cli.difficulty{ self.chain.difficulty = i }
+        if let Some(i) = cli.block_size{ self.chain.block_size = i }
+        if let Some(i) = cli.reward{ self.chain.reward = i }
+        if cli.light{ self.chain.light = true }
+        if let Some(i) = cli.miner_port{ self.miner.port = i }
+        if let Some(i) = cli.half_life{ self.reputation.half_life = i }
+        if let Some(i) = cli.ban_threshold{ self.reputation.ban_threshold = i }
+        if let Some(i) = cli.ban_duration{ self.reputation.ban_duration = i }
+        if let Some(i) = cli.peer_rate{ self.limits.peer_rate = i }
+        if let Some(i) = cli.peer_burst{ self.limits.peer_burst = i }
+        if let Some(i) = cli.global_rate{ self.limits.global_rate = i }
+        if let Some(i) = cli.global_burst{ self.limits.global_burst = i }
+        if let Some(i) = cli.max_message_bytes{ self.limits.max_message_bytes = i }
+        if let Some(i) = cli.max_lookups{ self.limits.max_lookups = i }
+        if let Some(i) = cli.max_fanout{ self.limits.max_fanout = i }
+        if let Some(i) = cli.metrics_listen{ self.metrics.listen = i }
+        if let Some(i) = cli.admin_listen{ self.admin.listen = i }
+        if let Some(i) = cli.admin_key{ self.admin.key = i }
+        if let Some(i) = cli.log_level{ self.log.level = i }
+        if let Some(i) = cli.log_format{ self.log.format = i }
+        if let Some(i) = cli.log_file{ self.log.file = i }
+    }
+    pub fn validate(&self) -> Result<(), String>{
+        // IDs are compared as i64, so more than 15 hex digits would overflow the distance
+        if !(1..=15).contains(&self.dht.id_size){ return Err(format!("dht.id_size MUST BE BETWEEN 1 AND 15, GOT {}", self.dht.id_size)) }
+        if self.dht.k == 0{ return Err("dht.k MUST BE POSITIVE".to_string()) }
+        if self.dht.alpha == 0 || self.dht.alpha > self.dht.k{ return Err(format!("dht.alpha MUST BE BETWEEN 1 AND k ({}), GOT {}", self.dht.k, self.dht.alpha)) }
+        if self.dht.refresh_period == 0{ return Err("dht.refresh_period MUST BE POSITIVE".to_string()) }
+        if self.dht.bucket_refresh == 0{ return Err("dht.bucket_refresh MUST BE POSITIVE".to_string()) }
+        if self.chain.difficulty > 64{ return Err(format!("chain.difficulty CANNOT EXCEED THE 64 DIGITS OF A HASH, GOT {}", self.chain.difficulty)) }
+        if self.chain.block_size == 0{ return Err("chain.block_size MUST BE POSITIVE".to_string()) }
+        if self.reputation.half_life == 0{ return Err("reputation.half_life MUST BE POSITIVE".to_string()) }
+        if self.reputation.ban_threshold >= 0{ return Err(format!("reputation.ban_threshold MUST BE NEGATIVE, GOT {}", self.reputation.ban_threshold)) }
+        let limits = &self.limits;
+        for (name, value) in [("peer_rate", limits.peer_rate as usize), ("peer_burst", limits.peer_burst as usize), ("global_rate", limits.global_rate as usize),
+            ("global_burst", limits.global_burst as usize), ("max_message_bytes", limits.max_message_bytes), ("max_lookups", limits.max_lookups), ("max_fanout", limits.max_fanout)]{
+            if value == 0{ return Err(format!("limits.{} MUST BE POSITIVE", name)) }
+        }
+        if self.miner.port > u16::MAX as u32{ return Err(format!("miner.port {} IS NOT A VALID PORT", self.miner.port)) }
+        if !self.metrics.listen.is_empty(){
+            self.metrics.listen.parse::<SocketAddr>().map_err(|_| format!("metrics.listen {} IS NOT AN ip:port ADDRESS", self.metrics.listen))?;
+        }
+        if !self.admin.key.is_empty() && (self.admin.key.len() != 64 || !self.admin.key.chars().all(|i| i.is_ascii_hexdigit())){
+            return Err(format!("admin.key {} IS NOT A KEY FINGERPRINT", self.admin.key))
+        }
+        if !self.admin.listen.is_empty(){
+            let addr = self.admin.listen.parse::<SocketAddr>().map_err(|_| format!("admin.listen {} IS NOT AN ip:port ADDRESS", self.admin.listen))?;
+            // without a key anyone who can reach the listener is the admin
+            if self.admin.key.is_empty() && !addr.ip().is_loopback(){ return Err(format!("admin.listen {} MUST BE A LOOPBACK ADDRESS WITHOUT admin.key", self.admin.listen)) }
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.log.level).map_err(|e| format!("log.level {} IS NOT A VALID FILTER: {}", self.log.level, e))?;
+        if !["text", "json"].contains(&self.log.format.as_str()){ return Err(format!("log.format MUST BE text OR json, GOT {}", self.log.format)) }
+        for listen in &self.network.listen{
+            listen.parse::<IpAddr>().map_err(|_| format!("network.listen {} IS NOT AN IP ADDRESS", listen))?;
+        }
+        self.bootstrap_peers().map(|_| ())
+    }
+    pub fn bootstrap_peers(&self) -> Result<Vec<BootstrapPeer>, String>{
+        self.network.bootstraps.iter().map(|i| BootstrapPeer::parse(i)).collect()
+    }
+    //Defaults, then the configuration file, then command line flags
+    pub fn from_args() -> Config{
+        let cli = Cli::parse();
+        let mut config = match cli.config.clone(){
+            Some(path) => Config::load(&path),
+            None if fs::metadata(CONFIG_FILE).is_ok() => Config::load(CONFIG_FILE),
+            None => Ok(Config::default()),
+        }.unwrap_or_else(|e| panic!("{}", e));
+        config.apply(cli);
+        if let Err(e) = config.validate(){ panic!("INVALID CONFIGURATION: {}", e) }
+        config
+    }
+}
@@ -1,71 +1,539 @@
-use prost::Message;
-use tonic::{Request, Response, Status};
+use tonic::{Code, Request, Response, Status};
 use tonic::transport::Server;
-use crate::proto::{Node, NodeInfo, FindNodeRequest, FindNodeResponse, UpdateRequest, UpdateResponse, JoinRequest, JoinResponse, NeighboursRequest, NeighboursResponse, RemoveRequest, RemoveResponse, Signature, TransactionRequest, TransactionResponse, Block, RetrieveBlockchainRequest, RetrieveBlockchainResponse, UpdateBlockchainRequest, UpdateBlockchainResponse, ObtainTransactionsRequest, ObtainTransactionsResponse, CreateBidRequest, CreateBidResponse, BidValueRequest, BidValueResponse};
+use crate::proto::{Bid, ChainState, Node, NodeInfo, ObservedAddress, PingRequest, PingResponse, FindNodeRequest, FindNodeResponse, UpdateRequest, UpdateResponse, JoinRequest, JoinResponse, NeighboursRequest, NeighboursResponse, RemoveRequest, RemoveResponse, Signature, TransactionRequest, TransactionResponse, Block, RetrieveBlockchainRequest, RetrieveBlockchainResponse, UpdateBlockchainRequest, UpdateBlockchainResponse, ObtainTransactionsRequest, ObtainTransactionsResponse, CreateBidRequest, CreateBidResponse, BidValueRequest, BidValueResponse, SubscribeBlocksRequest, SubscribeTransactionsRequest, AuctionsRequest, AuctionsResponse, CloseBidRequest, CloseBidResponse, BalanceRequest, BalanceResponse, TransactionStatusRequest, TransactionStatusResponse, ProofRequest, ProofResponse};
+use rand::RngCore;
 use crate::proto::miner_server::*;
-use crate::requests::{find_node, mine_request, neighbours_request, transaction_request, update_blockchain_request, update_request};
+use crate::requests::{dial_back, find_node, mine_request, subscribe_blocks_request, subscribe_transactions_request, update_blockchain_request};
 use crate::signatures::*;
 use crate::util::*;
 use crate::blockchain::*;
+use crate::propagation::*;
+use crate::keystore::Identity;
+use crate::storage::{reconcile, save_routes, BlockStore};
+use crate::config::Config;
+use crate::bootstrap::{bootstrap_peers, trusts, BootstrapPeer};
+use crate::tls::{certificate_key, serve};
+#[cfg(any(test, feature = "adversary"))]
+use crate::adversary::Persona;
+use crate::limits::RateLimiter;
+use crate::metrics::{metrics, Metrics};
+use crate::light::{confirm_transactions, LightClient};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use crate::reputation::{register, reputation, Event, Reputation};
 use crate::proto::endpoint_server::Endpoint;
 
+//Public key of the TLS client certificate, the one key a request is known to come from before its signature is checked
+fn transport_key<T>(request:&Request<T>) -> Option<Vec<u8>>{
+    request.peer_certs()?.first().and_then(|i| certificate_key(i.get_ref()))
+}
+//The TLS client key must be the key the request is signed with, so the transport and DHT identities cannot diverge
+fn check_transport<T>(request:&Request<T>, sign:&Signature) -> Result<(), Rejection>{
+    request.peer_certs().ok_or(Rejection::NoCertificate)?;
+    if transport_key(request).as_deref() != Some(sign.pkey.as_slice()){
+        return Err(Rejection::OtherKey)
+    }
+    Ok(())
+}
+
 pub type SafeNode = std::sync::Arc<tokio::sync::RwLock<Node>>;
 #[derive(Clone,Debug,Default)]
 pub struct EndpointService{
     node:SafeNode,
     bootstraps:std::sync::Arc<tokio::sync::RwLock<Vec<NodeInfo>>>,
-    blocks:std::sync::Arc<tokio::sync::RwLock<Vec<Block>>>,
+    blocks:std::sync::Arc<tokio::sync::RwLock<Box<dyn BlockStore>>>,
     miner_info: std::sync::Arc<tokio::sync::RwLock<MinerInfo>>,
     transaction_list:std::sync::Arc<tokio::sync::RwLock<Vec<String>>>,
+    announcements:Announcements,
+    replay:std::sync::Arc<ReplayCache>,
+    routes:Option<String>,
+    config:std::sync::Arc<Config>,
+    peers:Vec<BootstrapPeer>,
+    #[cfg(any(test, feature = "adversary"))]
+    persona:std::sync::Arc<tokio::sync::RwLock<Persona>>, //Misbehaviour the attack scenarios give the node, see adversary.rs
+    reputation:std::sync::Arc<Reputation>,
+    limits:std::sync::Arc<RateLimiter>,
+    metrics:std::sync::Arc<Metrics>,
+    light:Option<std::sync::Arc<tokio::sync::RwLock<LightClient>>>, //Set on light clients, which keep no block bodies
+    orphans:std::sync::Arc<tokio::sync::RwLock<Vec<UpdateBlockchainRequest>>>, //Blocks whose parent has not arrived yet, with their bodies, oldest first
+}
+
+//What became of a block handed to append_block
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum BlockOutcome{
+    Appended,
+    Known, //Already in the chain
+    Orphan, //Its parent is not known yet, appended once it is
+    Fork, //Extends an earlier block of the chain, so it competes with one this node has
+    Invalid, //No proof of work, or a body that does not match the header or overdraws a sender
 }
 
 impl EndpointService{
-    pub async fn is_correct_key(&self, source_id:String, sign:Signature){
-        if let Some(neighbour) = self.node.read().await.get_neighbour(source_id){
-            assert!(neighbour.pkey.eq(&sign.pkey))
+    pub async fn is_known_key(&self, source_id:String, sign:&Signature) -> bool{
+        match self.node.read().await.get_neighbour(source_id){
+            Some(neighbour) => neighbour.pkey == sign.pkey,
+            None => true,
+        }
+    }
+    //A request must be signed with the key its sender is known by, a mismatch counts against the peer it came from
+    pub async fn is_correct_key<T: Signed>(&self, request:&Request<T>, source_id:String) -> Result<(), Status>{
+        let sign = request.get_ref().sign().ok_or(Status::unauthenticated("MISSING SIGNATURE"))?;
+        if self.is_known_key(source_id, &sign).await{ return Ok(()) }
+        if let Some(key) = transport_key(request){ self.reputation.record(&key_fingerprint(&key), Event::BadSignature); }
+        Err(Status::unauthenticated("KEY DOES NOT MATCH THE KNOWN CONTACT"))
+    }
+    //Requests spending or bidding for an account must be signed with the key its ID is taken from
+    fn owns_account(&self, id:&str, sign:&Signature) -> Result<(), Status>{
+        if owns_id(id, &sign.pkey){ Ok(()) } else { Err(Status::permission_denied("KEY DOES NOT OWN THIS ACCOUNT")) }
+    }
+    //Fingerprint of the key the request came over, turned away when that peer is banned or over its rate limit
+    fn screen<T: Signed>(&self, request:&Request<T>) -> Result<Option<String>, Status>{
+        let peer = transport_key(request).map(|i| key_fingerprint(&i));
+        if peer.as_ref().is_some_and(|i| self.reputation.is_banned(i)){ return Err(Status::permission_denied("PEER IS BANNED")) }
+        self.limits.check(T::METHOD, peer.as_deref().unwrap_or_default())?;
+        Ok(peer)
+    }
+    //Every announcement goes out to each subscriber, so their number bounds the fan-out of a single transaction or block
+    fn check_fanout(&self) -> Result<(), Status>{
+        if self.announcements.subscribers() >= self.limits.max_fanout(){ return Err(Status::resource_exhausted("TOO MANY SUBSCRIBERS")) }
+        Ok(())
+    }
+    //Serves the call inside a span naming this node, the peer and the RPC, and counts it under the status it ended with
+    async fn observe<R>(&self, method:&'static str, peer:String, handler:impl std::future::Future<Output=Result<R, Status>>) -> Result<R, Status>{
+        let own_id = self.node.read().await.info.clone().unwrap().id;
+        let span = info_span!("rpc", node = %own_id, peer = %peer, method, outgoing = false);
+        let started = tokio::time::Instant::now();
+        let result = handler.instrument(span.clone()).await;
+        let code = result.as_ref().map_or_else(|e| e.code(), |_| Code::Ok);
+        self.metrics.rpc(method, code, started.elapsed());
+        span.in_scope(|| match &result{
+            Ok(_) => debug!(elapsed_ms = started.elapsed().as_millis() as u64, "RPC SERVED"),
+            Err(status) => info!(code = ?code, reason = status.message(), "RPC REFUSED"),
+        });
+        result
+    }
+    //Counts a failed authentication against the peer the request came from
+    fn blame(&self, peer:Option<String>, result:Result<Signature, Rejection>) -> Result<Signature, Status>{
+        if let (Err(rejection), Some(peer)) = (&result, peer){
+            if rejection.is_forgery(){ self.reputation.record(&peer, Event::BadSignature); }
+        }
+        Ok(result?)
+    }
+    //Rejects envelopes signed for another node or RPC, stale ones and replays
+    pub async fn authenticate_message<T: Signed>(&self, message:&T) -> Result<Signature, Rejection>{
+        let recipient = self.node.read().await.info.clone().unwrap().id;
+        // a Sybil persona takes requests for any of its identities
+        #[cfg(any(test, feature = "adversary"))]
+        let recipient = {
+            let persona = self.persona.read().await.clone();
+            message.sign().map(|i| i.recipient).filter(|i| persona.answers_for(i)).unwrap_or(recipient)
+        };
+        let sign = open(message, &recipient, &self.replay).inspect_err(|_| self.metrics.signature_failure(T::METHOD))?;
+        self.node.write().await.touch(&sign.pkey);
+        Ok(sign)
+    }
+    //Checks for a request addressed to no one, from a peer that cannot know this node's ID yet
+    fn authenticate_anonymous<T: Signed>(&self, request:&Request<T>) -> Result<Signature, Status>{
+        let peer = self.screen(request)?;
+        let result = open(request.get_ref(), "", &self.replay).inspect_err(|_| self.metrics.signature_failure(T::METHOD))
+            .and_then(|sign| check_transport(request, &sign).map(|_| sign));
+        self.blame(peer, result)
+    }
+    //Same checks for a request received directly, which must also come over TLS with the signing key
+    pub async fn authenticate<T: Signed>(&self, request:&Request<T>) -> Result<Signature, Status>{
+        let peer = self.screen(request)?;
+        let result = match self.authenticate_message(request.get_ref()).await{
+            Ok(sign) => check_transport(request, &sign).map(|_| sign),
+            Err(rejection) => Err(rejection),
+        };
+        self.blame(peer, result)
+    }
+    pub async fn save_routes(&self){
+        if let Some(path) = self.routes.clone(){
+            let snapshot = self.node.read().await.snapshot();
+            if let Err(e) = save_routes(&path, snapshot){ warn!(path, error = %e, "FAILURE SAVING ROUTING TABLE") }
+        }
+    }
+    pub async fn node(&self) -> Node{
+        self.node.read().await.clone()
+    }
+    pub fn reputation(&self) -> std::sync::Arc<Reputation>{
+        self.reputation.clone()
+    }
+    pub fn config(&self) -> std::sync::Arc<Config>{
+        self.config.clone()
+    }
+    pub fn peers(&self) -> Vec<BootstrapPeer>{
+        self.peers.clone()
+    }
+    pub async fn height(&self) -> usize{
+        self.blocks.read().await.height()
+    }
+    pub async fn headers(&self) -> Vec<Block>{
+        self.blocks.read().await.blocks()
+    }
+    pub async fn header(&self, hash:&[u8]) -> Option<Block>{
+        self.blocks.read().await.get(hash)
+    }
+    //Transactions of a block, which only full nodes keep
+    pub async fn body(&self, hash:&[u8]) -> Option<Vec<String>>{
+        self.blocks.read().await.body(hash)
+    }
+    pub async fn bootstraps(&self) -> Vec<NodeInfo>{
+        self.bootstraps.read().await.clone()
+    }
+    pub fn light(&self) -> Option<std::sync::Arc<tokio::sync::RwLock<LightClient>>>{
+        self.light.clone()
+    }
+    //Transactions collected for the next block, none on nodes that do not mine
+    pub async fn mempool(&self) -> Vec<String>{
+        self.miner_info.read().await.current_transactions.read().await.clone()
+    }
+    pub async fn auctions(&self) -> Vec<Bid>{
+        self.miner_info.read().await.active_bids.read().await.clone()
+    }
+    pub async fn chain_state(&self) -> ChainState{
+        self.blocks.read().await.state()
+    }
+    pub async fn balance(&self, id:&str) -> i64{
+        self.blocks.read().await.state().balance(id)
+    }
+    pub async fn apply_transaction(&self, request:&TransactionRequest){
+        let mut store = self.blocks.write().await;
+        let mut state = store.state();
+        state.apply_transaction(request.sender.clone(), request.value, request.destination.clone());
+        store.save_state(state).expect("FAILURE STORING CHAIN STATE");
+    }
+    //Records a transfer this node sends: a light client counts it once proven, whether or not a relay brings it back before it is mined
+    pub async fn sent(&self, request:TransactionRequest){
+        let Some(light) = &self.light else { return };
+        light.write().await.track(request);
+        self.confirm();
+    }
+    //Whether the transaction is in a block of this node's chain, proven to light clients and found in a body by full nodes
+    pub async fn is_confirmed(&self, transaction:&str) -> bool{
+        match &self.light{
+            Some(light) => light.read().await.is_confirmed(transaction),
+            None => self.blocks.read().await.find_transaction(transaction).is_some(),
+        }
+    }
+    pub async fn advertise(&self, ip:String) -> Node{
+        self.node.write().await.advertise(ip);
+        self.node().await
+    }
+    pub async fn set_non_routable(&self) -> Node{
+        self.node.write().await.info.as_mut().unwrap().non_routable = true;
+        self.node().await
+    }
+    //Adds contacts to the routing table, subscribing to the bootstraps among them that have a pinned key
+    pub async fn learn(&self, nodes:Vec<NodeInfo>){
+        let own_id = self.node.read().await.info.clone().unwrap().id;
+        for mut i in nodes{
+            // a peer may hand our own entry back after a restart
+            if i.id==own_id || self.reputation.is_banned_node(&i){ continue }
+            // an ID is the fingerprint of the key answering for it, so one key cannot stand for many contacts
+            if !owns_id(&i.id, &i.pkey){
+                warn!(node = %i.id, "REJECTED CONTACT: ID IS NOT ITS KEY'S");
+                continue
+            }
+            if i.bootstrap && !trusts(&self.peers, &i){
+                warn!(node = %i.id, "REJECTED BOOTSTRAP: KEY DOES NOT MATCH THE PINNED FINGERPRINT");
+                // still a contact, but never subscribed to nor sent blocks as a bootstrap
+                i.bootstrap = false;
+            }
+            self.node.write().await.new_route(i.clone(), self.config.dht.k).await;
+            if i.bootstrap{
+                let known = self.bootstraps.read().await.contains(&i.clone());
+                self.bootstraps.write().await.push(i.clone());
+                if !known{ self.subscribe(i.clone()) }
+            }
         }
     }
-    pub async fn setup_client(&mut self, ip: String, port:Option<u32>, miner:bool) -> Node{
-        let node = Node::new(ip, port, miner);
+    pub async fn looked_up(&self, target:&str){
+        self.node.write().await.looked_up(target)
+    }
+    pub async fn forget(&self, id:String){
+        self.node.write().await.forget(id)
+    }
+    pub async fn promote(&self) -> Vec<NodeInfo>{
+        self.node.write().await.promote(self.config.dht.k)
+    }
+    #[cfg(any(test, feature = "adversary"))]
+    pub async fn set_persona(&self, persona:Persona){
+        *self.persona.write().await = persona;
+    }
+    pub async fn reply<T: Signed>(&self, message:T, recipient:String) -> Response<T>{
+        let signer = self.node.read().await.clone();
+        #[cfg(any(test, feature = "adversary"))]
+        let signer = self.persona.read().await.signer(&signer);
+        Response::new(seal(message, recipient, &signer))
+    }
+    pub async fn setup_client(&mut self, config:std::sync::Arc<Config>, port:Option<u32>, miner:bool, identity:Identity, store:Box<dyn BlockStore>, routes:Option<String>) -> Node{
+        let addresses = if config.network.listen.is_empty(){ get_ip_addresses().await } else { config.network.listen.clone() };
+        let node = Node::new(addresses, port, miner, identity, &config.dht);
+        // bans are kept next to the routing table, by nodes that keep one
+        let bans = routes.as_ref().map(|i| std::path::Path::new(i).with_file_name(BANS_FILE).to_string_lossy().to_string());
+        let id = node.info.clone().unwrap().id;
+        register(&id, Reputation::new(config.reputation.clone(), bans));
+        self.reputation = reputation(&id);
+        self.limits = std::sync::Arc::new(RateLimiter::new(config.limits.clone()));
+        self.metrics = metrics(&id);
+        self.routes = routes;
+        self.config = config.clone();
+        // peers given in the configuration are this node's own, the process wide ones are the fallback
+        self.peers = match config.bootstrap_peers(){
+            Ok(peers) if !peers.is_empty() => peers,
+            _ => bootstrap_peers().await,
+        };
         self.node = SafeNode::from(tokio::sync::RwLock::from(node.clone()));
         self.bootstraps = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
-        self.blocks = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
+        let mut store = store;
+        reconcile(store.as_mut(), &config.chain).expect("FAILURE RECONCILING CHAIN STATE");
+        self.blocks = std::sync::Arc::from(tokio::sync::RwLock::from(store));
         self.transaction_list = std::sync::Arc::from(tokio::sync::RwLock::from(Vec::new()));
+        // bootstraps and miners serve and build the full chain, so only other clients may go light
+        if config.chain.light && port.is_none() && !miner{
+            info!(id, "RUNNING AS A LIGHT CLIENT");
+            self.light = Some(std::sync::Arc::new(tokio::sync::RwLock::new(LightClient::default())));
+        }
         if miner{
             self.miner_info = std::sync::Arc::from(tokio::sync::RwLock::from(MinerInfo::new()));
-            self.miner_info.write().await.reserve_address(self.node.read().await.clone().info.unwrap().ip);
+            self.miner_info.write().await.reserve_address(self.node.read().await.clone().info.unwrap().ip, config.miner.port);
             let miner = self.miner_info.read().await.clone();
             let addr = format_addr(miner.miner_ip, miner.miner_port);
-            let source_id = node.clone().info.unwrap().id;
+            let miner_node = node.clone();
             tokio::spawn(async move {
                 let mut service = MinerService::default();
-                service.init_source(source_id).await;
-                Server::builder()
-                    .add_service(MinerServer::new(service))
-                    .serve(addr.clone()).await.expect("FAILURE SETTING UP MINER SERVICE");
+                service.init_source(miner_node.info.clone().unwrap().id, config.chain.clone()).await;
+                let router = Server::builder().add_service(MinerServer::new(service));
+                serve(router, addr, &miner_node).await.expect("FAILURE SETTING UP MINER SERVICE");
             });
         };
         node
     }
+    //Keeps a long-lived stream open to the peer and feeds every announcement back through this node
+    pub fn subscribe(&self, peer:NodeInfo){
+        let service = self.clone();
+        let block_peer = peer.clone();
+        tokio::spawn(async move {
+            let own = service.node.read().await.clone();
+            let span = info_span!("subscription", node = %own.info.clone().unwrap().id, peer = %block_peer.id, stream = "blocks");
+            if let Some(mut stream) = subscribe_blocks_request(own, block_peer.clone()).instrument(span.clone()).await{
+                while let Ok(Some(block)) = stream.message().await{
+                    service.relay_block(block, &block_peer).instrument(span.clone()).await;
+                }
+            }
+        });
+        let service = self.clone();
+        tokio::spawn(async move {
+            let own = service.node.read().await.clone();
+            let span = info_span!("subscription", node = %own.info.clone().unwrap().id, peer = %peer.id, stream = "transactions");
+            if let Some(mut stream) = subscribe_transactions_request(own, peer.clone()).instrument(span.clone()).await{
+                while let Ok(Some(transaction)) = stream.message().await{
+                    service.relay_transaction(transaction, &peer).instrument(span.clone()).await;
+                }
+            }
+        });
+    }
+    //Announcements come from the peer the stream was opened to, which answers for what it relays
+    pub async fn relay_block(&self, request:UpdateBlockchainRequest, peer:&NodeInfo){
+        let fingerprint = key_fingerprint(&peer.pkey);
+        match self.authenticate_message(&request).await{
+            Ok(sign) if self.is_known_key(request.clone().source_id, &sign).await => {
+                // an announcement without a block counts as an invalid one
+                let outcome = match request.new{ Some(block) => self.append_block(block, request.transactions).await, None => BlockOutcome::Invalid };
+                if outcome == BlockOutcome::Invalid{ self.reputation.record(&fingerprint, Event::InvalidBlock); }
+            }
+            Err(rejection) if !rejection.is_forgery() => {}
+            _ => { self.reputation.record(&fingerprint, Event::BadSignature); }
+        }
+    }
+    pub async fn relay_transaction(&self, request:TransactionRequest, peer:&NodeInfo){
+        match self.authenticate_message(&request).await{
+            Ok(sign) if self.is_known_key(request.clone().source_id, &sign).await => {
+                if let Err(status) = self.process_transaction(request).await{ debug!(reason = status.message(), "DROPPED RELAYED TRANSACTION") }
+            }
+            Err(rejection) if !rejection.is_forgery() => {}
+            _ => { self.reputation.record(&key_fingerprint(&peer.pkey), Event::BadSignature); }
+        }
+    }
+    //Appends a block once and re-announces it, signed by this node, to every subscriber, then the orphans it was the missing parent of.
+    //Only a block without the proof of work or with a body that does not match it or overdraws is Invalid; one whose parent is
+    //not known yet is kept as an orphan, and one extending an earlier block is a fork this node does not follow.
+    pub async fn append_block(&self, new:Block, transactions:Vec<String>) -> BlockOutcome{
+        let outcome = self.append_one(new, transactions).await;
+        if outcome != BlockOutcome::Appended{ return outcome }
+        loop{
+            let tip = self.blocks.read().await.blocks().last().cloned().map(hash_block).unwrap_or_default();
+            let child = {
+                let mut orphans = self.orphans.write().await;
+                orphans.iter().position(|i| i.new.as_ref().is_some_and(|i| i.prev_hash == tip)).map(|i| orphans.remove(i))
+            };
+            let Some(UpdateBlockchainRequest{ new: Some(block), transactions, .. }) = child else { return outcome };
+            debug!(hash = %String::from_utf8_lossy(&hash_block(block.clone())), "ADOPTING ORPHAN");
+            self.append_one(block, transactions).await;
+        }
+    }
+    async fn append_one(&self, new:Block, transactions:Vec<String>) -> BlockOutcome{
+        let hash = String::from_utf8(hash_block(new.clone())).unwrap();
+        if self.announcements.seen(&hash).await{ return BlockOutcome::Known }
+        if !hash.starts_with(&create_prefix(self.config.chain.difficulty)){
+            warn!(hash, "REJECTED BLOCK WITHOUT PROOF OF WORK");
+            return BlockOutcome::Invalid
+        }
+        if self.light.is_none() && hash_transactions(transactions.clone()) != new.merkle_root{
+            warn!(hash, "REJECTED BLOCK BODY");
+            return BlockOutcome::Invalid
+        }
+        let mut blocks = self.blocks.write().await;
+        if blocks.get(hash.as_bytes()).is_some(){ return BlockOutcome::Known }
+        let tip = blocks.blocks().last().cloned().unwrap_or_else(genesis);
+        if new.prev_hash != hash_block(tip){
+            // a competing block for a height this node already has
+            if blocks.blocks().into_iter().chain([genesis()]).any(|i| hash_block(i) == new.prev_hash){
+                debug!(hash, "IGNORED FORK BLOCK");
+                self.metrics.fork();
+                return BlockOutcome::Fork
+            }
+            debug!(hash, "KEEPING ORPHAN BLOCK");
+            let mut orphans = self.orphans.write().await;
+            if !orphans.iter().any(|i| i.new.as_ref() == Some(&new)){
+                if orphans.len() >= MAX_ORPHANS{ orphans.remove(0); }
+                orphans.push(UpdateBlockchainRequest{ new: Some(new), transactions, ..Default::default() });
+            }
+            return BlockOutcome::Orphan
+        }
+        // balances follow the bodies, light clients only count their own transfers once proven
+        let mut state = blocks.state();
+        let body = if self.light.is_none(){ &transactions[..] } else { &[] };
+        if let Err(reason) = state.apply_block(&new, body, self.config.chain.reward){
+            warn!(hash, reason, "REJECTED BLOCK");
+            return BlockOutcome::Invalid
+        }
+        blocks.append(&new).expect("FAILURE STORING BLOCK");
+        if self.light.is_none(){ blocks.save_body(&hash_block(new.clone()), &transactions).expect("FAILURE STORING BLOCK BODY") }
+        blocks.save_state(state).expect("FAILURE STORING CHAIN STATE");
+        drop(blocks);
+        self.announcements.first_seen(hash).await;
+        // a new header may hold the transactions a light client waits on, and a light client has no body to pass on
+        let mut transactions = transactions;
+        if self.light.is_some(){ self.confirm(); transactions.clear() }
+        #[cfg(any(test, feature = "adversary"))]
+        if self.persona.read().await.withholds_blocks(){ return BlockOutcome::Appended }
+        let info = self.node.read().await.info.clone().unwrap();
+        self.announcements.announce_block(UpdateBlockchainRequest{source_id:info.id, new:Option::from(new), sign:None, transactions});
+        BlockOutcome::Appended
+    }
+    //Appends the headers that extend this node's chain, as fetched when a light client syncs
+    pub async fn extend_headers(&self, headers:Vec<Block>) -> usize{
+        let mut added = 0;
+        for new in headers{
+            let mut blocks = self.blocks.write().await;
+            let tip = blocks.blocks().last().cloned().unwrap_or_else(genesis);
+            // the block may have been announced meanwhile
+            if new.prev_hash != hash_block(tip){ continue }
+            blocks.append(&new).expect("FAILURE STORING BLOCK");
+            let mut state = blocks.state();
+            state.apply_block(&new, &[], self.config.chain.reward).expect("FAILURE APPLYING HEADER");
+            blocks.save_state(state).expect("FAILURE STORING CHAIN STATE");
+            self.announcements.first_seen(String::from_utf8(hash_block(new.clone())).unwrap()).await;
+            added += 1;
+        }
+        added
+    }
+    //Looks for proofs of the light client's unconfirmed transactions in the background
+    fn confirm(&self){
+        let service = self.clone();
+        tokio::spawn(async move { confirm_transactions(&service).await }.in_current_span());
+    }
+    //What the sender can still spend: its balance less the transfers from it waiting in this miner's next block
+    pub async fn spendable(&self, id:&str) -> i64{
+        let pending:i64 = self.mempool().await.iter().filter_map(|i| parse_transaction(i)).filter(|i| i.sender == id).map(|i| i.value as i64).sum();
+        self.balance(id).await - pending
+    }
+    //Admits, relays and, on a miner, queues a transaction signed by its sender; full nodes refuse one its sender cannot pay for.
+    //Balances only move once the transaction is in a block.
+    pub async fn process_transaction(&self, request:TransactionRequest) -> Result<String, Status>{
+        let mut status = "".to_string();
+        let node = self.node.read().await.clone();
+        let blocks = self.blocks.read().await.blocks();
+        let mut miner = self.miner_info.write().await.clone();
+        let transaction = request_line(&request);
+        let transfer = transfer_of(&transaction).to_string();
+        // the seen cache forgets, the chain and the mempool do not: a nonce is spent once
+        if self.light.is_none() && self.chain_state().await.is_spent(&request.sender, request.nonce){ return Err(Status::already_exists("TRANSACTION ALREADY CONFIRMED")) }
+        if self.announcements.seen(&transfer).await{return Ok(status)}
+        if request.sender == COINBASE{ return Err(Status::invalid_argument("REWARDS ARE ONLY MINED")) }
+        // relays cannot sign for the sender, so its own signature travels with the transfer
+        if !owns_id(&request.sender, &request.sender_key) || !verify_transfer(&transfer, &request.sender_sign, &request.sender_key){
+            return Err(Status::permission_denied("TRANSFER NOT SIGNED BY ITS SENDER"))
+        }
+        if self.mempool().await.iter().filter_map(|i| parse_transaction(i)).any(|i| i.sender == request.sender && i.nonce == request.nonce){
+            return Err(Status::already_exists("TRANSACTION ALREADY PENDING"))
+        }
+        if self.light.is_none() && self.spendable(&request.sender).await < request.value as i64{ return Err(Status::failed_precondition("INSUFFICIENT BALANCE")) }
+        if !self.announcements.first_seen(transfer).await{return Ok(status)}
+        if node.info.clone().unwrap().miner && !miner.current_transactions.read().await.clone().contains(&transaction){
+            info!(sender = %request.sender, destination = %request.destination, value = request.value, "RECEIVED TRANSACTION");
+            status = miner.write_transaction(transaction.clone(), self.config.chain.block_size).await;
+            if status=="queued"{
+                let reward = TransactionRequest{source_id:node.info.clone().unwrap().id, sender:COINBASE.to_string(), value:self.config.chain.reward, destination:node.info.clone().unwrap().id, nonce:new_nonce(), ..Default::default()};
+                // the full block is mined, the next transactions start a new one
+                let transfers = std::mem::take(&mut *miner.current_transactions.write().await);
+                let mut transactions = transfers.clone();
+                if reward.value > 0{ transactions.insert(0, request_line(&reward)) }
+                let new = mine_request(node.clone(), blocks.last().cloned().unwrap_or_else(genesis),
+                             transactions.clone(), format_url(miner.miner_ip, miner.miner_port)).await;
+                let boot = self.bootstraps.read().await.first().cloned();
+                // the reward line heads the body, every node credits it when the block is applied
+                match (new, boot){
+                    (Some(new), Some(boot)) => update_blockchain_request(node.clone(), new, transactions, boot).await,
+                    // without a bootstrap to relay it, the block goes out to this node's own subscribers
+                    (Some(new), None) => { self.append_block(new, transactions).await; }
+                    (None, _) => {
+                        warn!("FAILURE MINING BLOCK, TRANSACTIONS KEPT FOR THE NEXT ONE");
+                        miner.current_transactions.write().await.splice(0..0, transfers);
+                    }
+                }
+            };
+        };
+        let own_id = node.info.clone().unwrap().id;
+        // light clients do not count others' transfers, and their own only once proven to be in a block
+        if let Some(light) = &self.light{
+            if request.sender == own_id || request.destination == own_id{
+                light.write().await.track(request.clone());
+                self.confirm();
+            }
+        }
+        if request.destination==node.info.clone().unwrap().id{let mut t = self.transaction_list.write().await.clone(); t.push(transaction);};
+        let info = node.info.clone().unwrap();
+        self.announcements.announce_transaction(TransactionRequest{source_id:info.id, sign:None, ..request});
+        Ok(status)
+    }
 }
 #[tonic::async_trait]
 impl Endpoint for EndpointService{
     async fn join(&self, request: Request<JoinRequest>)  -> Result<Response<JoinResponse>, Status> {
-        let req = request.get_ref().clone().node.unwrap().info;
-        verify_join_request(request.get_ref().clone());
-        let mut neighbours = Vec::new();
-        if let Some(sender) = req {
+        self.observe("Join", request.get_ref().node.clone().and_then(|i| i.info).unwrap_or_default().id, async move {
+            // joining nodes cannot know this node's ID yet, so join requests are addressed to no one
+            self.authenticate_anonymous(&request)?;
+            let sender = request.get_ref().clone().node.and_then(|i| i.info).ok_or(Status::invalid_argument("MISSING NODE"))?;
+            let recipient = sender.id.clone();
+            let mut neighbours = Vec::new();
+            let mut observed = None;
             let own = self.node.read().await.clone();
+            // tell the sender where its request came from and whether it can be dialed back there
+            if let Some(addr) = request.remote_addr(){
+                let ip = addr.ip().to_canonical().to_string();
+                observed = Some(ObservedAddress{ ip: ip.clone(), port: addr.port() as u32, reachable: dial_back(&own, &sender, ip).await });
+            }
             let bootstraps = self.bootstraps.read().await.clone();
-            println!("Got a request from {}@{}", sender.id, sender.port);
+            debug!(port = sender.port, "JOIN REQUEST");
             if let Some(info) = own.info.clone() {
                 if !info.bootstrap {
-                    println!("NOT A BOOTSTRAP DESTINATION, PLEASE CHECK THE AVAILABLE NODES");
-                    let hash=sign_join_response(Vec::new(), Vec::new(), own.skey.clone());
-                    let sign = Signature{hash, pkey:own.info.clone().unwrap().pkey.clone()};
-                    return Ok(Response::new(JoinResponse { neighbours: Vec::new(), blockchain:Vec::new(), sign:Option::from(sign) }));
+                    warn!("NOT A BOOTSTRAP DESTINATION, PLEASE CHECK THE AVAILABLE NODES");
+                    return Ok(self.reply(JoinResponse { neighbours: Vec::new(), blockchain:Vec::new(), sign:None, observed }, recipient).await);
                 }
-                if sender.bootstrap { neighbours = own.get_neighbours(); } else { neighbours = own.get_closest_nodes(sender.clone()); };
+                if sender.bootstrap { neighbours = own.get_neighbours(); } else { neighbours = own.get_closest_nodes(sender.clone(), self.config.dht.k); };
                 neighbours.push(info.clone());
                 let mut req = Vec::new();
                 req.push(sender.clone());
@@ -75,135 +543,287 @@ impl Endpoint for EndpointService{
                     }
                 }
             }
-        };
-        let hash = sign_join_response(neighbours.clone(), self.node.read().await.clone().blockchain, self.node.read().await.clone().skey);
-        let sign = Signature{hash, pkey:self.node.read().await.info.clone().unwrap().pkey.clone()};
-        Ok(Response::new(JoinResponse{ neighbours, blockchain:self.node.read().await.clone().blockchain, sign:Option::from(sign) }))
+            Ok(self.reply(JoinResponse{ neighbours, blockchain:self.node.read().await.clone().blockchain, sign:None, observed }, recipient).await)
+        }).await
+    }
+    async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PingResponse>, Status>{
+        self.observe("Ping", request.get_ref().node.clone().unwrap_or_default().id, async move {
+            match request.get_ref().sign.as_ref().is_some_and(|i| i.recipient.is_empty()){
+                true => self.authenticate_anonymous(&request)?,
+                false => self.authenticate(&request).await?,
+            };
+            let recipient = request.get_ref().node.clone().unwrap_or_default().id;
+            let observed = request.remote_addr().map(|addr| ObservedAddress{ ip: addr.ip().to_canonical().to_string(), port: addr.port() as u32, reachable: false });
+            let node = self.node.read().await.info.clone();
+            Ok(self.reply(PingResponse{ response: true, sign:None, observed, node }, recipient).await)
+        }).await
     }
     async fn find_node(&self, request: Request<FindNodeRequest>) -> Result<Response<FindNodeResponse>, Status>{
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_find_node_request(request.get_ref().clone());
-        println!("NODE {} FINDING {}", self.node.read().await.info.clone().unwrap().id, request.get_ref().clone().target);
-        if let Some(res) = self.node.read().await.get_neighbour(request.get_ref().clone().target){
-            let hash = sign_find_node_response(self.node.read().await.clone().info.unwrap().id, res.clone(),self.node.read().await.clone().skey);
-            let sign = Signature{hash, pkey:self.node.read().await.info.clone().unwrap().pkey.clone()};
-            return Ok(Response::new(FindNodeResponse{source_id:self.node.read().await.clone().info.unwrap().id,node:Option::from(res), sign:Option::from(sign)}))
-        }
-        let closest = self.node.read().await.get_bucket(request.get_ref().clone().target);
-        for i in closest{
-            let response = find_node(self.node.read().await.clone(), request.get_ref().clone().target, format_url(i.ip, i.port.to_string()))
-                .await;
-            if let Some(res) = response{
-                let hash = sign_find_node_response(self.node.read().await.clone().info.unwrap().id,res.clone(), self.node.read().await.skey.clone());
-                let sign = Signature{hash, pkey:self.node.read().await.info.clone().unwrap().pkey.clone()};
-                return Ok(Response::new(FindNodeResponse{source_id:self.node.read().await.clone().info.unwrap().id,node:Option::from(res), sign:Option::from(sign)}))
-            }
-        }
-        Ok(Response::new(FindNodeResponse{source_id:self.node.read().await.clone().info.unwrap().id,node:None, sign:None}))
+        self.observe("FindNode", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            debug!(id = %request.get_ref().target, "FINDING NODE");
+            let recipient = request.get_ref().clone().source_id;
+            let target = request.get_ref().clone().target;
+            #[cfg(any(test, feature = "adversary"))]
+            if self.persona.read().await.forges_find_node(){
+                let own = self.node.read().await.info.clone().unwrap();
+                return Ok(self.reply(FindNodeResponse{source_id:own.id.clone(), node:Some(NodeInfo{ id: target, ..own }), sign:None}, recipient).await)
+            }
+            self.node.write().await.looked_up(&target);
+            let neighbour = self.node.read().await.get_neighbour(target.clone());
+            if let Some(res) = neighbour{
+                return Ok(self.reply(FindNodeResponse{source_id:self.node.read().await.clone().info.unwrap().id,node:Option::from(res), sign:None}, recipient).await)
+            }
+            // only forward to contacts closer to the target than this node, so a lookup for a missing ID ends
+            let own_id = self.node.read().await.info.clone().unwrap().id;
+            let mut closest:Vec<NodeInfo> = self.node.read().await.closest_to(&target, self.config.dht.k).into_iter()
+                .filter(|i| Node::distance(i.id.clone(), target.clone()) < Node::distance(own_id.clone(), target.clone())).collect();
+            self.reputation.prefer(&mut closest);
+            closest.truncate(self.limits.max_fanout());
+            // forwarded lookups hold a connection to every contact asked, so only so many run at once
+            let _permit = if closest.is_empty(){ None } else { Some(self.limits.lookup()?) };
+            for batch in closest.chunks(self.config.dht.alpha){
+                let mut lookups = tokio::task::JoinSet::new();
+                for i in batch{
+                    lookups.spawn(find_node(self.node.read().await.clone(), request.get_ref().clone().target, i.clone()));
+                }
+                while let Some(response) = lookups.join_next().await{
+                    if let Ok(Some(res)) = response{
+                        return Ok(self.reply(FindNodeResponse{source_id:self.node.read().await.clone().info.unwrap().id,node:Option::from(res), sign:None}, recipient).await)
+                    }
+                }
+            }
+            Ok(self.reply(FindNodeResponse{source_id:self.node.read().await.clone().info.unwrap().id,node:None, sign:None}, recipient).await)
+        }).await
     }
     async fn update_node(&self, request: Request<UpdateRequest>) -> Result<Response<UpdateResponse>, Status> {
-        verify_update_request(request.get_ref().clone());
-        let nodes = request.get_ref().clone().neighbours;
-        for i in nodes{
-            self.node.write().await.new_route(i.clone()).await;
-            if i.bootstrap{
-                self.bootstraps.write().await.push(i.clone());
-            }
-        }
-        let hash = sign_update_response(self.node.read().await.clone().info.unwrap().id,true, self.node.read().await.skey.clone());
-        let sign = Signature{hash, pkey:self.node.read().await.info.clone().unwrap().pkey.clone()};
-        return Ok(Response::new(UpdateResponse{ source_id:self.node.read().await.clone().info.unwrap().id,response:true, sign:Option::from(sign) }))
+        self.observe("UpdateNode", request.get_ref().source_id.clone(), async move {
+            self.authenticate(&request).await?;
+            self.learn(request.get_ref().clone().neighbours).await;
+            let recipient = request.get_ref().clone().source_id;
+            return Ok(self.reply(UpdateResponse{ source_id:self.node.read().await.clone().info.unwrap().id,response:true, sign:None }, recipient).await)
+        }).await
     }
     async fn get_neighbours(&self, request: Request<NeighboursRequest>) -> Result<Response<NeighboursResponse>, Status>{
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_neighbours_request(request.get_ref().clone());
-        let neighbours=self.node.read().await.get_neighbours();
-        let hash = sign_neighbours_response(self.node.read().await.clone().info.unwrap().id,neighbours.clone(), self.node.read().await.skey.clone());
-        let sign = Signature{hash, pkey:self.node.read().await.info.clone().unwrap().pkey.clone()};
-        return Ok(Response::new(NeighboursResponse{ source_id:self.node.read().await.clone().info.unwrap().id,neighbours, sign:Option::from(sign)}));
+        self.observe("GetNeighbours", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let neighbours=self.node.read().await.get_neighbours();
+            let recipient = request.get_ref().clone().source_id;
+            return Ok(self.reply(NeighboursResponse{ source_id:self.node.read().await.clone().info.unwrap().id,neighbours, sign:None}, recipient).await);
+        }).await
     }
     async fn remove_node(&self, request: Request<RemoveRequest>) -> Result<Response<RemoveResponse>, Status>{
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_remove_request(request.get_ref().clone());
-        let mut node = self.node.write().await;
-        if node.get_neighbour(request.get_ref().node.clone().unwrap().id)!=None{
-            node.remove(request.get_ref().node.clone().unwrap().id);
-        }
-        let hash = sign_remove_response(node.clone().info.unwrap().id,true,node.skey.clone());
-        let sign = Signature{hash, pkey:node.clone().info.clone().unwrap().pkey.clone()};
-        Ok(Response::new(RemoveResponse{ source_id:node.clone().info.unwrap().id,success:true, sign:Option::from(sign)}))
+        self.observe("RemoveNode", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let target = request.get_ref().node.clone().ok_or(Status::invalid_argument("MISSING NODE"))?.id;
+            let mut node = self.node.write().await;
+            if node.get_neighbour(target.clone()).is_some(){
+                node.remove(target);
+            }
+            let response = RemoveResponse{ source_id:node.clone().info.unwrap().id,success:true, sign:None};
+            Ok(Response::new(seal(response, request.get_ref().clone().source_id, &node)))
+        }).await
     }
 
     async fn transaction(&self, request: Request<TransactionRequest>) -> Result<Response<TransactionResponse>, Status> {
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_transaction_request(request.get_ref().clone());
-        let mut status = "".to_string();
-        let node = self.node.read().await.clone();
-        let blocks = self.blocks.read().await.clone();
-        let mut miner = self.miner_info.write().await.clone();
-        let transaction = format!("{}->{}->{}", request.get_ref().sender, request.get_ref().value, request.get_ref().destination);
-        if node.info.clone().unwrap().miner && !miner.current_transactions.read().await.clone().contains(&transaction){
-            println!("Node {}: {} RECEIVED TRANSACTION FROM {}, VALUE: {}", node.info.clone().unwrap().id, request.get_ref().destination, request.get_ref().sender, request.get_ref().value);
-            status = miner.write_transaction(transaction.clone()).await;
-            if status=="queued"{
-                let new = mine_request(node.clone().info.unwrap().id, blocks.get(blocks.len()-1).unwrap().clone(),
-                             miner.current_transactions.read().await.clone(), format_url(miner.miner_ip, miner.miner_port)).await;
-                let boots = self.bootstraps.read().await.clone();
-                let boot = boots.get(0).unwrap();
-                update_blockchain_request(node.clone(), new.clone().unwrap(), format_url(boot.clone().ip, boot.clone().port.to_string())).await;
-            };
-        };
-        if node.info.clone().unwrap().bootstrap{
-            let n = neighbours_request(node.clone(), format_url(node.info.clone().unwrap().ip, node.info.clone().unwrap().port.to_string())).await;
-            for i in n{
-                if self.bootstraps.read().await.clone().contains(&i.clone()){continue;};
-                let url = format_url(i.ip, i.port.to_string());
-                transaction_request(node.clone(), request.get_ref().clone().sender, request.get_ref().clone().value as i32, request.get_ref().clone().destination, url).await;
-            }
-        }
-        if request.get_ref().clone().destination==node.info.clone().unwrap().id{let mut t = self.transaction_list.write().await.clone(); t.push(transaction);};
-        let info=node.clone().info.unwrap();
-        let hash=sign_transaction_response(info.clone().id,status.clone(), node.skey.clone());
-        let sign = Option::from(Signature{hash, pkey:info.clone().pkey});
-        Ok(Response::new(TransactionResponse{source_id:info.clone().id,state:status, sign}))
+        self.observe("Transaction", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            let sign = self.authenticate(&request).await?;
+            // sent by the sender itself rather than relayed
+            if request.get_ref().sender == request.get_ref().source_id{ self.owns_account(&request.get_ref().sender, &sign)? }
+            let status = self.process_transaction(request.get_ref().clone()).await?;
+            let info=self.node.read().await.clone().info.unwrap();
+            Ok(self.reply(TransactionResponse{source_id:info.clone().id,state:status, sign:None}, request.get_ref().clone().source_id).await)
+        }).await
     }
 
     async fn obtain_transactions(&self, request: Request<ObtainTransactionsRequest>) -> Result<Response<ObtainTransactionsResponse>, Status> {
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_obtain_transactions_request(request.get_ref().clone());
-        let node = self.node.read().await.clone();
-        let info=node.info.unwrap();
-        let hash=sign_obtain_transactions_response(info.clone().id, self.transaction_list.clone().read().await.clone(), node.skey.clone());
-        let sign = Option::from(Signature{hash, pkey:info.clone().pkey});
-        Ok(Response::new(ObtainTransactionsResponse{source_id:info.clone().id,transactions:self.transaction_list.clone().read().await.clone(), sign}))
+        self.observe("ObtainTransactions", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let info=self.node.read().await.clone().info.unwrap();
+            let transactions = self.transaction_list.read().await.clone();
+            Ok(self.reply(ObtainTransactionsResponse{source_id:info.clone().id,transactions, sign:None}, request.get_ref().clone().source_id).await)
+        }).await
     }
 
     async fn retrieve_blockchain(&self, request: Request<RetrieveBlockchainRequest>) -> Result<Response<RetrieveBlockchainResponse>, Status> {
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_retrieve_blockchain_request(request.get_ref().clone());
-        let blockchain = self.blocks.read().await.clone();
-        let info=self.node.read().await.clone().info.unwrap();
-        let hash=sign_retrieve_blockchain_request(info.id.clone(), self.node.read().await.clone().skey);
-        let sign = Option::from(Signature{hash, pkey:info.pkey.clone()});
-        Ok(Response::new(RetrieveBlockchainResponse{source_id:self.node.read().await.clone().info.unwrap().id,blockchain, sign}))
+        self.observe("RetrieveBlockchain", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let blockchain = self.blocks.read().await.blocks();
+            #[cfg(any(test, feature = "adversary"))]
+            let blockchain = if self.persona.read().await.withholds_blocks(){ Vec::new() } else { blockchain };
+            let info=self.node.read().await.clone().info.unwrap();
+            Ok(self.reply(RetrieveBlockchainResponse{source_id:info.id,blockchain, sign:None}, request.get_ref().clone().source_id).await)
+        }).await
     }
 
     async fn update_blockchain(&self, request: Request<UpdateBlockchainRequest>) -> Result<Response<UpdateBlockchainResponse>, Status> {
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        verify_update_blockchain_request(request.get_ref().clone());
-        let mut temp = self.blocks.read().await.clone();
-        temp.push(request.get_ref().clone().new.unwrap());
-        verify_blockchain(temp);
-        todo!()
+        self.observe("UpdateBlockchain", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let block = request.get_ref().clone().new.ok_or(Status::invalid_argument("MISSING BLOCK"))?;
+            match self.append_block(block, request.get_ref().clone().transactions).await{
+                BlockOutcome::Invalid => {
+                    if let Some(key) = transport_key(&request){ self.reputation.record(&key_fingerprint(&key), Event::InvalidBlock); }
+                    return Err(Status::invalid_argument("INVALID BLOCK"))
+                }
+                BlockOutcome::Fork => return Err(Status::failed_precondition("BLOCK DOES NOT EXTEND THE CHAIN")),
+                _ => {}
+            }
+            let info = self.node.read().await.info.clone().unwrap();
+            Ok(self.reply(UpdateBlockchainResponse{source_id:info.id, sign:None}, request.get_ref().clone().source_id).await)
+        }).await
     }
 
     async fn create_bid(&self, request: Request<CreateBidRequest>) -> Result<Response<CreateBidResponse>, Status> {
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        todo!()
+        self.observe("CreateBid", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            let sign = self.authenticate(&request).await?;
+            self.owns_account(&request.get_ref().source_id, &sign)?;
+            let request = request.get_ref();
+            let name = match request.name.as_str(){
+                "" => { let mut name = [0u8; 8]; with_rng(|i| i.fill_bytes(&mut name)); hex::encode(name) }
+                name => name.to_string(),
+            };
+            let closed = self.blocks.read().await.closed_auctions();
+            let bids = self.miner_info.read().await.active_bids.clone();
+            let mut bids = bids.write().await;
+            if bids.iter().chain(closed.iter()).any(|i| i.name == name.as_bytes()){ return Err(Status::already_exists(format!("AUCTION {} ALREADY EXISTS", name))) }
+            bids.push(Bid{ name: name.clone().into_bytes(), highest_bidder: String::new(), value: 0, participants: Vec::new(), owner: request.source_id.clone(), owner_key: key_fingerprint(&sign.pkey) });
+            drop(bids);
+            info!(auction = %name, owner = %request.source_id, "AUCTION OPENED");
+            let info = self.node.read().await.info.clone().unwrap();
+            Ok(self.reply(CreateBidResponse{ source_id: info.id, bid_name: name, sign: None }, request.source_id.clone()).await)
+        }).await
     }
 
     async fn bid_value(&self, request: Request<BidValueRequest>) -> Result<Response<BidValueResponse>, Status> {
-        self.is_correct_key(request.get_ref().clone().source_id, request.get_ref().clone().sign.unwrap()).await;
-        todo!()
+        self.observe("BidValue", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            let sign = self.authenticate(&request).await?;
+            self.owns_account(&request.get_ref().source_id, &sign)?;
+            let request = request.get_ref();
+            if self.balance(&request.source_id).await < request.value as i64{ return Err(Status::failed_precondition("INSUFFICIENT BALANCE")) }
+            let bids = self.miner_info.read().await.active_bids.clone();
+            let mut bids = bids.write().await;
+            let bid = bids.iter_mut().find(|i| i.name == request.name.as_bytes()).ok_or_else(|| Status::not_found(format!("NO OPEN AUCTION {}", request.name)))?;
+            let success = request.value > bid.value;
+            if success{
+                bid.value = request.value;
+                bid.highest_bidder = request.source_id.clone();
+                if !bid.participants.contains(&request.source_id){ bid.participants.push(request.source_id.clone()) }
+            }
+            drop(bids);
+            let info = self.node.read().await.info.clone().unwrap();
+            Ok(self.reply(BidValueResponse{ source_id: info.id, success, sign: None }, request.source_id.clone()).await)
+        }).await
+    }
+
+    async fn auctions(&self, request: Request<AuctionsRequest>) -> Result<Response<AuctionsResponse>, Status> {
+        self.observe("Auctions", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let info = self.node.read().await.info.clone().unwrap();
+            let response = AuctionsResponse{ source_id: info.id, open: self.auctions().await, closed: self.blocks.read().await.closed_auctions(), sign: None };
+            Ok(self.reply(response, request.get_ref().clone().source_id).await)
+        }).await
+    }
+
+    async fn close_bid(&self, request: Request<CloseBidRequest>) -> Result<Response<CloseBidResponse>, Status> {
+        self.observe("CloseBid", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            let sign = self.authenticate(&request).await?;
+            let request = request.get_ref();
+            let bids = self.miner_info.read().await.active_bids.clone();
+            let mut bids = bids.write().await;
+            let index = bids.iter().position(|i| i.name == request.name.as_bytes()).ok_or_else(|| Status::not_found(format!("NO OPEN AUCTION {}", request.name)))?;
+            if bids[index].owner_key != key_fingerprint(&sign.pkey){ return Err(Status::permission_denied("ONLY THE OWNER MAY CLOSE AN AUCTION")) }
+            // stays open when it cannot be stored closed
+            if let Err(e) = self.blocks.write().await.close_auction(bids[index].clone()){
+                error!(auction = %request.name, error = %e, "FAILURE STORING CLOSED AUCTION");
+                return Err(Status::internal("FAILURE STORING CLOSED AUCTION"))
+            }
+            let bid = bids.remove(index);
+            drop(bids);
+            let info = self.node.read().await.info.clone().unwrap();
+            // the node never spends for the winner, who pays the owner with a transfer of their own
+            info!(auction = %request.name, winner = %bid.highest_bidder, value = bid.value, "AUCTION CLOSED");
+            Ok(self.reply(CloseBidResponse{ source_id: info.id, bid: Some(bid), sign: None }, request.source_id.clone()).await)
+        }).await
+    }
+
+    async fn balance(&self, request: Request<BalanceRequest>) -> Result<Response<BalanceResponse>, Status> {
+        self.observe("Balance", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let request = request.get_ref();
+            let account = match request.account.as_str(){ "" => &request.source_id, account => account };
+            let state = self.chain_state().await;
+            let info = self.node.read().await.info.clone().unwrap();
+            Ok(self.reply(BalanceResponse{ source_id: info.id, balance: state.balance(account), height: state.height, sign: None }, request.source_id.clone()).await)
+        }).await
+    }
+
+    async fn transaction_status(&self, request: Request<TransactionStatusRequest>) -> Result<Response<TransactionStatusResponse>, Status> {
+        self.observe("TransactionStatus", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let request = request.get_ref();
+            let transaction = transaction_line(&request.sender, request.value, &request.destination, request.nonce);
+            let state = if self.mempool().await.iter().any(|i| transfer_of(i) == transaction){ "pending" }
+                else if self.is_confirmed(&transaction).await{ "confirmed" }
+                else if self.announcements.seen(&transaction).await{ if self.light.is_some(){ "unconfirmed" } else { "relayed" } }
+                else { "unknown" };
+            let info = self.node.read().await.info.clone().unwrap();
+            Ok(self.reply(TransactionStatusResponse{ source_id: info.id, state: state.to_string(), sign: None }, request.source_id.clone()).await)
+        }).await
+    }
+
+    async fn proof(&self, request: Request<ProofRequest>) -> Result<Response<ProofResponse>, Status> {
+        self.observe("Proof", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            let request = request.get_ref();
+            // light clients keep no bodies, so they never find one
+            let found = self.blocks.read().await.find_transaction(&request.transaction);
+            let (block, steps) = match found{
+                Some((block, body)) => {
+                    let index = body.iter().position(|i| transfer_of(i) == transfer_of(&request.transaction)).unwrap();
+                    (Some(block), merkle_proof(&body, index))
+                }
+                None => (None, Vec::new()),
+            };
+            let info = self.node.read().await.info.clone().unwrap();
+            Ok(self.reply(ProofResponse{ source_id: info.id, block, steps, sign: None }, request.source_id.clone()).await)
+        }).await
+    }
+
+    type SubscribeBlocksStream = AnnouncementStream<UpdateBlockchainRequest>;
+
+    async fn subscribe_blocks(&self, request: Request<SubscribeBlocksRequest>) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        self.observe("SubscribeBlocks", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            self.check_fanout()?;
+            let own = self.node.read().await.clone();
+            Ok(Response::new(self.announcements.block_stream(own, request.get_ref().clone().source_id)))
+        }).await
+    }
+
+    type SubscribeTransactionsStream = AnnouncementStream<TransactionRequest>;
+
+    async fn subscribe_transactions(&self, request: Request<SubscribeTransactionsRequest>) -> Result<Response<Self::SubscribeTransactionsStream>, Status> {
+        self.observe("SubscribeTransactions", request.get_ref().source_id.clone(), async move {
+            self.is_correct_key(&request, request.get_ref().clone().source_id).await?;
+            self.authenticate(&request).await?;
+            self.check_fanout()?;
+            let own = self.node.read().await.clone();
+            Ok(Response::new(self.announcements.transaction_stream(own, request.get_ref().clone().source_id)))
+        }).await
     }
 }
\ No newline at end of file
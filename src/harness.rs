@@ -0,0 +1,130 @@
+use std::future::Future;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use crate::blockchain::request_line;
+use crate::config::Config;
+use crate::endpoint::EndpointService;
+use crate::keystore::Identity;
+use crate::nodes_init::{lookup, start_node};
+use crate::proto::NodeInfo;
+use crate::requests::{find_node, signed_transfer, transaction_request};
+use crate::storage::MemoryStore;
+use crate::simulation::SimNetwork;
+use crate::transport::{assign, Transport};
+use crate::util::*;
+
+//In-process networks for integration tests: every node listens on loopback with an ephemeral port and keeps its chain in memory.
+//Nodes are EndpointServices started like the binaries start them, so tests drive them through the same RPCs.
+
+const POLL_MS: u64 = 100; //How often wait_for checks its condition
+
+//Loopback only, one transaction per block and a proof of work that takes no time
+pub fn test_config() -> Config{
+    let mut config = Config::default();
+    config.network.listen = vec!["127.0.0.1".to_string()];
+    config.chain.difficulty = 1;
+    config.chain.block_size = 1;
+    config
+}
+
+pub struct Network{
+    pub config: Arc<Config>,
+    pub bootstraps: Vec<EndpointService>,
+    pub miners: Vec<EndpointService>,
+    pub clients: Vec<EndpointService>,
+    transport: Option<Arc<dyn Transport>>, //Assigned to every node, TCP when None
+}
+impl Network{
+    pub async fn start(config:Config, bootstraps:usize, miners:usize, clients:usize) -> Network{
+        Self::launch(config, bootstraps, miners, clients, None).await
+    }
+    //Same network over a simulated one, see simulation.rs
+    pub async fn simulate(config:Config, bootstraps:usize, miners:usize, clients:usize, network:&SimNetwork) -> Network{
+        Self::launch(config, bootstraps, miners, clients, Some(Arc::new(network.clone()))).await
+    }
+    //Starts the bootstraps, pinned to the keys generated for them, then the miners and the clients, each one joined before the next starts.
+    //Every client starts with CLIENT_FUNDS to spend.
+    async fn launch(mut config:Config, bootstraps:usize, miners:usize, clients:usize, transport:Option<Arc<dyn Transport>>) -> Network{
+        let identities:Vec<Identity> = (0..bootstraps).map(|_| Identity::generate(KEY_ALGORITHM, config.dht.id_size)).collect();
+        let ports:Vec<u32> = (0..bootstraps).map(|_| free_port()).collect();
+        config.network.bootstraps = identities.iter().zip(&ports).map(|(i, port)| format!("127.0.0.1:{}@{}", port, key_fingerprint(&i.pkey))).collect();
+        let clients:Vec<Identity> = (0..clients).map(|_| Identity::generate(KEY_ALGORITHM, config.dht.id_size)).collect();
+        for i in &clients{ config.chain.allocations.insert(i.id.clone(), CLIENT_FUNDS); }
+        let config = Arc::new(config);
+        let mut network = Network{ config: config.clone(), bootstraps: Vec::new(), miners: Vec::new(), clients: Vec::new(), transport };
+        for (identity, port) in identities.into_iter().zip(ports){
+            network.bootstraps.push(network.spawn(Some(port), false, identity).await);
+        }
+        for _ in 0..miners{ network.miners.push(network.spawn(None, true, Identity::generate(KEY_ALGORITHM, config.dht.id_size)).await) }
+        for identity in clients{ network.clients.push(network.spawn(None, false, identity).await) }
+        network
+    }
+    async fn spawn(&self, port:Option<u32>, miner:bool, identity:Identity) -> EndpointService{
+        if let Some(transport) = self.transport.clone(){ assign(&identity.id, transport) }
+        start_node(self.config.clone(), port, miner, identity, Box::new(MemoryStore::default()), None).await
+    }
+    pub fn nodes(&self) -> Vec<EndpointService>{
+        self.bootstraps.iter().chain(&self.miners).chain(&self.clients).cloned().collect()
+    }
+    pub async fn heights(&self) -> Vec<usize>{
+        let mut heights = Vec::new();
+        for i in self.nodes(){ heights.push(i.height().await) }
+        heights
+    }
+    pub async fn wait_for_height(&self, height:usize, timeout:Duration) -> bool{
+        wait_for(timeout, || async { self.heights().await.iter().all(|i| *i >= height) }).await
+    }
+    //Until every node has at least count contacts in its routing table
+    pub async fn wait_for_neighbours(&self, count:usize, timeout:Duration) -> bool{
+        wait_for(timeout, || async {
+            for i in self.nodes(){
+                if i.node().await.get_neighbours().len() < count{ return false }
+            }
+            true
+        }).await
+    }
+    //Sends a transaction to every neighbour of the sender, as the interactive client does, returning its line
+    pub async fn transfer(&self, from:&EndpointService, value:i32, destination:String) -> String{
+        let node = from.node().await;
+        let sender = node.info.clone().unwrap().id;
+        let nonce = new_nonce();
+        let request = signed_transfer(&node, sender.clone(), value as u32, destination.clone(), nonce);
+        from.sent(request.clone()).await;
+        for i in node.get_neighbours(){
+            transaction_request(node.clone(), sender.clone(), value, destination.clone(), nonce, i).await;
+        }
+        request_line(&request)
+    }
+    //Transfers 5 from the first client to the second and waits for it to be mined, returning the destination once every node has the block
+    pub async fn mined_transfer(&self, timeout:Duration) -> Option<String>{
+        let destination = self.clients[1].node().await.info.unwrap().id;
+        self.transfer(&self.clients[0], 5, destination.clone()).await;
+        self.wait_for_height(1, timeout).await.then_some(destination)
+    }
+    //Finds a node from another one: in its routing table, else with FindNode through its closest contacts, else with an iterative lookup
+    pub async fn find(&self, from:&EndpointService, id:String) -> Option<NodeInfo>{
+        let node = from.node().await;
+        if let Some(found) = node.get_neighbour(id.clone()){ return Some(found) }
+        for i in node.closest_to(&id, self.config.dht.alpha){
+            if let Some(found) = find_node(node.clone(), id.clone(), i).await{ return Some(found) }
+        }
+        lookup(from, id.clone(), &self.config.dht).await.into_iter().find(|i| i.id == id)
+    }
+}
+
+//Polls condition until it holds or the timeout expires, returning whether it held
+pub async fn wait_for<F, Fut>(timeout:Duration, mut condition:F) -> bool
+where F: FnMut() -> Fut, Fut: Future<Output = bool>{
+    let deadline = Instant::now() + timeout;
+    loop{
+        if condition().await{ return true }
+        if Instant::now() >= deadline{ return false }
+        tokio::time::sleep(Duration::from_millis(POLL_MS)).await;
+    }
+}
+//A port nothing listens on right now; bootstraps need theirs before they start so that peers can be configured with it
+fn free_port() -> u32{
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port() as u32
+}
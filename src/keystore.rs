@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use prost::Message;
+use tracing::info;
+use crate::proto::{KeyAlgorithm, Keystore};
+use crate::util::*;
+
+//Long-lived identity of a node: its DHT ID and the keypair that signs for it
+#[derive(Clone,Debug)]
+pub struct Identity{
+    pub id: String,
+    pub algorithm: KeyAlgorithm,
+    pub skey: Vec<u8>,
+    pub pkey: Vec<u8>,
+}
+
+fn derive_key(passphrase:&str, salt:&[u8], iterations:u32) -> Vec<u8>{
+    let mut key = vec![0u8; 32];
+    pbkdf2_hmac(passphrase.as_bytes(), salt, iterations as usize, MessageDigest::sha256(), &mut key).unwrap();
+    key
+}
+//The public half is bound to the ciphertext so it cannot be swapped in the file without failing decryption
+fn associated_data(keystore:&Keystore) -> Vec<u8>{
+    format!("{}|{}|{}", keystore.id, keystore.algorithm, hex::encode(&keystore.pkey)).into_bytes()
+}
+
+impl Identity{
+    //The ID is taken from the key, see owns_id
+    pub fn generate(algorithm:KeyAlgorithm, id_size:usize) -> Identity{
+        let (skey, pkey) = new_key(algorithm);
+        Identity{ id: key_fingerprint(&pkey)[..id_size].to_string(), algorithm, skey, pkey }
+    }
+    pub fn seal(&self, passphrase:&str) -> Keystore{
+        let mut salt = vec![0u8; 16];
+        let mut iv = vec![0u8; 12];
+        rand_bytes(&mut salt).unwrap();
+        rand_bytes(&mut iv).unwrap();
+        let mut keystore = Keystore{ id: self.id.clone(), algorithm: self.algorithm as i32, pkey: self.pkey.clone(),
+            salt, iterations: KEYSTORE_ITERATIONS, iv, skey: Vec::new(), tag: vec![0u8; 16] };
+        let key = derive_key(passphrase, &keystore.salt, keystore.iterations);
+        keystore.skey = encrypt_aead(Cipher::aes_256_gcm(), &key, Some(&keystore.iv), &associated_data(&keystore), &self.skey, &mut keystore.tag).unwrap();
+        keystore
+    }
+    pub fn unseal(keystore:Keystore, passphrase:&str) -> Result<Identity, Box<dyn Error>>{
+        // a tampered file could otherwise make the passphrase cheap to guess
+        if keystore.iterations < KEYSTORE_ITERATIONS{ return Err(format!("KEYSTORE USES FEWER THAN {} ITERATIONS", KEYSTORE_ITERATIONS).into()) }
+        let key = derive_key(passphrase, &keystore.salt, keystore.iterations);
+        let skey = decrypt_aead(Cipher::aes_256_gcm(), &key, Some(&keystore.iv), &associated_data(&keystore), &keystore.skey, &keystore.tag)
+            .map_err(|_| "WRONG PASSPHRASE OR CORRUPTED KEYSTORE")?;
+        Ok(Identity{ id: keystore.id.clone(), algorithm: KeyAlgorithm::try_from(keystore.algorithm)?, skey, pkey: keystore.pkey })
+    }
+    pub fn save(&self, path:&str, passphrase:&str) -> Result<(), Box<dyn Error>>{
+        if let Some(parent) = Path::new(path).parent(){ fs::create_dir_all(parent)?; }
+        // created private, so the key is never readable by others, and narrowed when the file already existed
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(&self.seal(passphrase).encode_to_vec())?;
+        Ok(())
+    }
+    pub fn load(path:&str, passphrase:&str) -> Result<Identity, Box<dyn Error>>{
+        let keystore = Keystore::decode(fs::read(path)?.as_slice())?;
+        Self::unseal(keystore, passphrase)
+    }
+    //Reuses the identity stored at path, or creates and stores one on first run
+    pub fn load_or_create(path:&str, passphrase:&str, id_size:usize) -> Result<Identity, Box<dyn Error>>{
+        if Path::new(path).exists(){ return Self::load(path, passphrase) }
+        let identity = Self::generate(KEY_ALGORITHM, id_size);
+        identity.save(path, passphrase)?;
+        info!(id = %identity.id, path, "CREATED NEW IDENTITY");
+        Ok(identity)
+    }
+}
+
+//Re-encrypts an identity under a separate passphrase so it can be moved to another machine
+pub fn export_identity(path:&str, passphrase:&str, destination:&str, export_passphrase:&str) -> Result<(), Box<dyn Error>>{
+    Identity::load(path, passphrase)?.save(destination, export_passphrase)
+}
+pub fn import_identity(source:&str, export_passphrase:&str, path:&str, passphrase:&str) -> Result<Identity, Box<dyn Error>>{
+    if Path::new(path).exists(){ return Err(format!("KEYSTORE {} ALREADY EXISTS", path).into()) }
+    let identity = Identity::load(source, export_passphrase)?;
+    identity.save(path, passphrase)?;
+    Ok(identity)
+}
@@ -0,0 +1,34 @@
+use std::env;
+use util::*;
+use keystore::*;
+use ssd::{util, keystore};
+
+fn usage(){
+    println!("Usage:");
+    println!("\tkeystore show <keystore>");
+    println!("\tkeystore export <keystore> <destination>");
+    println!("\tkeystore import <source> <keystore>");
+}
+
+fn main(){
+    let args: Vec<String> = env::args().collect();
+    match args.iter().map(|i| i.as_str()).collect::<Vec<&str>>().as_slice(){
+        [_, "show", path] => {
+            let identity = Identity::load(path, &read_passphrase("Keystore passphrase? ")).expect("FAILURE LOADING IDENTITY");
+            println!("ID: {}\nALGORITHM: {}\nFINGERPRINT: {}\n{}", identity.id, identity.algorithm.as_str_name(), key_fingerprint(&identity.pkey), String::from_utf8(identity.pkey).unwrap());
+        }
+        [_, "export", path, destination] => {
+            let passphrase = read_passphrase("Keystore passphrase? ");
+            let export_passphrase = prompt_line("Export passphrase? ");
+            export_identity(path, &passphrase, destination, &export_passphrase).expect("FAILURE EXPORTING IDENTITY");
+            println!("EXPORTED {} TO {}", path, destination);
+        }
+        [_, "import", source, path] => {
+            let export_passphrase = prompt_line("Export passphrase? ");
+            let passphrase = read_passphrase("New keystore passphrase? ");
+            let identity = import_identity(source, &export_passphrase, path, &passphrase).expect("FAILURE IMPORTING IDENTITY");
+            println!("IMPORTED IDENTITY {} INTO {}", identity.id, path);
+        }
+        _ => usage(),
+    }
+}
@@ -0,0 +1,37 @@
+//The node modules as a library: the binaries are built on it, and the integration tests under tests/ run whole networks in one process.
+// every RPC fails with a tonic::Status, boxing it would only add noise at each call site
+#![allow(clippy::result_large_err)]
+
+pub mod endpoint;
+pub mod util;
+pub mod node;
+pub mod proto{
+    tonic::include_proto!("kademlia");
+}
+pub mod blockchain;
+pub mod requests;
+pub mod signatures;
+pub mod nodes_init;
+pub mod propagation;
+pub mod keystore;
+pub mod storage;
+pub mod bootstrap;
+pub mod config;
+pub mod tls;
+pub mod transport;
+// the misbehaving nodes of the attack scenarios, kept out of the binaries
+#[cfg(any(test, feature = "adversary"))]
+pub mod adversary;
+pub mod reputation;
+pub mod limits;
+pub mod metrics;
+pub mod admin;
+pub mod light;
+pub mod logging;
+// the in-process test network and its simulated transport, kept out of the binaries like the adversary
+#[cfg(any(test, feature = "adversary"))]
+pub mod harness;
+#[cfg(any(test, feature = "adversary"))]
+pub mod simulation;
+#[cfg(any(test, feature = "adversary"))]
+pub mod scenarios;
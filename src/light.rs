@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use tracing::{debug, info, warn};
+use crate::blockchain::{hash_block, request_line, transfer_of, verify_headers, verify_proof};
+use crate::endpoint::EndpointService;
+use crate::proto::TransactionRequest;
+use crate::reputation::Event;
+use crate::requests::{proof_request, try_retrieve_blockchain_request};
+use crate::util::*;
+
+//Light clients keep block headers only: each one must carry the proof of work and extend the one before it.
+//Transfers between other accounts are not counted, the client's own are once a full node proves them to be in a block it holds.
+
+#[derive(Debug,Default)]
+pub struct LightClient{
+    unconfirmed: Vec<TransactionRequest>, //Own transactions not proven to be in a block yet
+    confirmed: HashSet<String>,
+}
+impl LightClient{
+    pub fn track(&mut self, request:TransactionRequest){
+        self.unconfirmed.push(request)
+    }
+    pub fn unconfirmed(&self) -> Vec<TransactionRequest>{
+        self.unconfirmed.clone()
+    }
+    //False when the transaction was confirmed before, so it is counted once
+    pub fn confirm(&mut self, transaction:&str) -> bool{
+        self.unconfirmed.retain(|i| request_line(i) != transaction);
+        self.confirmed.insert(transfer_of(transaction).to_string())
+    }
+    pub fn is_confirmed(&self, transaction:&str) -> bool{
+        self.confirmed.contains(transfer_of(transaction))
+    }
+}
+
+//Fetches the chain of the first bootstrap whose headers all check out and extend this client's, returning how many were added
+pub async fn sync_headers(service:&EndpointService) -> usize{
+    let node = service.node().await;
+    let own = service.headers().await;
+    for peer in service.bootstraps().await{
+        let Ok(headers) = try_retrieve_blockchain_request(&node, &peer).await else { continue };
+        if headers.len() <= own.len() || headers[..own.len()] != own[..]{ continue }
+        if !verify_headers(&headers, service.config().chain.difficulty){
+            warn!(peer = %peer.id, "REJECTED HEADERS");
+            service.reputation().record(&key_fingerprint(&peer.pkey), Event::InvalidBlock);
+            continue
+        }
+        let added = service.extend_headers(headers[own.len()..].to_vec()).await;
+        info!(peer = %peer.id, added, "SYNCED HEADERS");
+        return added
+    }
+    0
+}
+//Asks the bootstraps for proofs of the client's unconfirmed transactions and counts each one proven against a header it holds
+pub async fn confirm_transactions(service:&EndpointService){
+    let Some(light) = service.light() else { return };
+    let unconfirmed = light.read().await.unconfirmed();
+    if unconfirmed.is_empty(){ return }
+    let node = service.node().await;
+    let peers = service.bootstraps().await;
+    for request in unconfirmed{
+        let transaction = request_line(&request);
+        for peer in &peers{
+            let Some(proof) = proof_request(&node, transaction.clone(), peer).await else { continue };
+            let Some(header) = proof.block else { continue };
+            // a block this client has no header of yet proves nothing, it is asked again on the next header
+            if service.header(&hash_block(header.clone())).await.is_none(){
+                debug!(peer = %peer.id, transaction, "PROOF FOR AN UNKNOWN BLOCK");
+                continue
+            }
+            if !verify_proof(&transaction, &proof.steps, &header.merkle_root){
+                warn!(peer = %peer.id, transaction, "REJECTED PROOF");
+                service.reputation().record(&key_fingerprint(&peer.pkey), Event::InvalidBlock);
+                continue
+            }
+            if light.write().await.confirm(&transaction){
+                service.apply_transaction(&request).await;
+                info!(transaction, "TRANSACTION CONFIRMED");
+            }
+            break
+        }
+    }
+}
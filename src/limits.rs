@@ -0,0 +1,102 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+use tonic::Status;
+use crate::config::LimitsConfig;
+use crate::util::*;
+
+//Admission control for the Endpoint RPCs: a token bucket per RPC for everyone together and one per RPC and peer,
+//plus a cap on the lookups a node forwards at once. Every refusal is a RESOURCE_EXHAUSTED status.
+
+#[derive(Clone,Copy,Debug)]
+struct TokenBucket{
+    tokens: f64,
+    updated: Instant,
+}
+impl TokenBucket{
+    fn full(burst:u32) -> TokenBucket{
+        TokenBucket{ tokens: burst as f64, updated: Instant::now() }
+    }
+    //Refilled at rate tokens per second, up to burst
+    fn refilled(&self, rate:u32, burst:u32) -> f64{
+        (self.tokens + self.updated.elapsed().as_secs_f64() * rate as f64).min(burst as f64)
+    }
+    fn take(&mut self, rate:u32, burst:u32) -> bool{
+        self.tokens = self.refilled(rate, burst);
+        self.updated = Instant::now();
+        if self.tokens < 1.0{ return false }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+//Buckets by peer key fingerprint and RPC, the least recently used one forgotten once MAX_TRACKED_PEERS are kept
+#[derive(Debug,Default)]
+struct PeerBuckets{
+    buckets: HashMap<(String, &'static str), (TokenBucket, u64)>, //With the stamp of their last use
+    used: BTreeMap<u64, (String, &'static str)>,
+    stamp: u64,
+}
+impl PeerBuckets{
+    fn take(&mut self, key:(String, &'static str), rate:u32, burst:u32) -> bool{
+        self.stamp += 1;
+        match self.buckets.get(&key){
+            Some((_, used)) => { self.used.remove(used); }
+            None if self.buckets.len() >= MAX_TRACKED_PEERS => {
+                if let Some((_, oldest)) = self.used.pop_first(){ self.buckets.remove(&oldest); }
+            }
+            None => {}
+        }
+        self.used.insert(self.stamp, key.clone());
+        let (bucket, used) = self.buckets.entry(key).or_insert_with(|| (TokenBucket::full(burst), 0));
+        *used = self.stamp;
+        bucket.take(rate, burst)
+    }
+    fn len(&self) -> usize{
+        self.buckets.len()
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter{
+    config: LimitsConfig,
+    global: Mutex<HashMap<&'static str, TokenBucket>>,
+    peers: Mutex<PeerBuckets>,
+    lookups: Semaphore,
+}
+impl Default for RateLimiter{
+    fn default() -> Self {
+        RateLimiter::new(LimitsConfig::default())
+    }
+}
+impl RateLimiter{
+    pub fn new(config:LimitsConfig) -> RateLimiter{
+        let lookups = Semaphore::new(config.max_lookups);
+        RateLimiter{ config, global: Mutex::new(HashMap::new()), peers: Mutex::new(PeerBuckets::default()), lookups }
+    }
+    //Takes a token for one call of method by peer, the peer's bucket first so a flooding peer does not drain the global one
+    pub fn check(&self, method:&'static str, peer:&str) -> Result<(), Status>{
+        let config = &self.config;
+        if !self.peers.lock().unwrap().take((peer.to_string(), method), config.peer_rate, config.peer_burst){
+            return Err(Status::resource_exhausted(format!("RATE LIMIT EXCEEDED FOR {}", method)))
+        }
+        let mut global = self.global.lock().unwrap();
+        let bucket = global.entry(method).or_insert_with(|| TokenBucket::full(config.global_burst));
+        if !bucket.take(config.global_rate, config.global_burst){
+            return Err(Status::resource_exhausted(format!("NODE IS OVERLOADED, TRY {} LATER", method)))
+        }
+        Ok(())
+    }
+    //Held while a lookup is forwarded to other nodes
+    pub fn lookup(&self) -> Result<SemaphorePermit<'_>, Status>{
+        self.lookups.try_acquire().map_err(|_| Status::resource_exhausted("TOO MANY LOOKUPS IN FLIGHT"))
+    }
+    pub fn max_fanout(&self) -> usize{
+        self.config.max_fanout
+    }
+    //Peer buckets currently kept
+    pub fn tracked(&self) -> usize{
+        self.peers.lock().unwrap().len()
+    }
+}
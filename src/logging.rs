@@ -0,0 +1,32 @@
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+use crate::config::LogConfig;
+
+//Node diagnostics go through tracing, to stderr or a file and never to stdout, which is left to the interactive menus.
+//RPCs run inside an "rpc" span carrying the node, peer and method, so every event logged while serving or making one has them.
+
+//Formats events as the configuration asks, written to writer
+pub fn subscriber<W>(config:&LogConfig, writer:W) -> Box<dyn Subscriber + Send + Sync>
+where W: for<'a> MakeWriter<'a> + Send + Sync + 'static{
+    let filter = EnvFilter::try_new(&config.level).expect("INVALID LOG LEVEL");
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer).with_ansi(false);
+    match config.format.as_str(){
+        "json" => Box::new(builder.json().with_current_span(true).with_span_list(false).finish()),
+        _ => Box::new(builder.finish()),
+    }
+}
+//Installs the subscriber for the whole process, appending to log.file when one is given
+pub fn init(config:&LogConfig){
+    let writer = match config.file.as_str(){
+        "" => BoxMakeWriter::new(std::io::stderr),
+        path => {
+            let file = OpenOptions::new().create(true).append(true).open(path).unwrap_or_else(|e| panic!("FAILURE OPENING LOG FILE {}: {}", path, e));
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+    };
+    tracing::subscriber::set_global_default(subscriber(config, writer)).expect("FAILURE INSTALLING LOGGER");
+}
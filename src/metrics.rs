@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tonic::Code;
+use tracing::{info, warn};
+use crate::config::MetricsConfig;
+use crate::endpoint::EndpointService;
+use crate::nodes_init::started;
+use crate::util::*;
+
+//What each node counts as it runs, and the Prometheus text exposition of it for every node in the process.
+//Routing table, mempool, chain and reputation figures are read from the nodes when scraped rather than counted.
+
+#[derive(Clone,Debug,Default)]
+struct Histogram{
+    buckets: [u64; LATENCY_BUCKETS.len()], //Observations per bucket, not cumulative
+    sum: f64,
+    count: u64,
+}
+impl Histogram{
+    fn observe(&mut self, value:f64){
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|i| value <= *i){ self.buckets[index] += 1 }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug,Default)]
+pub struct Metrics{
+    rpcs: Mutex<BTreeMap<(&'static str, String), u64>>, //By RPC and status code
+    latencies: Mutex<BTreeMap<&'static str, Histogram>>,
+    signature_failures: Mutex<BTreeMap<String, u64>>, //By signed method, requests and responses alike
+    forks: AtomicU64,
+    hashes: AtomicU64,
+    hash_rate: Mutex<f64>, //Hashes per second while mining the last block
+}
+impl Metrics{
+    pub fn rpc(&self, method:&'static str, code:Code, elapsed:Duration){
+        *self.rpcs.lock().unwrap().entry((method, format!("{:?}", code))).or_default() += 1;
+        self.latencies.lock().unwrap().entry(method).or_default().observe(elapsed.as_secs_f64());
+    }
+    pub fn signature_failure(&self, method:&str){
+        let method = method.rsplit('/').next().unwrap_or(method);
+        *self.signature_failures.lock().unwrap().entry(method.to_string()).or_default() += 1;
+    }
+    //A valid block that extends an earlier block than the tip
+    pub fn fork(&self){
+        self.forks.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn mined(&self, hashes:u64, elapsed:Duration){
+        self.hashes.fetch_add(hashes, Ordering::Relaxed);
+        if !elapsed.is_zero(){ *self.hash_rate.lock().unwrap() = hashes as f64 / elapsed.as_secs_f64() }
+    }
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<Metrics>>>{
+    static METRICS: OnceLock<RwLock<HashMap<String, Arc<Metrics>>>> = OnceLock::new();
+    METRICS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+//What the node with this ID has counted so far
+pub fn metrics(node:&str) -> Arc<Metrics>{
+    if let Some(metrics) = registry().read().unwrap().get(node){ return metrics.clone() }
+    registry().write().unwrap().entry(node.to_string()).or_default().clone()
+}
+
+type Family = (&'static str, &'static str, &'static str); //Name, type and help of a metric
+const RPCS: Family = ("blocc_rpc_requests_total", "counter", "Endpoint RPCs served, by method and status code");
+const LATENCIES: Family = ("blocc_rpc_duration_seconds", "histogram", "Time taken to serve an Endpoint RPC");
+const ROUTES: Family = ("blocc_routing_table_contacts", "gauge", "Contacts in each bucket of the routing table");
+const MEMPOOL: Family = ("blocc_mempool_transactions", "gauge", "Transactions waiting to be mined");
+const HEIGHT: Family = ("blocc_chain_height", "gauge", "Blocks in the local chain");
+const FORKS: Family = ("blocc_forks_total", "counter", "Valid blocks received that branch off before the tip");
+const HASHES: Family = ("blocc_mining_hashes_total", "counter", "Block hashes computed while mining");
+const HASH_RATE: Family = ("blocc_mining_hash_rate", "gauge", "Hashes per second while mining the last block");
+const REPUTATION: Family = ("blocc_peer_reputation", "gauge", "Current score of each peer");
+const BANS: Family = ("blocc_banned_peers", "gauge", "Peers currently banned");
+const SIGNATURES: Family = ("blocc_signature_failures_total", "counter", "Messages rejected by signature checks, by signed method");
+
+//Samples grouped under their family, so each HELP and TYPE line is written once
+#[derive(Default)]
+struct Exposition{
+    families: Vec<(Family, Vec<String>)>,
+}
+impl Exposition{
+    fn sample(&mut self, family:Family, suffix:&str, labels:String, value:f64){
+        let line = format!("{}{}{{{}}} {}", family.0, suffix, labels, value);
+        match self.families.iter_mut().find(|(i, _)| i.0 == family.0){
+            Some((_, lines)) => lines.push(line),
+            None => self.families.push((family, vec![line])),
+        }
+    }
+    fn text(&self) -> String{
+        let mut res = String::new();
+        for ((name, kind, help), lines) in self.families.iter(){
+            res += &format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+            for i in lines{ res += &format!("{}\n", i) }
+        }
+        res
+    }
+}
+
+pub async fn render(services:&[EndpointService]) -> String{
+    let mut out = Exposition::default();
+    for service in services{
+        let node = service.node().await;
+        let id = node.info.clone().unwrap().id;
+        let metrics = metrics(&id);
+        let label = format!("node=\"{}\"", id);
+        for ((method, code), count) in metrics.rpcs.lock().unwrap().iter(){
+            out.sample(RPCS, "", format!("{},method=\"{}\",code=\"{}\"", label, method, code), *count as f64);
+        }
+        for (method, histogram) in metrics.latencies.lock().unwrap().iter(){
+            let labels = format!("{},method=\"{}\"", label, method);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets){
+                cumulative += count;
+                out.sample(LATENCIES, "_bucket", format!("{},le=\"{}\"", labels, le), cumulative as f64);
+            }
+            out.sample(LATENCIES, "_bucket", format!("{},le=\"+Inf\"", labels), histogram.count as f64);
+            out.sample(LATENCIES, "_sum", labels.clone(), histogram.sum);
+            out.sample(LATENCIES, "_count", labels, histogram.count as f64);
+        }
+        for (bucket, count) in node.get_quantity().into_iter().enumerate(){
+            out.sample(ROUTES, "", format!("{},bucket=\"{}\"", label, bucket), count as f64);
+        }
+        out.sample(MEMPOOL, "", label.clone(), service.mempool().await.len() as f64);
+        out.sample(HEIGHT, "", label.clone(), service.height().await as f64);
+        out.sample(FORKS, "", label.clone(), metrics.forks.load(Ordering::Relaxed) as f64);
+        out.sample(HASHES, "", label.clone(), metrics.hashes.load(Ordering::Relaxed) as f64);
+        out.sample(HASH_RATE, "", label.clone(), *metrics.hash_rate.lock().unwrap());
+        let reputation = service.reputation();
+        for (peer, score) in reputation.scores(){
+            out.sample(REPUTATION, "", format!("{},peer=\"{}\"", label, peer), score);
+        }
+        out.sample(BANS, "", label.clone(), reputation.bans().bans.len() as f64);
+        for (method, count) in metrics.signature_failures.lock().unwrap().iter(){
+            out.sample(SIGNATURES, "", format!("{},method=\"{}\"", label, method), *count as f64);
+        }
+    }
+    out.text()
+}
+
+//Answers GET /metrics with the exposition of every node started in this process, and anything else with a 404
+pub async fn serve(listener:TcpListener){
+    loop{
+        let (stream, _) = match listener.accept().await{
+            Ok(connection) => connection,
+            Err(e) => { warn!(error = %e, "FAILURE ACCEPTING METRICS CONNECTION"); continue }
+        };
+        tokio::spawn(async move{
+            if let Err(e) = scrape(stream).await{ warn!(error = %e, "FAILURE SERVING METRICS") }
+        });
+    }
+}
+async fn scrape(mut stream:TcpStream) -> std::io::Result<()>{
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    // only the request line matters, the rest of the headers are read and ignored
+    while !request.windows(4).any(|i| i == b"\r\n\r\n") && request.len() < MAX_METRICS_REQUEST_BYTES{
+        let read = tokio::time::timeout(Duration::from_millis(RPC_TIMEOUT_MS), stream.read(&mut buffer)).await??;
+        if read == 0{ break }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let line = String::from_utf8_lossy(&request).lines().next().unwrap_or_default().to_string();
+    let (status, body) = match line.split_whitespace().take(2).collect::<Vec<_>>()[..]{
+        ["GET", "/metrics"] => ("200 OK", render(&started()).await),
+        _ => ("404 Not Found", String::new()),
+    };
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+//Starts the listener when the configuration gives it an address
+pub async fn expose(config:&MetricsConfig){
+    if config.listen.is_empty(){ return }
+    let listener = TcpListener::bind(&config.listen).await.expect("FAILURE BINDING METRICS LISTENER");
+    info!(url = %format!("http://{}/metrics", listener.local_addr().unwrap()), "SERVING METRICS");
+    tokio::spawn(serve(listener));
+}
@@ -1,21 +1,20 @@
-use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::os::fd::{AsFd, AsRawFd};
-use rand::RngCore;
-use sha256::digest;
-use tokio::net::TcpSocket;
+use rand::Rng;
+use tracing::{info, warn};
 
 use crate::util::*;
-use crate::proto::{NodeInfo, BucketNode, KBucket, Node};
-mod proto {
-    tonic::include_proto!("kademlia");
-}
+use crate::requests::pool;
+use crate::keystore::Identity;
+use crate::config::DhtConfig;
+use crate::proto::{NodeInfo, BucketNode, KBucket, Node, RoutingSnapshot};
 
 impl NodeInfo{
-
-    pub fn clone(&self) -> NodeInfo{
-        NodeInfo{ id: self.id.clone(), ip: self.ip.clone(), port: self.port.clone(), pkey:self.pkey.clone(), bootstrap: self.bootstrap.clone(), miner:self.miner.clone() }
+    //Where the node can be reached, most preferred first
+    pub fn urls(&self) -> Vec<String>{
+        let mut addresses = self.addresses.clone();
+        if addresses.is_empty(){ addresses.push(self.ip.clone()) }
+        addresses.into_iter().map(|i| format_url(i, self.port.to_string())).collect()
     }
 }
 impl Display for NodeInfo{
@@ -27,27 +26,33 @@ impl Display for NodeInfo{
 impl KBucket{
     pub fn new() -> KBucket{
         let nodes = Vec::new();
-        KBucket{ nodes }
+        KBucket{ nodes, replacements:Vec::new(), last_lookup:now_millis() }
     }
     pub fn insert(&mut self, new: NodeInfo, distance: i64){
-        self.nodes.push(BucketNode{position:(distance - 2_i64.pow(distance.ilog2() as u32)) as u32, info:Option::from(new.clone())})
+        self.nodes.push(BucketNode{position:(distance - 2_i64.pow(distance.ilog2())) as u32, info:Option::from(new.clone()), last_seen:now_millis()})
+    }
+    //Keeps the k most recently met contacts that did not fit, the newest last
+    pub fn remember(&mut self, new: NodeInfo, distance: i64, k:usize){
+        self.replacements.retain(|i| i.info.as_ref().is_some_and(|info| info.id != new.id));
+        self.replacements.push(BucketNode{position:(distance - 2_i64.pow(distance.ilog2())) as u32, info:Option::from(new), last_seen:now_millis()});
+        if self.replacements.len() > k{ self.replacements.remove(0); }
     }
     pub fn get_node(&self, distance:i64) -> Option<NodeInfo>{
         for i in self.nodes.clone(){
-            if i.position == (distance - 2_i64.pow(distance.ilog2() as u32)) as u32{
+            if i.position == (distance - 2_i64.pow(distance.ilog2())) as u32{
                 return i.info.clone();
             }
         }
         None
     }
     pub fn contains(&self, distance: i64) -> bool{
-        if self.get_node(distance)!=None{return true;};
+        if self.get_node(distance).is_some(){return true;};
         false
     }
     pub fn print(&self) -> String{
         let mut res = String::new();
         for i in 0..self.nodes.len(){
-            if self.nodes.get(i) != Some(&BucketNode { position: 0, info: None }){
+            if self.nodes.get(i) != Some(&BucketNode { position: 0, info: None, last_seen: 0 }){
                 res = format!("{}\t\t\t{:?}@{}\n", res, self.nodes.get(i).unwrap().info.clone().unwrap(), i.clone());
             }
         };
@@ -55,8 +60,8 @@ impl KBucket{
     }
     pub fn remove(&mut self, _node:String, distance:i64){
         for i in 0..self.nodes.clone().len(){
-            if let Some(cur) = self.nodes.get(i).clone(){
-                if cur.position== (distance - 2_i64.pow(distance.ilog2() as u32)) as u32 {
+            if let Some(cur) = self.nodes.get(i){
+                if cur.position== (distance - 2_i64.pow(distance.ilog2())) as u32 {
                     self.nodes.remove(i);
                 }
             }
@@ -64,8 +69,8 @@ impl KBucket{
     }
     pub fn get_quantity(&self) -> i64{
         let mut res=0;
-        for i in self.nodes.to_vec(){
-            if i.info!=None{
+        for i in self.nodes.iter(){
+            if i.info.is_some(){
                 res+=1;
             }
         }
@@ -74,42 +79,101 @@ impl KBucket{
 }
 
 impl Node{
-    pub fn init_routes() -> Vec<KBucket>{
+    pub fn init_routes(buckets:usize) -> Vec<KBucket>{
         let mut routes = Vec::new();
-        for _i in 0..N_BUCKETS{routes.push(KBucket::new());};
+        for _i in 0..buckets{routes.push(KBucket::new());};
         routes
     }
     pub fn distance(one:String, two:String) -> i64{
         i64::from_str_radix(one.as_str(), 16).unwrap() ^ i64::from_str_radix(two.as_str(), 16).unwrap()
     }
-    pub fn new(ip: String, port:Option<u32>, miner:bool) -> Self{
-        let mut input = [0u8; 8];
-        rand::thread_rng().fill_bytes(&mut input);
-        let input = digest(&input);
-        let destination;
-        let bootstrap;
-        if port.clone()!=None{ destination=format!("{}:{}", ip, port.unwrap());bootstrap=true }
-        else{ destination=format!("{}:0", ip);bootstrap=false };
+    //Binds the first address to settle the port, the remaining ones share it and are dropped if they cannot bind
+    pub fn new(addresses: Vec<String>, port:Option<u32>, miner:bool, identity:Identity, dht:&DhtConfig) -> Self{
+        let ip = addresses.first().expect("NO ADDRESS TO LISTEN ON").clone();
+        let (destination, bootstrap) = match port{
+            Some(port) => (format!("{}:{}", format_host(&ip), port), true),
+            None => (format!("{}:0", format_host(&ip)), false),
+        };
         let socket = bind(destination).unwrap().expect("FAILURE BINDING SOCKET");
-        let (skey, pkey) = new_key();
-        let info = NodeInfo{ id: input[..ID_SIZE].to_string(), ip, port: socket.local_addr().unwrap().port() as u32, pkey, bootstrap, miner};
-        let routes = Self::init_routes();
-        let node = Node{ info:Option::from(info.clone()), skey, kbuckets:routes, neighbours:Vec::new(), blockchain:Vec::new()};
-        node
+        let port = socket.local_addr().unwrap().port() as u32;
+        let mut bound = vec![ip.clone()];
+        for i in addresses.iter().skip(1){
+            match bind(format!("{}:{}", format_host(i), port)){
+                Ok(Some(_)) => bound.push(i.clone()),
+                _ => warn!(address = %i, port, "FAILURE BINDING, NOT ADVERTISING THE ADDRESS"),
+            }
+        }
+        let info = NodeInfo{ id: identity.id, ip, port, pkey:identity.pkey, bootstrap, miner, algorithm:identity.algorithm as i32, addresses:bound, non_routable:false};
+        let routes = Self::init_routes(dht.buckets());
+        Node{ info:Option::from(info.clone()), skey:identity.skey, kbuckets:routes, neighbours:Vec::new(), blockchain:Vec::new()}
+    }
+    //Advertises an address peers observed first; it is reached through a NAT mapping, so it is not bound locally
+    pub fn advertise(&mut self, ip:String){
+        let info = self.info.as_mut().unwrap();
+        info.addresses.retain(|i| *i != ip);
+        info.addresses.insert(0, ip.clone());
+        info.ip = ip;
+    }
+    //Signs and dials like a node but never listens nor routes, for programs that only make requests
+    pub fn client(identity:Identity) -> Self{
+        let info = NodeInfo{ id: identity.id, pkey: identity.pkey, algorithm: identity.algorithm as i32, non_routable: true, ..Default::default() };
+        Node{ info: Some(info), skey: identity.skey, ..Default::default() }
     }
-    pub fn clone(&self) -> Node{
-        Node{ info: Option::from(self.info.clone()), skey:self.skey.clone(),
-            kbuckets: self.kbuckets.clone(), neighbours:self.neighbours.clone(), blockchain:self.blockchain.clone()}
+    //Copy that is safe to put on the wire, without the private key
+    pub fn public(&self) -> Node{
+        Node{ skey:Vec::new(), ..self.clone() }
     }
 
-    pub async fn new_route(&mut self, new: NodeInfo) -> bool{
+    pub async fn new_route(&mut self, new: NodeInfo, k:usize) -> bool{
+        // nobody could dial it back, so other nodes would only waste lookups on it
+        if new.non_routable{ return false }
         let distance = Self::distance(self.info.clone().unwrap().id, new.clone().id);
+        if self.get_quantity().get(distance.ilog2() as usize).unwrap()>=&(k as i64){
+            self.evict_unhealthy(distance.ilog2() as usize);
+        }
         let quantity = self.get_quantity();
-        if !self.kbuckets.get(distance.ilog2() as usize).unwrap().contains(distance) && (self.info.clone().unwrap().bootstrap || quantity.get(distance.ilog2() as usize).unwrap()<&(K_SIZE as i64)){
-            self.kbuckets.get_mut(distance.ilog2() as usize).unwrap().insert(new.clone(), distance);
+        let bucket = self.kbuckets.get_mut(distance.ilog2() as usize).unwrap();
+        if bucket.contains(distance){ return false }
+        if self.info.clone().unwrap().bootstrap || quantity.get(distance.ilog2() as usize).unwrap()<&(k as i64){
+            bucket.insert(new.clone(), distance);
             self.neighbours.push(new.clone().id);
             return true;
         };
+        bucket.remember(new, distance, k);
+        false
+    }
+    //Fills free bucket slots with the most recently met replacements that still look alive
+    pub fn promote(&mut self, k:usize) -> Vec<NodeInfo>{
+        let mut promoted = Vec::new();
+        for bucket in self.kbuckets.iter_mut(){
+            while (bucket.get_quantity() as usize) < k{
+                let candidate = match bucket.replacements.pop(){ Some(candidate) => candidate, None => break };
+                let info = candidate.info.clone().unwrap();
+                if pool().is_unhealthy_node(&info) || pool().is_unresponsive(&info){ continue }
+                bucket.nodes.push(candidate);
+                self.neighbours.push(info.id.clone());
+                promoted.push(info);
+            }
+        }
+        promoted
+    }
+    //Drops a contact from its bucket and from the neighbour list
+    pub fn forget(&mut self, id:String){
+        if let Some(info) = self.get_neighbour(id.clone()){ pool().forget(&info) }
+        self.remove(id.clone());
+        self.neighbours.retain(|i| *i != id);
+    }
+    //Frees a slot in a full bucket by dropping a contact the connection pool has marked unhealthy
+    pub fn evict_unhealthy(&mut self, index:usize) -> bool{
+        for i in self.kbuckets.get(index).unwrap().clone().nodes{
+            let info = i.info.unwrap();
+            if pool().is_unhealthy_node(&info) || pool().is_unresponsive(&info){
+                info!(contact = %info.id, "CONTACT IS UNHEALTHY, EVICTING");
+                self.forget(info.id.clone());
+                for url in info.urls(){ pool().evict(&url) }
+                return true
+            }
+        }
         false
     }
     pub fn get_neighbour(&self, other:String) -> Option<NodeInfo>{
@@ -120,7 +184,7 @@ impl Node{
                 return Option::from(cur.clone())
             }
         }
-        return None
+        None
     }
     pub fn get_neighbours(&self) -> Vec<NodeInfo>{
         let mut res = Vec::new();
@@ -131,24 +195,33 @@ impl Node{
         }
         res
     }
-    pub fn get_bucket(&self, node:String) -> Vec<NodeInfo>{
-        let distance = Self::distance(self.info.clone().unwrap().id, node);
-        if distance==0{return Vec::new()}
-        let mut res= Vec::new();
-        for i in self.kbuckets.get(distance.ilog2() as usize).unwrap().clone().nodes{
-            res.push(i.info.unwrap());
-        };
-        while res.len()==0{
-            for i in self.kbuckets.get((distance.ilog2()- 1) as usize).unwrap().clone().nodes{
-                res.push(i.info.unwrap());
-            };
-            for i in self.kbuckets.get((distance.ilog2()+ 1) as usize).unwrap().clone().nodes{
-                res.push(i.info.unwrap());
-            };
-        };
+    //Known contacts ordered by distance to the target, at most k of them
+    pub fn closest_to(&self, target:&str, k:usize) -> Vec<NodeInfo>{
+        let mut res = self.get_neighbours();
+        res.sort_by_key(|i| Self::distance(i.id.clone(), target.to_string()));
+        res.dedup_by(|a, b| a.id == b.id);
+        res.truncate(k);
         res
     }
-    pub fn get_closest_nodes(&self, node:NodeInfo) -> Vec<NodeInfo>{
+    //Records that a lookup targeted the bucket the target falls into
+    pub fn looked_up(&mut self, target:&str){
+        let distance = Self::distance(self.info.clone().unwrap().id, target.to_string());
+        if distance==0{ return }
+        if let Some(bucket) = self.kbuckets.get_mut(distance.ilog2() as usize){ bucket.last_lookup = now_millis() }
+    }
+    //Buckets without a lookup for max_age_ms, from the one holding the closest contact outwards:
+    //the closer ones cannot be filled by lookups, nobody is known to be that close
+    pub fn stale_buckets(&self, max_age_ms:u64) -> Vec<usize>{
+        let first = match self.kbuckets.iter().position(|i| i.get_quantity() > 0){ Some(first) => first, None => return Vec::new() };
+        (first..self.kbuckets.len()).filter(|i| now_millis().saturating_sub(self.kbuckets[*i].last_lookup) > max_age_ms).collect()
+    }
+    //Random ID whose distance from this node falls in the range of bucket index
+    pub fn random_id(&self, index:usize) -> String{
+        let own = self.info.clone().unwrap().id;
+        let distance = (1i64 << index) | with_rng(|i| i.gen_range(0..(1i64 << index)));
+        format!("{:0width$x}", i64::from_str_radix(&own, 16).unwrap() ^ distance, width = own.len())
+    }
+    pub fn get_closest_nodes(&self, node:NodeInfo, k:usize) -> Vec<NodeInfo>{
         if self.info.clone().unwrap().bootstrap{
             let all_nodes = self.neighbours.clone();
             let mut res = Vec::new();
@@ -158,12 +231,10 @@ impl Node{
             }
             for i in all_nodes{
                 let distance = Self::distance(node.clone().id, i.clone());
-                if distance > 0{
-                    if temp.get(distance.ilog2() as usize).unwrap()< &(K_SIZE as i64) {
-                        if let Some(cur) = self.get_neighbour(i.clone()){
-                            res.push(cur);
-                            temp.insert(distance.ilog2() as usize, temp.get(distance.ilog2() as usize).unwrap() + 1i64);
-                        }
+                if distance > 0 && temp.get(distance.ilog2() as usize).unwrap()< &(k as i64) {
+                    if let Some(cur) = self.get_neighbour(i.clone()){
+                        res.push(cur);
+                        temp.insert(distance.ilog2() as usize, temp.get(distance.ilog2() as usize).unwrap() + 1i64);
                     }
                 }
             }
@@ -177,6 +248,25 @@ impl Node{
             bucket.remove(node.clone(), distance)
         }
     }
+    //Marks every contact holding this key as alive now
+    pub fn touch(&mut self, pkey:&[u8]){
+        for bucket in self.kbuckets.iter_mut(){
+            for i in bucket.nodes.iter_mut(){
+                if i.info.as_ref().is_some_and(|info| info.pkey == pkey){ i.last_seen = now_millis() }
+            }
+        }
+    }
+    pub fn snapshot(&self) -> RoutingSnapshot{
+        let mut contacts = Vec::new();
+        for i in self.kbuckets.clone(){
+            contacts.extend(i.nodes);
+        }
+        // contacts that answered our pings count as seen too
+        for i in contacts.iter_mut(){
+            if let Some(liveness) = i.info.as_ref().and_then(|info| pool().liveness(info)){ i.last_seen = i.last_seen.max(liveness.last_seen) }
+        }
+        RoutingSnapshot{ contacts }
+    }
     pub fn get_quantity(&self) ->Vec<i64>{
         let mut res = Vec::new();
         for i in self.kbuckets.clone(){
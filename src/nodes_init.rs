@@ -1,74 +1,230 @@
-use std::net::{Ipv4Addr, SocketAddr};
-use std::time::{Duration};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::Instant;
 use tokio::task;
+use tokio::task::JoinSet;
+use tokio::sync::Notify;
 use tonic::{Status};
 use tonic::transport::Server;
+use tracing::{info, info_span, warn, Instrument};
 use crate::endpoint::EndpointService;
 use crate::proto::*;
 use crate::requests::*;
 use crate::util::*;
-pub async fn init_client(node: Node) ->  Result<bool, Status>{
-    if node.info.clone().unwrap().bootstrap && node.info.clone().unwrap().port== BOOTSTRAP_PORTS.get(0).unwrap().parse::<u32>().unwrap(){
+use crate::keystore::Identity;
+use crate::config::{Config, DhtConfig};
+use crate::bootstrap::is_first_bootstrap;
+use crate::storage::{load_routes, BlockStore};
+use crate::light::sync_headers;
+//Services whose routing tables are flushed on shutdown
+fn services() -> &'static Mutex<Vec<EndpointService>>{
+    static SERVICES: OnceLock<Mutex<Vec<EndpointService>>> = OnceLock::new();
+    SERVICES.get_or_init(|| Mutex::new(Vec::new()))
+}
+//Every node started in this process
+pub fn started() -> Vec<EndpointService>{
+    services().lock().unwrap().clone()
+}
+fn stop() -> &'static Notify{
+    static STOP: OnceLock<Notify> = OnceLock::new();
+    STOP.get_or_init(Notify::new)
+}
+//Asks the binary to shut down as if interrupted
+pub fn request_shutdown(){
+    stop().notify_one()
+}
+//Returns on Ctrl-C or once a shutdown is requested, after which the binary calls shutdown
+pub async fn shutdown_requested(){
+    tokio::select!{
+        _ = tokio::signal::ctrl_c() => {}
+        _ = stop().notified() => {}
+    }
+}
+pub async fn shutdown(){
+    let services = services().lock().unwrap().clone();
+    for i in services{ i.save_routes().await }
+}
+//Rejoins through the contacts of a previous run, skipping the bootstraps if enough of them answer
+pub async fn warm_restart(node: Node, routes: &str) -> bool{
+    let snapshot = match load_routes(routes){ Some(snapshot) => snapshot, None => return false };
+    let own = node.info.clone().unwrap();
+    let mut alive = Vec::new();
+    for i in snapshot.contacts{
+        let info = match i.info{ Some(info) => info, None => continue };
+        if info.id == own.id || now_millis().saturating_sub(i.last_seen) > CONTACT_TTL_MS{ continue }
+        if !ping_request(&node, &info).await{ continue }
+        if update_request(node.clone(), vec![own.clone()], info.clone()).await{ alive.push(info) }
+    }
+    if alive.len() < WARM_RESTART_CONTACTS{ return false }
+    info!(contacts = alive.len(), "REJOINED THROUGH SAVED CONTACTS");
+    update_request(node.clone(), alive, own).await;
+    true
+}
+pub async fn init_client(service: &EndpointService, routes: Option<String>) ->  Result<bool, Status>{
+    let mut node = service.node().await;
+    if let Some(path) = routes{
+        if warm_restart(node.clone(), &path).await{ return Ok(true) }
+    }
+    let peers = service.peers();
+    if is_first_bootstrap(&node.info.clone().unwrap(), &peers).await{
         return Ok(true)
     }
-    let neighbours = join_request(node.clone()).await;
-    if neighbours.len()==0{return Ok(false)};
+    let (mut neighbours, mut observed) = join_request(node.clone(), &peers).await;
+    // behind a NAT the bootstraps see another address than the ones bound here, advertise it when they could dial it back
+    if let Some(address) = observed.clone().filter(|i| i.reachable && !node.info.clone().unwrap().addresses.contains(&i.ip)){
+        info!(address = %address.ip, "ADVERTISING OBSERVED ADDRESS");
+        node = service.advertise(address.ip).await;
+        (neighbours, observed) = join_request(node.clone(), &peers).await;
+    }
+    if observed.is_some_and(|i| !i.reachable){
+        warn!("CANNOT BE DIALED BACK, JOINING AS A NON-ROUTABLE CLIENT");
+        node = service.set_non_routable().await;
+    }
+    if neighbours.is_empty(){return Ok(false)};
     for i in neighbours.clone(){
-        let mut send=Vec::new();
-        send.push(node.info.clone().unwrap());
-        update_request(node.clone(), send, format_url(i.ip, i.port.to_string())).await;
+        let send=vec![node.info.clone().unwrap()];
+        update_request(node.clone(), send, i).await;
     }
-    update_request(node.clone(), neighbours.clone(), format_url(node.info.clone().unwrap().ip, node.info.clone().unwrap().port.to_string())).await;
+    update_request(node.clone(), neighbours.clone(), node.info.clone().unwrap()).await;
     Ok(true)
 }
 
-pub async fn serve_client(service: EndpointService,addr: SocketAddr) -> Result<bool, Box<dyn std::error::Error>>{
+pub async fn serve_client(service: EndpointService,addr: SocketAddr, node: Node) -> Result<bool, Box<dyn std::error::Error>>{
     //println!("{}", format_url(addr.ip().to_string(), addr.port().to_string()));
     let msg= format!("FAILURE INITIALIZING NODE SERVER: {}", addr.clone());
-    Server::builder()
-        .add_service(crate::EndpointServer::new(service))
-        .serve(addr.clone()).await.expect(msg.as_str());
+    let max_message_bytes = service.config().limits.max_message_bytes;
+    let router = Server::builder().add_service(crate::proto::endpoint_server::EndpointServer::new(service).max_decoding_message_size(max_message_bytes));
+    crate::tls::serve(router, addr, &node).await.expect(msg.as_str());
     Ok(true)
 }
-pub async fn refresh(node:Node){
-    tokio::time::sleep(Duration::new(REFRESH_PERIOD as u64, 0)).await;
-    let url = format_url(node.info.clone().unwrap().ip, node.info.clone().unwrap().port.to_string());
-    let neighbours = neighbours_request(node.clone(), url.clone()).await;
+//Iterative lookup: asks the alpha closest contacts not asked yet for their neighbours until no closer ones turn up
+pub async fn lookup(service:&EndpointService, target:String, dht:&DhtConfig) -> Vec<NodeInfo>{
+    let node = service.node().await;
+    let own_id = node.info.clone().unwrap().id;
+    service.looked_up(&target).await;
+    let mut shortlist = node.closest_to(&target, dht.k);
+    let mut asked = HashSet::new();
+    loop{
+        let batch:Vec<NodeInfo> = shortlist.iter().filter(|i| !asked.contains(&i.id)).take(dht.alpha).cloned().collect();
+        if batch.is_empty(){ break }
+        let mut requests = JoinSet::new();
+        for i in batch{
+            asked.insert(i.id.clone());
+            requests.spawn(neighbours_request(node.clone(), i));
+        }
+        while let Some(response) = requests.join_next().await{
+            for i in response.unwrap_or_default(){
+                if i.id != own_id && !i.non_routable && !shortlist.iter().any(|k| k.id == i.id){ shortlist.push(i) }
+            }
+        }
+        shortlist.sort_by_key(|i| Node::distance(i.id.clone(), target.clone()));
+        // peers with a bad record are asked last and are the first to fall off the shortlist
+        service.reputation().prefer(&mut shortlist);
+        shortlist.truncate(dht.k);
+    }
+    shortlist
+}
+//Pings the neighbours, replaces the dead ones from the replacement caches and looks up a random ID in every idle bucket
+pub async fn refresh(service:&EndpointService, dht:&DhtConfig){
+    tokio::time::sleep(Duration::new(dht.refresh_period, 0)).await;
+    let node = service.node().await;
+    let own = node.info.clone().unwrap();
     let mut res = 0;
-    for i in neighbours.clone(){
-        let receiver_url = format_url(i.ip.clone(), i.port.clone().to_string());
-        if !ping_request(receiver_url).await{
-            println!("NODE {} IS DOWN, REMOVING...", i.id.clone());
-            remove_request(node.clone(), i.clone(), url.clone()).await;
+    let mut rtt = Duration::ZERO;
+    for i in node.get_neighbours(){
+        if service.reputation().is_banned_node(&i){
+            info!(contact = %i.id, "CONTACT IS BANNED, REMOVING");
+            service.forget(i.id.clone()).await;
+            continue
+        }
+        // a single lost ping is tolerated, the contact goes once it misses MAX_MISSED_PINGS in a row
+        if !ping_request(&node, &i).await && (pool().is_unhealthy_node(&i) || pool().is_unresponsive(&i)){
+            info!(contact = %i.id, "CONTACT IS DOWN, REMOVING");
+            service.forget(i.id.clone()).await;
+        }
+        else if let Some(liveness) = pool().liveness(&i){
+            res+=1;
+            rtt += liveness.rtt;
         }
         else { res+=1 }
-        //else { if res=="".to_string(){res = format!("{}@{}",i.id, i.port)}else{res = format!("{}, {}@{}", res, i.id, i.port)} };
     }
-    println!("NODE {}@{} ACTIVE NEIGHBOURS: {}", node.info.clone().unwrap().id, node.info.clone().unwrap().port, res);
+    for i in service.promote().await{
+        info!(contact = %i.id, "PROMOTED FROM THE REPLACEMENT CACHE");
+    }
+    for index in node.stale_buckets(dht.bucket_refresh * 1000){
+        refresh_bucket(service, index, dht).await;
+    }
+    let average = if res > 0 { rtt.as_millis() / res as u128 } else { 0 };
+    info!(port = own.port, neighbours = res, rtt_ms = average as u64, "ACTIVE NEIGHBOURS");
 }
-
-pub async fn create_client(port:Option<u32>, miner:bool) -> Result<Option<Node>, Box<dyn std::error::Error>>{
+//Looks up a random ID in the bucket's range and adds the new contacts that answer, returning how many were added
+pub async fn refresh_bucket(service:&EndpointService, index:usize, dht:&DhtConfig) -> usize{
+    let node = service.node().await;
+    let mut alive = Vec::new();
+    for i in lookup(service, node.random_id(index), dht).await{
+        // contacts only heard of through other nodes must answer before they are routed to
+        if node.get_neighbour(i.id.clone()).is_none() && ping_request(&node, &i).await{ alive.push(i) }
+    }
+    let learned = alive.len();
+    service.learn(alive).await;
+    learned
+}
+//Announces this node to the contacts closest to its own ID, so it stays in their tables
+pub async fn republish(service:&EndpointService, dht:&DhtConfig){
+    let node = service.node().await;
+    let own = node.info.clone().unwrap();
+    if own.non_routable{ return }
+    for i in lookup(service, own.id.clone(), dht).await{
+        update_request(node.clone(), vec![own.clone()], i).await;
+    }
+}
+pub async fn create_client(config:Arc<Config>, port:Option<u32>, miner:bool, identity:Identity, store:Box<dyn BlockStore>, routes:Option<String>) -> Result<Option<Node>, Box<dyn std::error::Error>>{
+    let service = start_node(config, port, miner, identity, store, routes).await;
+    Ok(Option::from(service.node().await))
+}
+//Sets up, serves and joins a node, returning its service once the join is done
+pub async fn start_node(config:Arc<Config>, port:Option<u32>, miner:bool, identity:Identity, store:Box<dyn BlockStore>, routes:Option<String>) -> EndpointService{
     let mut service = EndpointService::default();
-    let node;
-    let addr= get_ip_address().await;
-    if port!=None{ node = service.setup_client(addr, port, false).await }
-    else{ node = service.setup_client(addr, None, miner).await; };
+    let node = if port.is_some(){ service.setup_client(config.clone(), port, false, identity, store, routes.clone()).await }
+    else{ service.setup_client(config.clone(), None, miner, identity, store, routes.clone()).await };
     //println!("CREATING NODE: {}@{}", node.info.clone().unwrap().id, node.info.clone().unwrap().port);
-    let server_node = node.clone();
-    task::spawn(async move{
-        let addr:SocketAddr = format_addr(server_node.info.clone().unwrap().ip, server_node.info.clone().unwrap().port.to_string());
-        println!("ADDRESS: {}", addr.clone());
-        serve_client(service, addr).await.expect("FAILURE SPAWNING CLIENT SERVER");
-    });
-    if port!=None{
-        let time_node=node.clone();
+    services().lock().unwrap().push(service.clone());
+    if routes.is_some(){
+        let snapshot_service = service.clone();
         task::spawn(async move{
-            loop{refresh(time_node.clone()).await;}
+            loop{
+                tokio::time::sleep(Duration::new(ROUTES_SNAPSHOT_PERIOD, 0)).await;
+                snapshot_service.save_routes().await;
+            }
         });
     };
-    init_client(node.clone()).await.expect("FAILURE INITIALIZING CLIENT");
-    if miner{
-
+    let info = node.info.clone().unwrap();
+    // everything the node does on its own, outside of an RPC, is logged under its ID
+    let span = info_span!("node", node = %info.id);
+    for ip in info.addresses.clone(){
+        let service = service.clone();
+        let server_node = node.clone();
+        let addr:SocketAddr = format_addr(ip, info.port.to_string());
+        task::spawn(async move{
+            info!(%addr, "LISTENING");
+            serve_client(service, addr, server_node).await.expect("FAILURE SPAWNING CLIENT SERVER");
+        }.instrument(span.clone()));
     }
-    Ok(Option::from(node.clone()))
+    init_client(&service, routes).instrument(span.clone()).await.expect("FAILURE INITIALIZING CLIENT");
+    if service.light().is_some(){ sync_headers(&service).instrument(span.clone()).await; }
+    let refresh_service = service.clone();
+    let dht = config.dht.clone();
+    task::spawn(async move{
+        let mut republished = Instant::now();
+        loop{
+            refresh(&refresh_service, &dht).await;
+            if republished.elapsed() >= Duration::new(dht.bucket_refresh, 0){
+                republish(&refresh_service, &dht).await;
+                republished = Instant::now();
+            }
+        }
+    }.instrument(span));
+    service
 }
\ No newline at end of file
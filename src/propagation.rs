@@ -0,0 +1,79 @@
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::BroadcastStream;
+use tonic::Status;
+use crate::proto::{Node, TransactionRequest, UpdateBlockchainRequest};
+use crate::signatures::seal;
+use crate::util::*;
+
+pub type AnnouncementStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+//Fan-out side of block/transaction gossip: every subscriber holds a receiver on these channels
+#[derive(Clone,Debug)]
+pub struct Announcements{
+    blocks: broadcast::Sender<UpdateBlockchainRequest>,
+    transactions: broadcast::Sender<TransactionRequest>,
+    seen: std::sync::Arc<tokio::sync::RwLock<SeenCache>>,
+}
+//Keys relayed lately, each forgotten after SEEN_TTL_MS or once MAX_SEEN newer ones came in
+#[derive(Debug,Default)]
+struct SeenCache{
+    keys: HashMap<String, u64>,
+    order: VecDeque<(String, u64)>, //Keys by the time they were first seen, oldest first
+}
+impl SeenCache{
+    fn insert(&mut self, key:String, now:u64) -> bool{
+        self.expire(now);
+        if self.keys.contains_key(&key){ return false }
+        while self.order.len() >= MAX_SEEN{ self.forget_oldest() }
+        self.keys.insert(key.clone(), now);
+        self.order.push_back((key, now));
+        true
+    }
+    fn contains(&self, key:&str, now:u64) -> bool{
+        self.keys.get(key).is_some_and(|i| now.saturating_sub(*i) < SEEN_TTL_MS)
+    }
+    fn expire(&mut self, now:u64){
+        while self.order.front().is_some_and(|(_, i)| now.saturating_sub(*i) >= SEEN_TTL_MS){ self.forget_oldest() }
+    }
+    fn forget_oldest(&mut self){
+        if let Some((key, _)) = self.order.pop_front(){ self.keys.remove(&key); }
+    }
+}
+impl Default for Announcements{
+    fn default() -> Self {
+        let (blocks, _) = broadcast::channel(ANNOUNCEMENT_CAPACITY);
+        let (transactions, _) = broadcast::channel(ANNOUNCEMENT_CAPACITY);
+        Announcements{ blocks, transactions, seen: std::sync::Arc::from(tokio::sync::RwLock::from(SeenCache::default())) }
+    }
+}
+impl Announcements{
+    //Returns true only the first time an item is seen, so relayed announcements do not loop between peers
+    pub async fn first_seen(&self, key:String) -> bool{
+        self.seen.write().await.insert(key, now_millis())
+    }
+    pub async fn seen(&self, key:&str) -> bool{
+        self.seen.read().await.contains(key, now_millis())
+    }
+    //Open subscriptions, whichever kind has more
+    pub fn subscribers(&self) -> usize{
+        self.blocks.receiver_count().max(self.transactions.receiver_count())
+    }
+    pub fn announce_block(&self, block:UpdateBlockchainRequest){
+        let _ = self.blocks.send(block); // no subscribers is not an error
+    }
+    pub fn announce_transaction(&self, transaction:TransactionRequest){
+        let _ = self.transactions.send(transaction);
+    }
+    //Announcements are kept unsigned and sealed separately for each subscriber as they are sent
+    pub fn block_stream(&self, source:Node, subscriber:String) -> AnnouncementStream<UpdateBlockchainRequest>{
+        Box::pin(BroadcastStream::new(self.blocks.subscribe()).filter_map(|i| i.ok())
+            .map(move |i| Ok(seal(i, subscriber.clone(), &source))))
+    }
+    pub fn transaction_stream(&self, source:Node, subscriber:String) -> AnnouncementStream<TransactionRequest>{
+        Box::pin(BroadcastStream::new(self.transactions.subscribe()).filter_map(|i| i.ok())
+            .map(move |i| Ok(seal(i, subscriber.clone(), &source))))
+    }
+}
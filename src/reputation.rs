@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tracing::{error, warn};
+use crate::config::ReputationConfig;
+use crate::proto::{Ban, BanList, NodeInfo};
+use crate::storage::{load_bans, save_bans};
+use crate::util::*;
+
+//What a node thinks of each peer, by key fingerprint. Every interaction moves the score, which decays back towards
+//neutral over time; peers below zero are tried last and peers below the ban threshold are shunned for a while.
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Event{
+    Success, //Answered with a valid signature
+    Timeout, //Unreachable or too slow after every retry
+    BadSignature, //Sent a message that failed authentication, or signed with a key it is not known by
+    InvalidBlock, //Sent a block that lacks the proof of work or does not match its body, or a proof that does not hold
+}
+impl Event{
+    fn weight(self) -> f64{
+        match self{
+            Event::Success => 1.0,
+            Event::Timeout => -2.0,
+            Event::BadSignature => -10.0,
+            Event::InvalidBlock => -15.0,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug)]
+struct Standing{
+    score: f64,
+    updated: u64,
+}
+
+#[derive(Debug)]
+pub struct Reputation{
+    config: ReputationConfig,
+    path: Option<String>, //Where the ban list is saved, not saved when None
+    peers: Mutex<HashMap<String, Standing>>,
+    bans: Mutex<HashMap<String, u64>>, //Until when each peer is banned, in unix millis
+}
+impl Default for Reputation{
+    fn default() -> Self {
+        Reputation::new(ReputationConfig::default(), None)
+    }
+}
+impl Reputation{
+    //Picks up the bans saved at path by a previous run
+    pub fn new(config:ReputationConfig, path:Option<String>) -> Reputation{
+        let now = now_millis();
+        let bans = path.as_deref().and_then(load_bans).map(|i| i.bans).unwrap_or_default()
+            .into_iter().filter(|i| i.until > now).map(|i| (i.fingerprint, i.until)).collect();
+        Reputation{ config, path, peers: Mutex::new(HashMap::new()), bans: Mutex::new(bans) }
+    }
+    fn decayed(&self, standing:Standing, now:u64) -> f64{
+        let elapsed = now.saturating_sub(standing.updated) as f64 / 1000.0;
+        standing.score * 0.5f64.powf(elapsed / self.config.half_life as f64)
+    }
+    pub fn score(&self, fingerprint:&str) -> f64{
+        self.peers.lock().unwrap().get(fingerprint).map(|i| self.decayed(*i, now_millis())).unwrap_or(0.0)
+    }
+    //Returns whether the event got the peer banned
+    pub fn record(&self, fingerprint:&str, event:Event) -> bool{
+        let now = now_millis();
+        let mut peers = self.peers.lock().unwrap();
+        let standing = peers.entry(fingerprint.to_string()).or_insert(Standing{ score: 0.0, updated: now });
+        let score = (self.decayed(*standing, now) + event.weight()).min(MAX_REPUTATION);
+        if score >= self.config.ban_threshold as f64{
+            *standing = Standing{ score, updated: now };
+            return false
+        }
+        drop(peers);
+        warn!(peer = fingerprint, seconds = self.config.ban_duration, event = ?event, "PEER BANNED");
+        self.ban(fingerprint, self.config.ban_duration);
+        true
+    }
+    pub fn ban(&self, fingerprint:&str, seconds:u64){
+        // a ban wipes the slate, the peer starts over from neutral once it expires
+        self.peers.lock().unwrap().remove(fingerprint);
+        self.bans.lock().unwrap().insert(fingerprint.to_string(), now_millis() + seconds * 1000);
+        self.save();
+    }
+    //Returns whether there was a ban to lift
+    pub fn unban(&self, fingerprint:&str) -> bool{
+        let lifted = self.bans.lock().unwrap().remove(fingerprint).is_some_and(|until| until > now_millis());
+        self.save();
+        lifted
+    }
+    pub fn ban_duration(&self) -> u64{
+        self.config.ban_duration
+    }
+    //Current score of every peer met and not banned since
+    pub fn scores(&self) -> Vec<(String, f64)>{
+        let now = now_millis();
+        self.peers.lock().unwrap().iter().map(|(fingerprint, standing)| (fingerprint.clone(), self.decayed(*standing, now))).collect()
+    }
+    pub fn is_banned(&self, fingerprint:&str) -> bool{
+        self.bans.lock().unwrap().get(fingerprint).is_some_and(|until| *until > now_millis())
+    }
+    pub fn is_banned_node(&self, info:&NodeInfo) -> bool{
+        self.is_banned(&key_fingerprint(&info.pkey))
+    }
+    //Drops banned contacts and moves the ones with a negative score to the back, keeping the order otherwise
+    pub fn prefer(&self, nodes:&mut Vec<NodeInfo>){
+        nodes.retain(|i| !self.is_banned_node(i));
+        nodes.sort_by_key(|i| self.score(&key_fingerprint(&i.pkey)) < 0.0);
+    }
+    pub fn bans(&self) -> BanList{
+        let now = now_millis();
+        let bans = self.bans.lock().unwrap().iter().filter(|(_, until)| **until > now)
+            .map(|(fingerprint, until)| Ban{ fingerprint: fingerprint.clone(), until: *until }).collect();
+        BanList{ bans }
+    }
+    pub fn save(&self){
+        if let Some(path) = self.path.clone(){
+            if let Err(e) = save_bans(&path, self.bans()){ error!(path, error = %e, "FAILURE SAVING BAN LIST") }
+        }
+    }
+}
+
+fn reputations() -> &'static RwLock<HashMap<String, Arc<Reputation>>>{
+    static REPUTATIONS: OnceLock<RwLock<HashMap<String, Arc<Reputation>>>> = OnceLock::new();
+    REPUTATIONS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+//Must happen before the node with this ID is started
+pub fn register(node:&str, reputation:Reputation){
+    reputations().write().unwrap().insert(node.to_string(), Arc::new(reputation));
+}
+//The scores kept by the node with this ID, fresh defaults for a node that never registered any
+pub fn reputation(node:&str) -> Arc<Reputation>{
+    if let Some(reputation) = reputations().read().unwrap().get(node){ return reputation.clone() }
+    reputations().write().unwrap().entry(node.to_string()).or_default().clone()
+}
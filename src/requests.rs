@@ -1,166 +1,463 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
 use rand::Rng;
-use tonic::{Status};
-use tonic::transport::Channel;
+use tokio::time::Instant;
+use tonic::{Code, Response, Status, Streaming};
+use tonic::transport::{Channel, Endpoint};
 use crate::proto;
-use crate::proto::{NeighboursRequest, Node, NodeInfo, UpdateRequest, Signature, Block, RetrieveBlockchainResponse, RetrieveBlockchainRequest, TransactionRequest, MineRequest, UpdateBlockchainRequest, ObtainTransactionsRequest};
+use crate::proto::{AuctionsRequest, AuctionsResponse, BalanceRequest, BalanceResponse, Bid, BidValueRequest, CloseBidRequest, CreateBidRequest, TransactionStatusRequest, NeighboursRequest, Node, NodeInfo, ObservedAddress, PingRequest, UpdateRequest, Block, RetrieveBlockchainRequest, TransactionRequest, MineRequest, UpdateBlockchainRequest, ObtainTransactionsRequest, SubscribeBlocksRequest, SubscribeTransactionsRequest, ProofRequest, ProofResponse};
 use crate::proto::endpoint_client::EndpointClient;
+use crate::blockchain::transaction_line;
 use crate::proto::miner_client::MinerClient;
 use crate::util::*;
 use crate::signatures::*;
-async fn try_connect(url:String) -> Result<Option<EndpointClient<Channel>>, Status>{
-    if EndpointClient::connect(url.clone()).await.is_err(){return Ok(None)}
-    Ok(Option::from(EndpointClient::connect(url).await.expect("FAILURE CONNECTING TO DESTINATION")))
+use crate::bootstrap::*;
+use crate::tls::Connector;
+use crate::reputation::{reputation, Event};
+use crate::metrics::metrics;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+#[derive(Debug,Default)]
+struct PeerConnection{
+    clients: HashMap<(String, Option<String>), EndpointClient<Channel>>, // by local and expected peer key, each local key dials with its own certificate
+    failures: u32,
+    open_until: Option<Instant>,
 }
-async fn try_connect_miner(url:String) -> Result<Option<MinerClient<Channel>>, Status>{
-    if MinerClient::connect(url.clone()).await.is_err(){return Ok(None)}
-    Ok(Option::from(MinerClient::connect(url).await.expect("FAILURE CONNECTING TO DESTINATION")))
+
+//What the pings to a contact showed so far
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Liveness{
+    pub rtt: Duration, // round trip of the last answered ping
+    pub last_seen: u64,
+    pub missed: u32, // consecutive unanswered pings
 }
 
-pub async fn ping_request(url:String) -> bool{
-    if let Some(_) = try_connect(url).await.expect("FAILURE PINGING DESTINATION"){ return true }
-    false
+//Keeps lazily dialed TLS EndpointClients per peer URL plus a circuit breaker on its recent failures,
+//and the liveness of each contact by key fingerprint
+#[derive(Debug,Default)]
+pub struct ConnectionPool{
+    peers: Mutex<HashMap<String, PeerConnection>>,
+    contacts: Mutex<HashMap<String, Liveness>>,
+}
+impl ConnectionPool{
+    pub fn get(&self, url:String, source:&Node, expected:Option<String>) -> Option<EndpointClient<Channel>>{
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(url.clone()).or_default();
+        let key = (key_fingerprint(&source.info.clone().unwrap().pkey), expected.clone());
+        if let Some(client) = peer.clients.get(&key){
+            return Option::from(client.clone())
+        }
+        let channel = endpoint(url)?
+            .timeout(Duration::from_millis(RPC_TIMEOUT_MS))
+            .connect_with_connector_lazy(Connector::new(source, expected));
+        let client = EndpointClient::new(channel);
+        peer.clients.insert(key, client.clone());
+        Option::from(client)
+    }
+    //False while the breaker is open; once the cooldown expires one call is let through (half-open)
+    pub fn available(&self, url:&str) -> bool{
+        match self.peers.lock().unwrap().get(url).and_then(|peer| peer.open_until){
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+    pub fn is_unhealthy(&self, url:&str) -> bool{
+        if let Some(peer) = self.peers.lock().unwrap().get(url){
+            return peer.failures >= BREAKER_THRESHOLD
+        }
+        false
+    }
+    pub fn success(&self, url:&str){
+        if let Some(peer) = self.peers.lock().unwrap().get_mut(url){
+            peer.failures = 0;
+            peer.open_until = None;
+        }
+    }
+    pub fn failure(&self, url:&str){
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(url.to_string()).or_default();
+        peer.failures += 1;
+        if peer.failures >= BREAKER_THRESHOLD{
+            peer.open_until = Option::from(Instant::now() + Duration::from_millis(BREAKER_COOLDOWN_MS));
+            peer.clients.clear(); // redial from scratch once the cooldown expires
+        }
+    }
+    //A node is unhealthy once every address it advertises is
+    pub fn is_unhealthy_node(&self, info:&NodeInfo) -> bool{
+        info.urls().iter().all(|url| self.is_unhealthy(url))
+    }
+    pub fn evict(&self, url:&str){
+        self.peers.lock().unwrap().remove(url);
+    }
+    pub fn answered(&self, info:&NodeInfo, rtt:Duration){
+        self.contacts.lock().unwrap().insert(key_fingerprint(&info.pkey), Liveness{ rtt, last_seen: now_millis(), missed: 0 });
+    }
+    pub fn missed(&self, info:&NodeInfo){
+        self.contacts.lock().unwrap().entry(key_fingerprint(&info.pkey)).or_default().missed += 1;
+    }
+    pub fn liveness(&self, info:&NodeInfo) -> Option<Liveness>{
+        self.contacts.lock().unwrap().get(&key_fingerprint(&info.pkey)).copied()
+    }
+    pub fn is_unresponsive(&self, info:&NodeInfo) -> bool{
+        self.liveness(info).is_some_and(|i| i.missed >= MAX_MISSED_PINGS)
+    }
+    pub fn forget(&self, info:&NodeInfo){
+        self.contacts.lock().unwrap().remove(&key_fingerprint(&info.pkey));
+    }
+}
+pub fn pool() -> &'static ConnectionPool{
+    static POOL: OnceLock<ConnectionPool> = OnceLock::new();
+    POOL.get_or_init(ConnectionPool::default)
+}
+//Responses are checked against their own replay window, shared by every request helper in the process
+fn responses() -> &'static ReplayCache{
+    static RESPONSES: OnceLock<ReplayCache> = OnceLock::new();
+    RESPONSES.get_or_init(ReplayCache::default)
+}
+//Verifies a response was signed for this node by the peer it was sent to; a bad signature is logged and treated like no answer.
+//Either way the outcome counts towards the peer's reputation.
+fn accept<T: Signed>(response:T, source:&Node, destination:&NodeInfo) -> Option<T>{
+    let reputation = reputation(&source.info.clone().unwrap().id);
+    let fingerprint = key_fingerprint(&destination.pkey);
+    let checked = open(&response, &source.info.clone().unwrap().id, responses()).and_then(|sign| match sign.pkey == destination.pkey{
+        true => Ok(sign),
+        false => Err(Rejection::OtherKey),
+    });
+    match checked{
+        Ok(_) => {
+            reputation.record(&fingerprint, Event::Success);
+            Option::from(response)
+        }
+        Err(rejection) => {
+            warn!(reason = rejection.message(), "REJECTED RESPONSE");
+            metrics(&source.info.clone().unwrap().id).signature_failure(T::METHOD);
+            if rejection.is_forgery(){ reputation.record(&fingerprint, Event::BadSignature); }
+            None
+        }
+    }
+}
+
+//Besides a valid signature, the answer of a bootstrap must come from its pinned key, and so must the bootstraps it lists
+async fn accept_bootstrap(response:proto::JoinResponse, source:&Node, peer:&BootstrapPeer, peers:&[BootstrapPeer]) -> Option<proto::JoinResponse>{
+    let sign = match open(&response, &source.info.clone().unwrap().id, responses()){
+        Ok(sign) => sign,
+        Err(rejection) => {
+            warn!(reason = rejection.message(), "REJECTED RESPONSE");
+            metrics(&source.info.clone().unwrap().id).signature_failure(proto::JoinResponse::METHOD);
+            return None
+        }
+    };
+    if !peer.accepts(&sign.pkey){
+        warn!(bootstrap = %peer.url(), "REJECTED BOOTSTRAP: KEY DOES NOT MATCH THE PINNED FINGERPRINT");
+        return None
+    }
+    for i in response.neighbours.iter().filter(|i| i.bootstrap){
+        if peers.iter().any(|k| k.lists(i) && !k.accepts(&i.pkey)){
+            warn!(bootstrap = %peer.url(), listed = %format!("{}:{}", i.ip, i.port), "REJECTED BOOTSTRAP: LISTS A BOOTSTRAP WITH AN UNPINNED KEY");
+            return None
+        }
+    }
+    Some(response)
+}
+fn rejected() -> Status{
+    Status::unauthenticated("REJECTED RESPONSE")
+}
+fn transient(status:&Status) -> bool{
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded | Code::Aborted)
+}
+//A request that timed out may still have been applied, so RPCs that change state are only sent again when it surely was not
+fn retryable(status:&Status, method:&str) -> bool{
+    transient(status) && !(status.code() == Code::DeadlineExceeded && NON_IDEMPOTENT.contains(&method))
+}
+const NON_IDEMPOTENT: [&str; 3] = [TransactionRequest::METHOD, UpdateBlockchainRequest::METHOD, CloseBidRequest::METHOD];
+fn backoff(attempt:u32) -> Duration{
+    let delay = BACKOFF_BASE_MS.saturating_mul(1 << attempt).min(BACKOFF_MAX_MS);
+    let jitter = with_rng(|i| i.gen_range(0..=delay/2));
+    Duration::from_millis(delay + jitter)
 }
 
-pub async fn join_request(source:Node) -> Vec<NodeInfo>{
-    let addresses = known_bootstrap_addresses().await;
-    let ip_index= rand::thread_rng().gen_range(0..addresses.len());
-    let url;
+fn endpoint(url:String) -> Option<Endpoint>{
+    Some(Endpoint::from_shared(url).ok()?.connect_timeout(Duration::from_millis(CONNECT_TIMEOUT_MS)))
+}
+//Addresses to try in order and the fingerprint the answering TLS key must have, if known
+struct Target{
+    peer: String, //Node ID, or the address of a bootstrap whose ID is not known yet
+    recipient: String, //ID the requests are sealed for, empty when not known yet
+    urls: Vec<String>,
+    fingerprint: Option<String>,
+}
+impl From<&NodeInfo> for Target{
+    fn from(info:&NodeInfo) -> Self {
+        Target{ peer: info.id.clone(), recipient: info.id.clone(), urls: info.urls(), fingerprint: Some(key_fingerprint(&info.pkey)) }
+    }
+}
+impl From<&BootstrapPeer> for Target{
+    fn from(peer:&BootstrapPeer) -> Self {
+        Target{ peer: peer.url(), recipient: String::new(), urls: vec![peer.url()], fingerprint: peer.fingerprint.clone() }
+    }
+}
+//Tries each address of the peer in order until one answers; banned peers are not called at all.
+//The message is sealed anew for every attempt, a resent signature would be refused as replayed.
+async fn call<M, T, F, Fut>(source:&Node, target:Target, message:M, rpc:F) -> Option<T>
+where M: Signed, F: Fn(EndpointClient<Channel>, tonic::Request<M>) -> Fut, Fut: Future<Output = Result<Response<T>, Status>>{
+    try_call(source, target, message, rpc).await.ok()
+}
+//Same as call, with the status of the last address tried when none answered
+async fn try_call<M, T, F, Fut>(source:&Node, target:Target, message:M, rpc:F) -> Result<T, Status>
+where M: Signed, F: Fn(EndpointClient<Channel>, tonic::Request<M>) -> Fut, Fut: Future<Output = Result<Response<T>, Status>>{
+    let span = info_span!("rpc", node = %source.info.clone().unwrap().id, peer = %target.peer, method = M::METHOD.rsplit('/').next(), outgoing = true);
+    async move {
+        if target.fingerprint.as_ref().is_some_and(|i| reputation(&source.info.clone().unwrap().id).is_banned(i)){ return Err(Status::permission_denied("PEER IS BANNED")) }
+        let mut last = Status::invalid_argument("PEER HAS NO ADDRESS");
+        let sealed = || seal(message.clone(), target.recipient.clone(), source);
+        for url in target.urls{
+            match call_url(source, url, target.fingerprint.clone(), &sealed, &rpc).await{
+                Ok(response) => return Ok(response),
+                Err(status) if transient(&status) && !retryable(&status, M::METHOD) => return Err(status),
+                Err(status) => last = status,
+            }
+        }
+        Err(last)
+    }.instrument(span).await
+}
+//Runs one RPC against a pooled client, retrying transient failures with exponential backoff and jitter.
+//Fails without calling when the peer's breaker is open, instead of panicking the caller.
+async fn call_url<M, T, S, F, Fut>(source:&Node, url:String, expected:Option<String>, sealed:&S, rpc:&F) -> Result<T, Status>
+where M: Signed, S: Fn() -> M, F: Fn(EndpointClient<Channel>, tonic::Request<M>) -> Fut, Fut: Future<Output = Result<Response<T>, Status>>{
+    if !pool().available(&url){ return Err(Status::unavailable(format!("TOO MANY FAILURES CALLING {}", url))) }
+    let mut last = Status::unavailable("NO ATTEMPT MADE");
+    for attempt in 0..=MAX_RETRIES{
+        let client = pool().get(url.clone(), source, expected.clone()).ok_or_else(|| Status::invalid_argument(format!("INVALID URL {}", url)))?;
+        match rpc(client, tonic::Request::new(sealed())).await{
+            Ok(response) => {
+                pool().success(&url);
+                debug!(url, attempt, "REQUEST ANSWERED");
+                return Ok(response.into_inner())
+            }
+            Err(status) if retryable(&status, M::METHOD) && attempt < MAX_RETRIES => tokio::time::sleep(backoff(attempt)).await,
+            Err(status) => {
+                warn!(url, code = ?status.code(), reason = status.message(), "REQUEST FAILED");
+                last = status;
+                break
+            }
+        }
+    }
+    // only a peer that did not answer counts against its breaker, one refusing a request, even for load, is alive
+    if transient(&last){ pool().failure(&url) }
+    if let (true, Some(fingerprint)) = (transient(&last), expected){ reputation(&source.info.clone().unwrap().id).record(&fingerprint, Event::Timeout); }
+    Err(last)
+}
+
+//The miner service runs beside its node and answers with the node's own key
+async fn try_connect_miner(source:&Node, url:String) -> Result<Option<MinerClient<Channel>>, Status>{
+    let expected = Some(key_fingerprint(&source.info.clone().unwrap().pkey));
+    match endpoint(url){
+        Some(endpoint) => Ok(endpoint.connect_with_connector(Connector::new(source, expected)).await.ok().map(MinerClient::new)),
+        None => Ok(None),
+    }
+}
+
+//Signed round trip that only counts when answered with the contact's own key; the outcome is recorded in the pool
+pub async fn ping_request(source:&Node, destination:&NodeInfo) -> bool{
+    let request = PingRequest{ node: source.info.clone(), sign:None };
+    let start = Instant::now();
+    let response = call(source, Target::from(destination), request, |mut client, request| async move { client.ping(request).await }).await;
+    let alive = match response.and_then(|i| accept(i, source, destination)){
+        Some(response) => response.response && response.sign.is_some_and(|i| i.pkey == destination.pkey),
+        None => false,
+    };
+    if alive{ pool().answered(destination, start.elapsed()) } else { pool().missed(destination) }
+    alive
+}
+
+//One signed ping, without retries, to the IP a join came from on the port the joiner advertises.
+//Only that IP is dialed, so a join cannot point the bootstrap at another host, nor hold it up for long.
+pub async fn dial_back(source:&Node, sender:&NodeInfo, ip:String) -> bool{
+    let Some(mut client) = pool().get(format_url(ip, sender.port.to_string()), source, Some(key_fingerprint(&sender.pkey))) else { return false };
+    let request = tonic::Request::new(seal(PingRequest{ node: source.info.clone(), sign:None }, sender.id.clone(), source));
+    match tokio::time::timeout(Duration::from_millis(DIAL_BACK_TIMEOUT_MS), client.ping(request)).await{
+        Ok(Ok(response)) => accept(response.into_inner(), source, sender).is_some_and(|i| i.response),
+        _ => false,
+    }
+}
+
+//Neighbours to start from, and the address the bootstraps saw the request come from, one that could dial back when any could
+pub async fn join_request(source:Node, peers:&[BootstrapPeer]) -> (Vec<NodeInfo>, Option<ObservedAddress>){
+    // the joining node does not know the bootstrap IDs yet, so join requests carry no recipient
     if source.info.clone().unwrap().bootstrap{
-        url = format_url(addresses.get(ip_index).unwrap().clone(), BOOTSTRAP_PORTS.get(0).unwrap().to_string());
-        if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-            let hash = sign_join_request(source.clone(), source.skey.clone());
-            let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-            let request = tonic::Request::new(proto::JoinRequest { node: Some(source.clone()), sign:Option::from(sign)});
-            let response = client.join(request).await.expect("FAILURE RETRIEVING NEIGHBOURS");
-            verify_join_response(response.get_ref().clone());
-            return response.get_ref().clone().neighbours;
-        };
+        if let Some(peer) = peers.first(){
+            let request = proto::JoinRequest { node: Some(source.public()), sign:None};
+            if let Some(response) = call(&source, Target::from(peer), request, |mut client, request| async move { client.join(request).await }).await{
+                if let Some(response) = accept_bootstrap(response, &source, peer, peers).await{
+                    return (response.neighbours, response.observed);
+                }
+            };
+        }
     }
     else {
-        let mut boot_addresses = Vec::new();
         let mut responses = Vec::new();
-        for i in addresses{
-            for j in 0..BOOTSTRAP_PORTS.len(){
-                boot_addresses.push(format_url(i.clone(), BOOTSTRAP_PORTS.get(j).unwrap().to_string()));
-            }
-        }
-        for url in boot_addresses{
-            if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-                let hash = sign_join_request(source.clone(), source.skey.clone());
-                let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-                let request = tonic::Request::new(proto::JoinRequest { node: Some(source.clone()), sign:Option::from(sign)});
-                let response = client.join(request).await.expect("FAILURE RETRIEVING NEIGHBOURS");
-                verify_join_response(response.get_ref().clone());
-                responses.push(response.get_ref().clone().neighbours)
+        let mut observed = Vec::new();
+        for peer in peers.iter(){
+            let request = proto::JoinRequest { node: Some(source.public()), sign:None};
+            if let Some(response) = call(&source, Target::from(peer), request, |mut client, request| async move { client.join(request).await }).await{
+                if let Some(response) = accept_bootstrap(response, &source, peer, peers).await{
+                    responses.push(response.neighbours);
+                    observed.extend(response.observed);
+                }
             };
         }
-        return join_request_consensus(responses);
+        let observed = observed.iter().find(|i| i.reachable).or(observed.first()).cloned();
+        return (join_request_consensus(responses), observed);
     };
-    Vec::new()
+    (Vec::new(), None)
 }
 
-pub async fn find_node(source:Node, node:String, url:String) -> Option<NodeInfo> {
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_find_node_request(source.info.clone().unwrap().id, node.clone(), source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(proto::FindNodeRequest{ source_id:source.info.clone().unwrap().id, target:node, sign:Option::from(sign)});
-        let response = client.find_node(request).await.expect("FAILURE FINDING NODE");
-        verify_find_node_response(response.get_ref().clone());
-        return response.get_ref().clone().node
-    };
-    None
+pub async fn find_node(source:Node, node:String, destination:NodeInfo) -> Option<NodeInfo> {
+    let request = proto::FindNodeRequest{ source_id:source.info.clone().unwrap().id, target:node, sign:None};
+    let response = call(&source, Target::from(&destination), request, |mut client, request| async move { client.find_node(request).await }).await?;
+    // only the node holding the key the ID comes from can answer for it
+    accept(response, &source, &destination)?.node.filter(|i| owns_id(&i.id, &i.pkey))
 }
 
-pub async fn remove_request(source:Node, node: NodeInfo, url:String){
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION") {
-        let hash= sign_remove_request(source.info.clone().unwrap().id, node.clone(), source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(proto::RemoveRequest{ source_id:source.info.clone().unwrap().id, node: Option::from(node.clone()), sign:Option::from(sign)});
-        let response = client.remove_node(request).await.expect("FAILURE REMOVING NODES");
-        verify_remove_response(response.get_ref().clone());
+pub async fn remove_request(source:Node, node: NodeInfo, destination:NodeInfo){
+    let request = proto::RemoveRequest{ source_id:source.info.clone().unwrap().id, node: Option::from(node.clone()), sign:None};
+    if let Some(response) = call(&source, Target::from(&destination), request, |mut client, request| async move { client.remove_node(request).await }).await{
+        accept(response, &source, &destination);
     }
 }
 
-pub async fn update_request(source:Node, nodes:Vec<NodeInfo>, url:String) -> bool{
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_update_request(source.info.clone().unwrap().id, nodes.clone(), source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(UpdateRequest{ source_id:source.info.clone().unwrap().id, neighbours: nodes.clone(), sign:Option::from(sign)});
-        let response = client.update_node(request).await.expect("FAILURE UPDATING NODE");
-        verify_update_response(response.get_ref().clone());
-        return true
+pub async fn update_request(source:Node, nodes:Vec<NodeInfo>, destination:NodeInfo) -> bool{
+    let request = UpdateRequest{ source_id:source.info.clone().unwrap().id, neighbours: nodes.clone(), sign:None};
+    if let Some(response) = call(&source, Target::from(&destination), request, |mut client, request| async move { client.update_node(request).await }).await{
+        return accept(response, &source, &destination).is_some()
     };
     false
 }
 
-pub async fn neighbours_request(source:Node, url:String) -> Vec<NodeInfo>{
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_neighbours_request(source.info.clone().unwrap().id, source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(NeighboursRequest{source_id:source.info.clone().unwrap().id, sign:Option::from(sign)});
-        let response = client.get_neighbours(request).await.expect("FAILURE RETRIEVING NEIGHBOURS");
-        verify_neighbours_response(response.get_ref().clone());
-        return response.get_ref().clone().neighbours
+pub async fn neighbours_request(source:Node, destination:NodeInfo) -> Vec<NodeInfo>{
+    let request = NeighboursRequest{source_id:source.info.clone().unwrap().id, sign:None};
+    if let Some(response) = call(&source, Target::from(&destination), request, |mut client, request| async move { client.get_neighbours(request).await }).await{
+        if let Some(response) = accept(response, &source, &destination){
+            return response.neighbours
+        }
     };
     Vec::new()
 }
 
-pub async fn retrieve_blockchain_request(source:Node, url:String) -> Vec<Block>{
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_retrieve_blockchain_request(source.info.clone().unwrap().id, source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(RetrieveBlockchainRequest{source_id:source.info.clone().unwrap().id, sign:Option::from(sign)});
-        let response = client.retrieve_blockchain(request).await.expect("FAILURE RETRIEVING NEIGHBOURS");
-        verify_retrieve_blockchain_response(response.get_ref().clone());
-        return response.get_ref().clone().blockchain
+pub async fn retrieve_blockchain_request(source:Node, destination:NodeInfo) -> Vec<Block>{
+    try_retrieve_blockchain_request(&source, &destination).await.unwrap_or_default()
+}
+pub async fn try_retrieve_blockchain_request(source:&Node, destination:&NodeInfo) -> Result<Vec<Block>, Status>{
+    let request = RetrieveBlockchainRequest{source_id:source.info.clone().unwrap().id, sign:None};
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.retrieve_blockchain(request).await }).await?;
+    Ok(accept(response, source, destination).ok_or_else(rejected)?.blockchain)
+}
+pub async fn update_blockchain_request(source:Node, new:Block, transactions:Vec<String>, destination:NodeInfo){
+    let request = UpdateBlockchainRequest{
+        source_id:source.info.clone().unwrap().id,
+        new:Option::from(new.clone()),
+        sign:None,
+        transactions
+    };
+    if let Some(response) = call(&source, Target::from(&destination), request, |mut client, request| async move { client.update_blockchain(request).await }).await{
+        accept(response, &source, &destination);
     }
-    Vec::new()
 }
-pub async fn update_blockchain_request(source:Node, new:Block, url:String){
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_update_blockchain_request(source.info.clone().unwrap().id, new.clone(), source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(UpdateBlockchainRequest{
-            source_id:source.info.clone().unwrap().id,
-            new:Option::from(new.clone()),
-            sign:Option::from(sign)
-        });
-        let response = client.update_blockchain(request).await.expect("FAILURE UPDATING BLOCKCHAIN");
-        verify_update_blockchain_response(response.get_ref().clone());
-    }
-}
-
-pub async fn transaction_request(source:Node, sender:String, value:i32, destination:String, url:String) -> String{
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_transaction_request(source.info.clone().unwrap().id, value.clone(), destination.clone(), source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(TransactionRequest{source_id:source.info.clone().unwrap().id,
-            sender, value:value as u32, destination, sign:Option::from(sign)});
-        let response = client.transaction(request).await.expect("FAILURE RETRIEVING NEIGHBOURS");
-        verify_transaction_response(response.get_ref().clone());
-        if response.get_ref().clone().state=="queued"{ println!("Transaction In Hold: Generating Block...") }
-        return response.get_ref().clone().state
-    }
-    String::new()
-}
-
-pub async fn obtain_transactions_request(source:Node, url:String) -> Vec<String>{
-    if let Some(mut client) = try_connect(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let hash = sign_obtain_transactions_request(source.info.clone().unwrap().id, source.skey.clone());
-        let sign = Signature{hash, pkey:source.info.clone().unwrap().pkey};
-        let request = tonic::Request::new(ObtainTransactionsRequest{source_id:source.info.clone().unwrap().id, sign:Option::from(sign)});
-        let response = client.obtain_transactions(request).await.expect("FAILURE OBTAINING TRANSACTIONS");
-        verify_obtain_transactions_response(response.get_ref().clone());
-        return response.get_ref().clone().transactions;
+
+//Sending the same nonce to several nodes makes one transfer, a new nonce another
+//Transfer signed by the source, which only counts when the source owns the sender's account
+pub fn signed_transfer(source:&Node, sender:String, value:u32, destination_id:String, nonce:u64) -> TransactionRequest{
+    let transfer = transaction_line(&sender, value, &destination_id, nonce);
+    TransactionRequest{ source_id: source.info.clone().unwrap().id, sender, value, destination: destination_id, sign: None, nonce,
+        sender_key: source.info.clone().unwrap().pkey, sender_sign: sign_transfer(&transfer, source) }
+}
+pub async fn transaction_request(source:Node, sender:String, value:i32, destination_id:String, nonce:u64, destination:NodeInfo) -> String{
+    try_transaction_request(&source, sender, value as u32, destination_id, nonce, &destination).await.unwrap_or_default()
+}
+//The state the node reports: "processed" or "queued" by a miner, empty from other nodes
+pub async fn try_transaction_request(source:&Node, sender:String, value:u32, destination_id:String, nonce:u64, destination:&NodeInfo) -> Result<String, Status>{
+    let request = signed_transfer(source, sender, value, destination_id, nonce);
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.transaction(request).await }).await?;
+    let response = accept(response, source, destination).ok_or_else(rejected)?;
+    if response.state=="queued"{ info!("TRANSACTION QUEUED, GENERATING BLOCK") }
+    Ok(response.state)
+}
+
+pub async fn obtain_transactions_request(source:Node, destination:NodeInfo) -> Vec<String>{
+    let request = ObtainTransactionsRequest{source_id:source.info.clone().unwrap().id, sign:None};
+    if let Some(response) = call(&source, Target::from(&destination), request, |mut client, request| async move { client.obtain_transactions(request).await }).await{
+        if let Some(response) = accept(response, &source, &destination){
+            return response.transactions;
+        }
     };
     Vec::new()
 }
 
-pub async fn mine_request(source:String, previous:Block, transactions:Vec<String>, url:String) -> Option<Block>{
-    if let Some(mut client) = try_connect_miner(url).await.expect("FAILURE CONNECTING TO DESTINATION"){
-        let request = tonic::Request::new(MineRequest{source_id:source, previous:Option::from(previous), transactions});
+pub async fn mine_request(source:Node, previous:Block, transactions:Vec<String>, url:String) -> Option<Block>{
+    if let Some(mut client) = try_connect_miner(&source, url).await.expect("FAILURE CONNECTING TO DESTINATION"){
+        let request = tonic::Request::new(MineRequest{source_id:source.info.clone().unwrap().id, previous:Option::from(previous), transactions});
         let response = client.mine(request).await.expect("FAILURE RETRIEVING NEIGHBOURS");
         return response.get_ref().clone().new
     }
     None
-}
\ No newline at end of file
+}
+
+pub async fn subscribe_blocks_request(source:Node, destination:NodeInfo) -> Option<Streaming<UpdateBlockchainRequest>>{
+    let request = SubscribeBlocksRequest{source_id:source.info.clone().unwrap().id, sign:None};
+    call(&source, Target::from(&destination), request, |mut client, request| async move { client.subscribe_blocks(request).await }).await
+}
+
+//Learns the ID of the node at a configured address from a ping addressed to no one, keeping the address to reach it by
+pub async fn identify_request(source:&Node, peer:&BootstrapPeer) -> Result<NodeInfo, Status>{
+    let request = PingRequest{ node: source.info.clone(), sign:None };
+    let response = try_call(source, Target::from(peer), request, |mut client, request| async move { client.ping(request).await }).await?;
+    let sign = open(&response, &source.info.clone().unwrap().id, responses())?;
+    let node = response.node.filter(|i| i.pkey == sign.pkey && peer.accepts(&i.pkey)).ok_or_else(|| Status::unauthenticated("NODE DID NOT ANSWER WITH ITS OWN KEY"))?;
+    // the addresses it advertises may not be reachable from here, the configured one was
+    Ok(NodeInfo{ ip: peer.host.clone(), port: peer.port, addresses: vec![peer.host.clone()], ..node })
+}
+pub async fn balance_request(source:&Node, account:String, destination:&NodeInfo) -> Result<BalanceResponse, Status>{
+    let request = BalanceRequest{ source_id: source.info.clone().unwrap().id, account, sign:None };
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.balance(request).await }).await?;
+    accept(response, source, destination).ok_or_else(rejected)
+}
+pub async fn transaction_status_request(source:&Node, sender:String, value:u32, destination_id:String, nonce:u64, destination:&NodeInfo) -> Result<String, Status>{
+    let request = TransactionStatusRequest{ source_id: source.info.clone().unwrap().id, sender, value, destination: destination_id, sign:None, nonce };
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.transaction_status(request).await }).await?;
+    Ok(accept(response, source, destination).ok_or_else(rejected)?.state)
+}
+//Returns the name of the new auction
+pub async fn create_bid_request(source:&Node, name:String, destination:&NodeInfo) -> Result<String, Status>{
+    let request = CreateBidRequest{ source_id: source.info.clone().unwrap().id, name, sign:None };
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.create_bid(request).await }).await?;
+    Ok(accept(response, source, destination).ok_or_else(rejected)?.bid_name)
+}
+//Returns whether the bid is now the highest
+pub async fn bid_value_request(source:&Node, name:String, value:u32, destination:&NodeInfo) -> Result<bool, Status>{
+    let request = BidValueRequest{ source_id: source.info.clone().unwrap().id, name, value, sign:None };
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.bid_value(request).await }).await?;
+    Ok(accept(response, source, destination).ok_or_else(rejected)?.success)
+}
+pub async fn auctions_request(source:&Node, destination:&NodeInfo) -> Result<AuctionsResponse, Status>{
+    let request = AuctionsRequest{ source_id: source.info.clone().unwrap().id, sign:None };
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.auctions(request).await }).await?;
+    accept(response, source, destination).ok_or_else(rejected)
+}
+pub async fn close_bid_request(source:&Node, name:String, destination:&NodeInfo) -> Result<Bid, Status>{
+    let request = CloseBidRequest{ source_id: source.info.clone().unwrap().id, name, sign:None };
+    let response = try_call(source, Target::from(destination), request, |mut client, request| async move { client.close_bid(request).await }).await?;
+    Ok(accept(response, source, destination).ok_or_else(rejected)?.bid.unwrap_or_default())
+}
+
+//Header of the block holding the transaction and the Merkle proof of it, from a node that keeps block bodies
+pub async fn proof_request(source:&Node, transaction:String, destination:&NodeInfo) -> Option<ProofResponse>{
+    let request = ProofRequest{ source_id: source.info.clone().unwrap().id, transaction, sign:None };
+    let response = call(source, Target::from(destination), request, |mut client, request| async move { client.proof(request).await }).await?;
+    accept(response, source, destination)
+}
+pub async fn subscribe_transactions_request(source:Node, destination:NodeInfo) -> Option<Streaming<TransactionRequest>>{
+    let request = SubscribeTransactionsRequest{source_id:source.info.clone().unwrap().id, sign:None};
+    call(&source, Target::from(&destination), request, |mut client, request| async move { client.subscribe_transactions(request).await }).await
+}
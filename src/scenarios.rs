@@ -0,0 +1,231 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use rand::Rng;
+use crate::adversary::{Behaviour, Persona};
+use crate::endpoint::EndpointService;
+use crate::harness::*;
+use crate::keystore::Identity;
+use crate::proto::NodeInfo;
+use crate::requests::{find_node, join_request, transaction_request, update_request};
+use crate::simulation::{Faults, SimNetwork};
+use crate::transport::{assign, transport};
+use crate::util::*;
+
+//Runs each attack of the threat model against a small simulated network with one adversary in it and reports how the honest
+//nodes fared. Await these on a current_thread runtime with the clock paused and util::seed_rng called, as tests/simulation.rs does.
+//Only built with the adversary feature, which the integration tests turn on.
+
+const SETTLE_SECS: u64 = 30; //Virtual time for a network to form before the attack starts
+const SPEND: i32 = 5; //Coins moved by the double spend, and given to the attacker beforehand
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Attack{
+    ForgedFindNode,
+    WrongKey,
+    WithheldBlocks,
+    DoubleSpend,
+    SybilFlood,
+    Eclipse,
+}
+impl Attack{
+    pub const ALL: [Attack; 6] = [Attack::ForgedFindNode, Attack::WrongKey, Attack::WithheldBlocks, Attack::DoubleSpend, Attack::SybilFlood, Attack::Eclipse];
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Verdict{
+    Detected, //Honest nodes noticed and rejected or dropped the attacker
+    Tolerated, //Nobody noticed, but the honest nodes were not misled
+    Subverted, //Honest nodes acted on the attacker's lie
+}
+
+#[derive(Clone,Debug)]
+pub struct Outcome{
+    pub attack: Attack,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+impl Display for Outcome{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<16}{:<11}{}", format!("{:?}", self.attack), format!("{:?}", self.verdict), self.detail)
+    }
+}
+
+//Every attack in turn, each on a network of its own
+pub async fn report(seed:u64) -> Vec<Outcome>{
+    let mut outcomes = Vec::new();
+    for i in Attack::ALL{ outcomes.push(run(i, seed).await) }
+    outcomes
+}
+
+pub async fn run(attack:Attack, seed:u64) -> Outcome{
+    let sim = SimNetwork::new(seed, Faults::default());
+    let (bootstraps, miners, clients) = match attack{
+        Attack::WithheldBlocks => (2, 1, 2),
+        Attack::DoubleSpend => (1, 1, 3),
+        _ => (1, 0, 4),
+    };
+    let network = Network::simulate(test_config(), bootstraps, miners, clients, &sim).await;
+    network.wait_for_neighbours(1, Duration::from_secs(SETTLE_SECS)).await;
+    // blocks only travel through the bootstraps, so that is where withholding them matters
+    let attacker = match attack{
+        Attack::WithheldBlocks => network.bootstraps[0].clone(),
+        _ => network.clients.last().unwrap().clone(),
+    };
+    let (verdict, detail) = match attack{
+        Attack::ForgedFindNode => forged_find_node(&network, &attacker).await,
+        Attack::WrongKey => wrong_key(&network, &attacker).await,
+        Attack::WithheldBlocks => withheld_blocks(&network, &attacker).await,
+        Attack::DoubleSpend => double_spend(&network, &attacker).await,
+        Attack::SybilFlood => sybil_flood(&network, &attacker, 3 * network.config.dht.k).await,
+        Attack::Eclipse => eclipse(&network, &attacker, &network.clients[0], 4 * network.config.dht.k).await,
+    };
+    // the nodes keep running until the runtime stops, cut them off so they do not slow down the next scenario
+    for i in network.nodes(){ sim.crash(&info(&i).await.id) }
+    Outcome{ attack, verdict, detail }
+}
+
+async fn info(service:&EndpointService) -> NodeInfo{
+    service.node().await.info.unwrap()
+}
+async fn honest(network:&Network, attacker:&EndpointService) -> Vec<EndpointService>{
+    let attacker = info(attacker).await.id;
+    let mut res = Vec::new();
+    for i in network.nodes(){
+        if info(&i).await.id != attacker{ res.push(i) }
+    }
+    res
+}
+//Long enough for every contact to be pinged until it would be dropped
+fn detection_window(network:&Network) -> Duration{
+    Duration::from_secs(network.config.dht.refresh_period * (MAX_MISSED_PINGS as u64 + 2))
+}
+
+//A node asks the attacker where another node is
+async fn forged_find_node(network:&Network, attacker:&EndpointService) -> (Verdict, String){
+    attacker.set_persona(Persona::honest().with(Behaviour::ForgeFindNode)).await;
+    let asker = network.clients[0].node().await;
+    let target = info(&network.clients[1]).await;
+    match find_node(asker.clone(), target.id.clone(), info(attacker).await).await{
+        None => (Verdict::Detected, "the forged answer was rejected".to_string()),
+        Some(found) if found.pkey == target.pkey => (Verdict::Tolerated, "the answer named the real node".to_string()),
+        Some(found) => (Verdict::Subverted, format!("{} resolved {} to the attacker at {}:{}", asker.info.unwrap().id, target.id, found.ip, found.port)),
+    }
+}
+
+//The attacker answers with signatures from a key that is not the one it is known by
+async fn wrong_key(network:&Network, attacker:&EndpointService) -> (Verdict, String){
+    let identity = Identity::generate(KEY_ALGORITHM, network.config.dht.id_size);
+    attacker.set_persona(Persona::honest().with(Behaviour::WrongKey(identity))).await;
+    let id = info(attacker).await.id;
+    let mut contacts = Vec::new();
+    for i in honest(network, attacker).await{
+        if i.node().await.get_neighbour(id.clone()).is_some(){ contacts.push(i) }
+    }
+    if contacts.is_empty(){ return (Verdict::Tolerated, "no honest node routed to the attacker".to_string()) }
+    let dropped = wait_for(detection_window(network), || async {
+        for i in contacts.iter(){
+            if i.node().await.get_neighbour(id.clone()).is_some(){ return false }
+        }
+        true
+    }).await;
+    if dropped{ return (Verdict::Detected, format!("all {} contacts dropped it once its pings came back signed with another key", contacts.len())) }
+    (Verdict::Subverted, "honest nodes still route to it".to_string())
+}
+
+//A bootstrap keeps the blocks announced to it
+async fn withheld_blocks(network:&Network, attacker:&EndpointService) -> (Verdict, String){
+    attacker.set_persona(Persona::honest().with(Behaviour::WithholdBlocks)).await;
+    let destination = info(&network.clients[1]).await.id;
+    network.transfer(&network.clients[0], SPEND, destination).await;
+    let honest = honest(network, attacker).await;
+    let mined = wait_for(Duration::from_secs(SETTLE_SECS), || async {
+        for i in honest.iter(){
+            if i.height().await < 1{ return false }
+        }
+        true
+    }).await;
+    if mined && attacker.height().await > 0{ return (Verdict::Tolerated, "the attacker kept the block but it reached every honest node through the other bootstrap".to_string()) }
+    if mined{ return (Verdict::Tolerated, "the block reached every honest node without going through the attacker".to_string()) }
+    let mut heights = Vec::new();
+    for i in honest.iter(){ heights.push(i.height().await) }
+    (Verdict::Subverted, format!("honest nodes stuck at heights {:?}", heights))
+}
+
+//The attacker is given SPEND coins and sends all it holds to two different nodes through different neighbours
+async fn double_spend(network:&Network, attacker:&EndpointService) -> (Verdict, String){
+    let own = attacker.node().await;
+    let id = own.info.clone().unwrap().id;
+    let funded = attacker.balance(&id).await + SPEND as i64;
+    network.transfer(&network.clients[0], SPEND, id.clone()).await;
+    let honest = honest(network, attacker).await;
+    if !balance_reaches(&honest, &id, funded).await{
+        return (Verdict::Tolerated, "the attacker was never credited, nothing to spend twice".to_string())
+    }
+    let neighbours = own.get_neighbours();
+    let (first, second) = (info(&network.clients[0]).await.id, info(&network.clients[1]).await.id);
+    let nonce = new_nonce();
+    for (index, i) in neighbours.into_iter().enumerate(){
+        let destination = if index % 2 == 0{ first.clone() } else { second.clone() };
+        transaction_request(own.clone(), id.clone(), funded as i32, destination, nonce, i).await;
+    }
+    balance_reaches(&honest, &id, 0).await;
+    let mut overdrawn = 0;
+    for i in honest.iter(){
+        if i.balance(&id).await < 0{ overdrawn += 1 }
+    }
+    if overdrawn > 0{ return (Verdict::Subverted, format!("{} of {} honest nodes accepted both spends", overdrawn, honest.len())) }
+    (Verdict::Detected, "no honest node accepted a spend beyond the attacker's balance".to_string())
+}
+
+async fn balance_reaches(nodes:&[EndpointService], id:&str, expected:i64) -> bool{
+    wait_for(Duration::from_secs(SETTLE_SECS), || async {
+        for i in nodes.iter(){
+            if i.balance(id).await != expected{ return false }
+        }
+        true
+    }).await
+}
+
+//The attacker joins through the bootstraps under many IDs, all of them answered with its own key and address
+async fn sybil_flood(network:&Network, attacker:&EndpointService, count:usize) -> (Verdict, String){
+    let own = attacker.node().await;
+    let buckets = network.config.dht.buckets();
+    let ids:Vec<String> = (0..count).map(|_| own.random_id(with_rng(|i| i.gen_range(0..buckets)))).collect();
+    attacker.set_persona(Persona::honest().with(Behaviour::Aliases(ids.clone()))).await;
+    for id in ids{
+        // the Sybil identities dial out over the attacker's network
+        assign(&id, transport(&own.info.clone().unwrap().id));
+        let mut sybil = own.clone();
+        sybil.info.as_mut().unwrap().id = id;
+        let (neighbours, _) = join_request(sybil.clone(), &attacker.peers()).await;
+        for i in neighbours{ update_request(sybil.clone(), vec![sybil.info.clone().unwrap()], i).await; }
+    }
+    tokio::time::sleep(detection_window(network)).await;
+    let pkey = own.info.unwrap().pkey;
+    let mut verdict = (Verdict::Detected, "no Sybil identity is left in the bootstraps' tables".to_string());
+    for i in network.bootstraps.iter(){
+        let contacts = i.node().await.get_neighbours();
+        let sybils = contacts.iter().filter(|i| i.pkey == pkey).count();
+        let detail = format!("bootstrap {} holds {} attacker contacts and {} honest ones", info(i).await.id, sybils, contacts.len() - sybils);
+        if sybils > contacts.len() - sybils{ return (Verdict::Subverted, detail) }
+        if sybils > 0{ verdict = (Verdict::Tolerated, detail) }
+    }
+    verdict
+}
+
+//The attacker pushes the victim contacts with the IDs closest to its own, all of them answered by the attacker
+async fn eclipse(network:&Network, attacker:&EndpointService, victim:&EndpointService, count:usize) -> (Verdict, String){
+    let own = info(attacker).await;
+    let target = info(victim).await;
+    let ids:Vec<String> = (1..=count as i64).map(|i| format!("{:0width$x}", i64::from_str_radix(&target.id, 16).unwrap() ^ i, width = target.id.len())).collect();
+    attacker.set_persona(Persona::honest().with(Behaviour::Aliases(ids.clone()))).await;
+    let fakes:Vec<NodeInfo> = ids.into_iter().map(|id| NodeInfo{ id, ..own.clone() }).collect();
+    update_request(attacker.node().await, fakes, target.clone()).await;
+    tokio::time::sleep(detection_window(network)).await;
+    let contacts = victim.node().await.get_neighbours();
+    let held = contacts.iter().filter(|i| i.pkey == own.pkey).count();
+    let detail = format!("victim {} holds {} attacker contacts and {} honest ones", target.id, held, contacts.len() - held);
+    if held > contacts.len() - held{ return (Verdict::Subverted, detail) }
+    if held > 0{ return (Verdict::Tolerated, detail) }
+    (Verdict::Detected, detail)
+}
@@ -1,42 +1,52 @@
 use std::time::Duration;
-use proto::endpoint_server::*;
-use proto::miner_server::*;
 use util::*;
 use requests::*;
-use crate::signatures::*;
-use blockchain::*;
 use nodes_init::*;
 
-mod endpoint;
-mod util;
-mod proto{
-    tonic::include_proto!("kademlia");
+use ssd::{util, requests, nodes_init, keystore, storage, bootstrap, config, metrics, admin, logging};
+
+fn bootstrap_identity(port:u32, passphrase:&str, id_size:usize) -> keystore::Identity{
+    let path = format!("{}/bootstrap-{}.key", KEYSTORE_DIR, port);
+    keystore::Identity::load_or_create(&path, passphrase, id_size).expect("FAILURE LOADING BOOTSTRAP IDENTITY")
+}
+fn bootstrap_store(port:u32) -> Box<dyn storage::BlockStore>{
+    let dir = format!("{}/bootstrap-{}", DATA_DIR, port);
+    Box::new(storage::DiskStore::open(&dir).expect("FAILURE OPENING BOOTSTRAP STORAGE"))
+}
+fn bootstrap_routes(port:u32) -> String{
+    format!("{}/bootstrap-{}/routes.bin", DATA_DIR, port)
 }
-mod node;
-mod requests;
-mod signatures;
-mod blockchain;
-mod nodes_init;
 
-async fn generate_bootstraps() -> Result<(), Box<dyn std::error::Error>>{
+async fn generate_bootstraps(config:std::sync::Arc<config::Config>) -> Result<(), Box<dyn std::error::Error>>{
+    let passphrase = read_passphrase("Bootstrap keystore passphrase? ");
+    let mut ports = Vec::new();
+    for i in bootstrap::bootstrap_peers().await{
+        if i.is_local().await{ ports.push(i.port) }
+    }
+    if ports.is_empty(){ return Err("NO BOOTSTRAP CONFIGURED ON THIS HOST".into()) }
     let mut boots= Vec::new();
-    boots.push(create_client(Option::from(BOOTSTRAP_PORTS.get(0).unwrap().to_string().parse::<u32>().unwrap()), false).await.expect("FAILURE INITIALIZING BOOTSTRAP"));
-    tokio::time::sleep(Duration::new(0, 1000)).await;
-    for mut i in 1..BOOTSTRAP_PORTS.len() {
-        let port = BOOTSTRAP_PORTS.get(i).unwrap().to_string().parse::<u32>().unwrap();
-        boots.push(create_client(Option::from(port.clone()), false).await.expect("FAILURE INITIALIZING BOOTSTRAP"));
+    for port in ports{
+        let identity = bootstrap_identity(port, &passphrase, config.dht.id_size);
+        println!("BOOTSTRAP {} FINGERPRINT: {}", port, key_fingerprint(&identity.pkey));
+        boots.push(create_client(config.clone(), Option::from(port), false, identity, bootstrap_store(port), Some(bootstrap_routes(port))).await.expect("FAILURE INITIALIZING BOOTSTRAP"));
+        tokio::time::sleep(Duration::new(0, 1000)).await;
     };
     for i in boots{
         let cur = i.unwrap().clone();
-        let url = format_url(cur.info.clone().unwrap().ip, cur.info.clone().unwrap().port.to_string());
-        let neighbours = neighbours_request(cur.clone(), url).await;
-        println!("{:?}", neighbours);
+        let neighbours = neighbours_request(cur.clone(), cur.info.clone().unwrap()).await;
+        tracing::info!(node = %cur.info.clone().unwrap().id, neighbours = ?neighbours.iter().map(|i| i.id.clone()).collect::<Vec<_>>(), "BOOTSTRAP NEIGHBOURS");
     }
     Ok(())
 }
 
 #[tokio::main]
 async fn main(){
-    generate_bootstraps().await.expect("FAILED TO CREATE SERVER");
-    loop{}
-}
\ No newline at end of file
+    let config = std::sync::Arc::new(config::Config::from_args());
+    logging::init(&config.log);
+    bootstrap::configure(config.bootstrap_peers().unwrap()).expect("FAILURE CONFIGURING BOOTSTRAPS");
+    metrics::expose(&config.metrics).await;
+    admin::expose(&config.admin).await;
+    generate_bootstraps(config).await.expect("FAILED TO CREATE SERVER");
+    shutdown_requested().await;
+    shutdown().await;
+}
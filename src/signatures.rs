@@ -1,269 +1,275 @@
-use std::fmt::format;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
 use openssl::hash::MessageDigest;
-use openssl::pkey::{PKey, Private, Public, Id};
-use openssl::rsa::Rsa;
+use openssl::pkey::{Id, PKey};
 use openssl::sign::{Signer, Verifier};
-use sha256::{digest, TrySha256Digest};
-use crate::blockchain::hash_block;
-use crate::proto::{Block, FindNodeRequest, FindNodeResponse, JoinRequest, JoinResponse, MineRequest, MineResponse, NeighboursRequest, NeighboursResponse, Node, NodeInfo, ObtainTransactionsRequest, ObtainTransactionsResponse, RemoveRequest, RemoveResponse, RetrieveBlockchainRequest, RetrieveBlockchainResponse, TransactionRequest, TransactionResponse, UpdateBlockchainRequest, UpdateBlockchainResponse, UpdateRequest, UpdateResponse};
+use prost::Message;
+use rand::RngCore;
+use tonic::Status;
+use crate::util::*;
+use crate::proto::{KeyAlgorithm, AbortRequest, AbortResponse, BidValueRequest, BidValueResponse, CreateBidRequest, CreateBidResponse, FindNodeRequest, FindNodeResponse, JoinRequest, JoinResponse, NeighboursRequest, NeighboursResponse, Node, ObtainTransactionsRequest, ObtainTransactionsResponse, PingRequest, PingResponse, RemoveRequest, RemoveResponse, RetrieveBlockchainRequest, RetrieveBlockchainResponse, Signature, SubscribeBlocksRequest, SubscribeTransactionsRequest, TransactionRequest, TransactionResponse, UpdateBlockchainRequest, UpdateBlockchainResponse, UpdateRequest, UpdateResponse, RoutingTableRequest, ChainRequest, MempoolRequest, PeersRequest, RefreshBucketsRequest, BanPeerRequest, UnbanPeerRequest, ShutdownRequest, AuctionsRequest, AuctionsResponse, CloseBidRequest, CloseBidResponse, BalanceRequest, BalanceResponse, TransactionStatusRequest, TransactionStatusResponse, ProofRequest, ProofResponse};
 
-fn sign(content:String, skey: Vec<u8>) -> Vec<u8>{
-    let skey = Rsa::private_key_from_pem(&*skey).unwrap();
-    let skey = PKey::from_rsa(skey).unwrap();
+fn sign(content:&[u8], skey: Vec<u8>) -> Vec<u8>{
+    let skey = PKey::private_key_from_pem(&skey).unwrap();
+    if skey.id() == Id::ED25519{
+        return Signer::new_without_digest(&skey).unwrap().sign_oneshot_to_vec(content).unwrap()
+    }
     let mut signer = Signer::new(MessageDigest::sha256(), &skey).unwrap();
-    signer.update(content.as_bytes()).unwrap();
-    let signature = signer.sign_to_vec().unwrap();
-    signature
+    signer.update(content).unwrap();
+    signer.sign_to_vec().unwrap()
 }
 
-pub fn verify(content:String, signature:Vec<u8>, pkey: Vec<u8>){
-    let pkey = PKey::public_key_from_pem(&*pkey).unwrap();
-    let mut verifier = Verifier::new(MessageDigest::sha256(), &pkey).unwrap();
-    verifier.update(content.as_bytes()).unwrap();
-    assert!(verifier.verify(&signature).unwrap())
-}
-pub fn sign_join_request(node:Node, skey:Vec<u8>) -> Vec<u8>{
-    sign(node.to_string(), skey)
-}
-pub fn verify_join_request(request:JoinRequest){
-    let content = request.node.unwrap();
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().pkey;
-    verify(content.to_string(), signature, pkey)
-}
-pub fn sign_join_response(neighbours:Vec<NodeInfo>, blockchain:Vec<Vec<u8>>, skey:Vec<u8>) -> Vec<u8>{
-    let mut input = String::new();
-    for i in neighbours{
-        input = format!("{}{}", input, i.to_string());
-    }
-    for i in blockchain{
-        input = format!("{}{}", input, String::from_utf8(i).unwrap());
-    }
-    sign(input, skey)
-}
-pub fn verify_join_response(response:JoinResponse){
-    let neighbours = response.neighbours;
-    let blockchain = response.blockchain;
-    let mut content = String::new();
-    for i in neighbours{
-        content = format!("{}{}", content, i.to_string());
+//Dispatches on the algorithm the signer claims, and refuses keys that do not match that claim
+pub fn verify(content:&[u8], signature:Vec<u8>, pkey: Vec<u8>, algorithm:i32) -> bool{
+    let pkey = match PKey::public_key_from_pem(&pkey){ Ok(pkey) => pkey, Err(_) => return false };
+    match KeyAlgorithm::try_from(algorithm){
+        Ok(KeyAlgorithm::Ed25519) if pkey.id() == Id::ED25519 => {
+            let mut verifier = Verifier::new_without_digest(&pkey).unwrap();
+            verifier.verify_oneshot(&signature, content).unwrap_or(false)
+        }
+        Ok(KeyAlgorithm::Rsa) if pkey.id() == Id::RSA && pkey.bits() >= RSA_KEY_BITS => {
+            let mut verifier = Verifier::new(MessageDigest::sha256(), &pkey).unwrap();
+            verifier.update(content).unwrap();
+            verifier.verify(&signature).unwrap_or(false)
+        }
+        _ => false,
     }
-    for i in blockchain{
-        content = format!("{}{}", content, String::from_utf8(i).unwrap());
-    }
-    let signature= response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content.to_string(), signature, pkey)
-}
-pub fn sign_find_node_request(source_id:String, node:String, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}", source_id.clone(), node);
-    sign(input, skey)
-}
-pub fn verify_find_node_request(request:FindNodeRequest){
-    let content = format!("{}{}", request.source_id, request.target);
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_find_node_response(source_id:String, info:NodeInfo, skey:Vec<u8>) -> Vec<u8>{
-    let content = format!("{}{}", source_id, info.to_string());
-    sign(content, skey)
 }
-pub fn verify_find_node_response(response:FindNodeResponse){
-    let content = format!("{}{}", response.source_id, response.node.unwrap());
-    let signature= response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content.to_string(), signature, pkey)
+
+fn transfer_content(transfer:&str) -> Vec<u8>{
+    let mut content = Vec::new();
+    put(&mut content, SIGNING_DOMAIN.as_bytes());
+    put(&mut content, b"transfer");
+    put(&mut content, transfer.as_bytes());
+    content
+}
+//Sender's signature over a transfer line, which stays with the line wherever it is relayed and mined
+pub fn sign_transfer(transfer:&str, source:&Node) -> Vec<u8>{
+    sign(&transfer_content(transfer), source.skey.clone())
+}
+//Transfer lines carry no algorithm, the key says which one it is for
+pub fn verify_transfer(transfer:&str, signature:&[u8], pkey:&[u8]) -> bool{
+    let algorithm = match PKey::public_key_from_pem(pkey){
+        Ok(key) if key.id() == Id::RSA => KeyAlgorithm::Rsa,
+        _ => KeyAlgorithm::Ed25519,
+    };
+    verify(&transfer_content(transfer), signature.to_vec(), pkey.to_vec(), algorithm as i32)
 }
-pub fn sign_remove_request(source_id:String, node:NodeInfo, skey:Vec<u8>) -> Vec<u8>{
-    //let input = format!("{}{}", source_id, node.to_string());
-    let input = format!("{}", node);
-    sign(input, skey)
+
+//Every signed message names the RPC it belongs to; the signature covers all of its other fields
+pub trait Signed: Message + Clone{
+    const METHOD: &'static str;
+    fn sign(&self) -> Option<Signature>;
+    fn set_sign(&mut self, sign:Option<Signature>);
 }
-pub fn verify_remove_request(request:RemoveRequest){
-    //let content = format!("{}{}", request.source_id, request.node.unwrap().to_string());
-    let content = format!("{}", request.node.unwrap());
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
+
+fn put(content:&mut Vec<u8>, field:&[u8]){
+    content.extend_from_slice(&(field.len() as u64).to_be_bytes());
+    content.extend_from_slice(field);
 }
-pub fn sign_remove_response(source_id:String, b:bool, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}", source_id, b.to_string());
-    sign(input, skey)
+
+//Canonical signing payload, see the Signature message in kademlia.proto for the layout.
+//prost writes fields in tag order and the protocol has no map fields, so the encoding is deterministic.
+fn envelope<T: Signed>(message:&T, sign:&Signature) -> Vec<u8>{
+    let mut unsigned = message.clone();
+    unsigned.set_sign(None);
+    let mut content = Vec::new();
+    put(&mut content, SIGNING_DOMAIN.as_bytes());
+    put(&mut content, sign.method.as_bytes());
+    put(&mut content, sign.recipient.as_bytes());
+    content.extend_from_slice(&sign.timestamp.to_be_bytes());
+    content.extend_from_slice(&sign.nonce.to_be_bytes());
+    put(&mut content, &unsigned.encode_to_vec());
+    content
 }
-pub fn verify_remove_response(response:RemoveResponse){
-    let content = format!("{}{}", response.source_id, response.success);
-    let signature= response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content.to_string(), signature, pkey)
+
+pub fn seal<T: Signed>(mut message:T, recipient:String, source:&Node) -> T{
+    let mut sign = Signature{
+        hash: Vec::new(),
+        pkey: source.info.clone().unwrap().pkey,
+        algorithm: source.info.clone().unwrap().algorithm,
+        method: T::METHOD.to_string(),
+        recipient,
+        timestamp: now_millis(),
+        nonce: with_rng(|i| i.next_u64()),
+    };
+    message.set_sign(None);
+    sign.hash = self::sign(&envelope(&message, &sign), source.skey.clone());
+    message.set_sign(Option::from(sign));
+    message
 }
-pub fn sign_update_request(source_id:String, nodes:Vec<NodeInfo>, skey:Vec<u8>) -> Vec<u8>{
-    let mut input = source_id.clone();
-    for i in nodes{
-        input = format!("{}{}", input, i.to_string())
+
+pub fn open<T: Signed>(message:&T, recipient:&str, replay:&ReplayCache) -> Result<Signature, Rejection>{
+    let sign = message.sign().ok_or(Rejection::Missing)?;
+    if sign.method != T::METHOD{ return Err(Rejection::OtherMethod) }
+    if sign.recipient != recipient{ return Err(Rejection::OtherRecipient) }
+    if !verify(&envelope(message, &sign), sign.hash.clone(), sign.pkey.clone(), sign.algorithm){
+        return Err(Rejection::Invalid)
     }
-    sign(input, skey)
+    replay.check(&sign)?;
+    Ok(sign)
 }
-pub fn verify_update_request(request:UpdateRequest){
-    let neighbours = request.neighbours;
-    let mut content = request.source_id.clone();
-    for i in neighbours{
-        content = format!("{}{}", content, i.to_string())
+
+//Why a signed message was refused, answered to the peer as UNAUTHENTICATED
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Rejection{
+    Missing,
+    OtherMethod,
+    OtherRecipient,
+    Invalid,
+    NoCertificate, //Received without the TLS client certificate it must be signed with
+    OtherKey, //Validly signed, but not with the key of the peer it came from
+    Stale,
+    Replayed,
+}
+impl Rejection{
+    //Only a bad or misattributed signature is a forgery; a late or repeated delivery can be an honest retry
+    pub fn is_forgery(self) -> bool{
+        !matches!(self, Rejection::Stale | Rejection::Replayed)
     }
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_update_response(source_id:String, b:bool, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}", source_id, b.to_string());
-    sign(input, skey)
-}
-pub fn verify_update_response(response:UpdateResponse){
-    let content = format!("{}{}", response.source_id, response.response);
-    let signature= response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content.to_string(), signature, pkey)
-}
-pub fn sign_neighbours_request(source_id:String, skey:Vec<u8>) -> Vec<u8>{
-    sign(source_id, skey)
-}
-pub fn verify_neighbours_request(request:NeighboursRequest){
-    let content = request.source_id;
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_neighbours_response(source_id:String, neighbours:Vec<NodeInfo>, skey:Vec<u8>) -> Vec<u8>{
-    let mut input = source_id.clone();
-    for i in neighbours{
-        input = format!("{}{}", input, i.to_string());
+    pub fn message(self) -> &'static str{
+        match self{
+            Rejection::Missing => "MISSING SIGNATURE",
+            Rejection::OtherMethod => "SIGNATURE FOR ANOTHER METHOD",
+            Rejection::OtherRecipient => "SIGNATURE FOR ANOTHER RECIPIENT",
+            Rejection::Invalid => "INVALID SIGNATURE",
+            Rejection::NoCertificate => "NO CLIENT CERTIFICATE",
+            Rejection::OtherKey => "SIGNED WITH ANOTHER KEY",
+            Rejection::Stale => "STALE SIGNATURE",
+            Rejection::Replayed => "REPLAYED SIGNATURE",
+        }
     }
-    sign(input, skey)
 }
-pub fn verify_neighbours_response(response:NeighboursResponse){
-    let neighbours = response.neighbours;
-    let mut content = response.source_id.clone();
-    for i in neighbours{
-        content=format!("{}{}", content, i.to_string());
+impl From<Rejection> for Status{
+    fn from(rejection:Rejection) -> Status{
+        Status::unauthenticated(rejection.message())
     }
-    let signature= response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_transaction_request(source_id:String, value:i32, destination:String, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}{}", source_id, value, destination);
-    sign(input, skey)
-}
-pub fn verify_transaction_request(request:TransactionRequest){
-    let source = request.source_id;
-    let value = request.value;
-    let destination = request.destination;
-    let content = format!("{}{}{}", source, value, destination);
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().clone().pkey;
-    verify(content, signature, pkey)
 }
-pub fn sign_transaction_response(source_id:String, state:String, skey:Vec<u8>) -> Vec<u8>{
-    let content = format!("{}{}", source_id, state);
-    sign(content, skey)
-}
-pub fn verify_transaction_response(response:TransactionResponse){
-    let input = format!("{}{}", response.source_id, response.state);
-    let signature = response.sign.clone().unwrap().hash;
-    let pkey = response.sign.clone().unwrap().clone().pkey;
-    verify(input, signature, pkey)
-}
-pub fn sign_obtain_transactions_request(source_id:String, skey:Vec<u8>) -> Vec<u8>{
-    let input = source_id.clone();
-    sign(input, skey)
-}
-pub fn verify_obtain_transactions_request(request:ObtainTransactionsRequest){
-    let content = request.source_id.clone();
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey = request.sign.clone().unwrap().clone().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_obtain_transactions_response(source_id:String, transactions:Vec<String>, skey:Vec<u8>) -> Vec<u8>{
-    let mut input = source_id.clone();
-    for i in transactions.clone(){
-        input = format!("{}{}", input, i.clone());
-    };
-    sign(input,skey)
-}
-pub fn verify_obtain_transactions_response(response:ObtainTransactionsResponse){
-    let mut content = response.source_id.clone();
-    for i in response.transactions.clone(){
-        content = format!("{}{}", content, i.clone());
-    };
-    let signature = response.sign.clone().unwrap().hash;
-    let pkey = response.sign.clone().unwrap().clone().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_retrieve_blockchain_request(source_id:String, skey:Vec<u8>) -> Vec<u8>{
-    sign(source_id.clone(), skey.clone())
-}
-pub fn verify_retrieve_blockchain_request(request: RetrieveBlockchainRequest){
-    let id = request.source_id;
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey= request.sign.clone().unwrap().pkey;
-    verify(id, signature, pkey)
-}
-pub fn sign_retrieve_blockchain_response(source_id:String, blockchain:Vec<Block>, skey:Vec<u8>) -> Vec<u8>{
-    let mut input = source_id.clone();
-    for i in blockchain{
-      input = format!("{}{}", input, String::from_utf8(hash_block(i.clone())).unwrap())
-    };
-    sign(input, skey)
-}
-pub fn verify_retrieve_blockchain_response(response: RetrieveBlockchainResponse){
-    let blockchain = response.blockchain;
-    let mut content = response.source_id.clone();
-    for i in blockchain{
-      content = format!("{}{}", content, String::from_utf8(hash_block(i.clone())).unwrap())
-    };
-    let signature = response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_update_blockchain_request(source_id:String, new:Block, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}", source_id, String::from_utf8(hash_block(new.clone())).unwrap());
-    sign(input, skey)
-}
-pub fn verify_update_blockchain_request(request:UpdateBlockchainRequest){
-    let source_id = request.source_id;
-    let new = request.new.unwrap();
-    let content = format!("{}{}", source_id, String::from_utf8(hash_block(new.clone())).unwrap());
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey= request.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}
-pub fn sign_update_blockchain_response(source_id:String, skey:Vec<u8>) -> Vec<u8>{
-    sign(source_id.clone(), skey.clone())
-}
-pub fn verify_update_blockchain_response(response:UpdateBlockchainResponse){
-    let source_id = response.source_id;
-    let signature = response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(source_id, signature, pkey)
+
+type ReplayWindow = VecDeque<(u64, u64)>;
+
+//Windows by signing key, the least recently used one forgotten once MAX_TRACKED_PEERS are kept
+#[derive(Debug,Default)]
+struct ReplayWindows{
+    windows: HashMap<Vec<u8>, (ReplayWindow, u64, u64)>, //With the stamp and time of their last use
+    used: BTreeMap<u64, Vec<u8>>,
+    stamp: u64,
+    floor: u64, //Newest timestamp forgotten with an evicted window
+}
+impl ReplayWindows{
+    //Drops the windows used longest ago once all their entries have aged out
+    fn prune(&mut self, now:u64){
+        while let Some((_, key)) = self.used.first_key_value(){
+            // an entry is kept until REPLAY_WINDOW_MS past its timestamp, at most REPLAY_WINDOW_MS past its use
+            if self.windows[key].2.saturating_add(2 * REPLAY_WINDOW_MS) >= now{ break }
+            let key = key.clone();
+            self.used.pop_first();
+            self.windows.remove(&key);
+        }
+    }
+    fn evict(&mut self){
+        if let Some((_, oldest)) = self.used.pop_first(){
+            if let Some((window, _, _)) = self.windows.remove(&oldest){
+                self.floor = window.iter().fold(self.floor, |floor, (timestamp, _)| floor.max(*timestamp));
+            }
+        }
+    }
+    fn check(&mut self, sign:&Signature, now:u64) -> Result<(), Rejection>{
+        self.prune(now);
+        let mut window = match self.windows.remove(&sign.pkey){
+            Some((window, used, _)) => { self.used.remove(&used); window }
+            // the key may have had its window evicted, so what it signed before the floor could be a replay
+            None if sign.timestamp <= self.floor => return Err(Rejection::Replayed),
+            None => {
+                if self.windows.len() >= MAX_TRACKED_PEERS{ self.evict() }
+                ReplayWindow::new()
+            }
+        };
+        window.retain(|(timestamp, _)| timestamp.saturating_add(REPLAY_WINDOW_MS) >= now);
+        let replayed = window.contains(&(sign.timestamp, sign.nonce));
+        if !replayed{ window.push_back((sign.timestamp, sign.nonce)) }
+        self.stamp += 1;
+        self.used.insert(self.stamp, sign.pkey.clone());
+        self.windows.insert(sign.pkey.clone(), (window, self.stamp, now));
+        if replayed{ return Err(Rejection::Replayed) }
+        Ok(())
+    }
 }
 
-/*pub fn sign_mine_request(source_id:String, prev:Block, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}", source_id, String::from_utf8(hash_block(prev)).unwrap());
-    sign(input, skey)
+//Remembers the (timestamp, nonce) pairs accepted from each sender within the replay window
+#[derive(Debug,Default)]
+pub struct ReplayCache{
+    seen: Mutex<ReplayWindows>,
+}
+impl ReplayCache{
+    pub fn check(&self, sign:&Signature) -> Result<(), Rejection>{
+        let now = now_millis();
+        // the timestamp is the sender's to choose, so nothing is added to it that could overflow
+        if now.abs_diff(sign.timestamp) > REPLAY_WINDOW_MS{
+            return Err(Rejection::Stale)
+        }
+        self.seen.lock().unwrap().check(sign, now)
+    }
+    pub fn tracked(&self) -> usize{
+        self.seen.lock().unwrap().windows.len()
+    }
 }
-pub fn verify_mine_request(request:MineRequest){
-    let content = format!("{}{}", request.source_id, String::from_utf8(hash_block(request.previous.unwrap())).unwrap());
-    let signature = request.sign.clone().unwrap().hash;
-    let pkey= request.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
+
+//Implements Signed for messages that carry their signature in a sign field, each under the RPC it belongs to
+macro_rules! signed{
+    ($($message:ty => $method:literal),* $(,)?) => {
+        $(impl Signed for $message{
+            const METHOD: &'static str = $method;
+            fn sign(&self) -> Option<Signature>{ self.sign.clone() }
+            fn set_sign(&mut self, sign:Option<Signature>){ self.sign = sign }
+        })*
+    };
 }
-pub fn sign_mine_response(source_id:String, new:Block, skey:Vec<u8>) -> Vec<u8>{
-    let input = format!("{}{}", source_id, String::from_utf8(hash_block(new)).unwrap());
-    sign(input, skey.clone())
+signed!{
+    JoinRequest => "/kademlia.Endpoint/Join",
+    JoinResponse => "/kademlia.Endpoint/Join:response",
+    PingRequest => "/kademlia.Endpoint/Ping",
+    PingResponse => "/kademlia.Endpoint/Ping:response",
+    FindNodeRequest => "/kademlia.Endpoint/FindNode",
+    FindNodeResponse => "/kademlia.Endpoint/FindNode:response",
+    UpdateRequest => "/kademlia.Endpoint/UpdateNode",
+    UpdateResponse => "/kademlia.Endpoint/UpdateNode:response",
+    NeighboursRequest => "/kademlia.Endpoint/GetNeighbours",
+    NeighboursResponse => "/kademlia.Endpoint/GetNeighbours:response",
+    RemoveRequest => "/kademlia.Endpoint/RemoveNode",
+    RemoveResponse => "/kademlia.Endpoint/RemoveNode:response",
+    TransactionRequest => "/kademlia.Endpoint/Transaction",
+    TransactionResponse => "/kademlia.Endpoint/Transaction:response",
+    ObtainTransactionsRequest => "/kademlia.Endpoint/ObtainTransactions",
+    ObtainTransactionsResponse => "/kademlia.Endpoint/ObtainTransactions:response",
+    RetrieveBlockchainRequest => "/kademlia.Endpoint/RetrieveBlockchain",
+    RetrieveBlockchainResponse => "/kademlia.Endpoint/RetrieveBlockchain:response",
+    UpdateBlockchainRequest => "/kademlia.Endpoint/UpdateBlockchain",
+    UpdateBlockchainResponse => "/kademlia.Endpoint/UpdateBlockchain:response",
+    CreateBidRequest => "/kademlia.Endpoint/CreateBid",
+    CreateBidResponse => "/kademlia.Endpoint/CreateBid:response",
+    BidValueRequest => "/kademlia.Endpoint/BidValue",
+    BidValueResponse => "/kademlia.Endpoint/BidValue:response",
+    AuctionsRequest => "/kademlia.Endpoint/Auctions",
+    AuctionsResponse => "/kademlia.Endpoint/Auctions:response",
+    CloseBidRequest => "/kademlia.Endpoint/CloseBid",
+    CloseBidResponse => "/kademlia.Endpoint/CloseBid:response",
+    BalanceRequest => "/kademlia.Endpoint/Balance",
+    BalanceResponse => "/kademlia.Endpoint/Balance:response",
+    TransactionStatusRequest => "/kademlia.Endpoint/TransactionStatus",
+    TransactionStatusResponse => "/kademlia.Endpoint/TransactionStatus:response",
+    ProofRequest => "/kademlia.Endpoint/Proof",
+    ProofResponse => "/kademlia.Endpoint/Proof:response",
+    SubscribeBlocksRequest => "/kademlia.Endpoint/SubscribeBlocks",
+    SubscribeTransactionsRequest => "/kademlia.Endpoint/SubscribeTransactions",
+    AbortRequest => "/kademlia.Miner/Abort",
+    AbortResponse => "/kademlia.Miner/Abort:response",
+    RoutingTableRequest => "/kademlia.Admin/RoutingTable",
+    ChainRequest => "/kademlia.Admin/Chain",
+    MempoolRequest => "/kademlia.Admin/Mempool",
+    PeersRequest => "/kademlia.Admin/Peers",
+    RefreshBucketsRequest => "/kademlia.Admin/RefreshBuckets",
+    BanPeerRequest => "/kademlia.Admin/BanPeer",
+    UnbanPeerRequest => "/kademlia.Admin/UnbanPeer",
+    ShutdownRequest => "/kademlia.Admin/Shutdown",
 }
-pub fn verify_mine_response(response:MineResponse){
-    let content = format!("{}{}", response.source_id, String::from_utf8(hash_block(response.new.unwrap())).unwrap());
-    let signature = response.sign.clone().unwrap().hash;
-    let pkey= response.sign.clone().unwrap().pkey;
-    verify(content, signature, pkey)
-}*/
\ No newline at end of file
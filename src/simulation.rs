@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::mpsc;
+use crate::transport::{Link, Listener, Transport};
+use crate::util::*;
+
+//In-memory network for reproducing ordering bugs. Every connection is a pair of pipes relayed chunk by chunk,
+//and each chunk is delayed, dropped or cut by a seeded RNG. Run it on a current_thread runtime with the tokio
+//clock paused (and util::seed_rng called with the same seed) and a failing seed replays the same run.
+
+const PIPE_SIZE: usize = 64 * 1024; //Bytes buffered on each side of a relayed connection
+const BACKLOG: usize = 128; //Connections waiting to be accepted per listener
+
+//Applied to every chunk that crosses the network
+#[derive(Clone,Debug)]
+pub struct Faults{
+    pub latency_ms: (u64, u64), //Delay drawn uniformly from this range
+    pub drop: f64, //Chance a chunk is lost, which breaks its connection like a lost TCP segment would break an RPC
+}
+impl Default for Faults{
+    fn default() -> Self {
+        Faults{ latency_ms: (1, 20), drop: 0.0 }
+    }
+}
+
+struct State{
+    rng: StdRng,
+    faults: Faults,
+    listeners: HashMap<SocketAddr, (String, mpsc::Sender<Link>)>,
+    addresses: HashMap<String, SocketAddr>, //First address each node listens on, reported as the source of its connections
+    groups: HashMap<String, usize>, //Nodes in different groups cannot reach each other, everyone is in group 0 unless partitioned
+    crashed: HashSet<String>,
+    trace: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct SimNetwork{
+    state: Arc<Mutex<State>>,
+}
+impl SimNetwork{
+    pub fn new(seed:u64, faults:Faults) -> SimNetwork{
+        let state = State{ rng: StdRng::seed_from_u64(seed), faults, listeners: HashMap::new(), addresses: HashMap::new(),
+            groups: HashMap::new(), crashed: HashSet::new(), trace: Vec::new() };
+        SimNetwork{ state: Arc::new(Mutex::new(state)) }
+    }
+    pub fn set_faults(&self, faults:Faults){
+        self.state.lock().unwrap().faults = faults;
+    }
+    //Splits the listed nodes into isolated groups, the nodes not listed stay together in another one
+    pub fn partition(&self, groups:&[Vec<String>]){
+        let mut state = self.state.lock().unwrap();
+        state.groups.clear();
+        for (index, group) in groups.iter().enumerate(){
+            for i in group{ state.groups.insert(i.clone(), index + 1); }
+        }
+        state.trace.push(format!("PARTITION {:?}", groups));
+    }
+    pub fn heal(&self){
+        let mut state = self.state.lock().unwrap();
+        state.groups.clear();
+        state.trace.push("HEAL".to_string());
+    }
+    //Nothing reaches a crashed node or leaves it and its connections break; its tasks keep running, unable to talk to anyone
+    pub fn crash(&self, node:&str){
+        let mut state = self.state.lock().unwrap();
+        state.crashed.insert(node.to_string());
+        state.trace.push(format!("CRASH {}", node));
+    }
+    pub fn recover(&self, node:&str){
+        let mut state = self.state.lock().unwrap();
+        state.crashed.remove(node);
+        state.trace.push(format!("RECOVER {}", node));
+    }
+    //Every connection, loss and fault event so far, in order
+    pub fn trace(&self) -> Vec<String>{
+        self.state.lock().unwrap().trace.clone()
+    }
+    //How long a chunk from one node to another takes, or None when it is lost
+    fn deliver(&self, from:&str, to:&str) -> Option<Duration>{
+        let mut state = self.state.lock().unwrap();
+        let group = |i:&str| state.groups.get(i).copied().unwrap_or(0);
+        if state.crashed.contains(from) || state.crashed.contains(to) || group(from) != group(to){
+            state.trace.push(format!("CUT {} -> {}", from, to));
+            return None
+        }
+        let faults = state.faults.clone();
+        if faults.drop > 0.0 && state.rng.gen_bool(faults.drop){
+            state.trace.push(format!("DROP {} -> {}", from, to));
+            return None
+        }
+        Some(Duration::from_millis(state.rng.gen_range(faults.latency_ms.0..=faults.latency_ms.1)))
+    }
+}
+
+async fn relay(network:SimNetwork, from:String, to:String, mut reader:ReadHalf<DuplexStream>, mut writer:WriteHalf<DuplexStream>){
+    let mut buffer = vec![0u8; PIPE_SIZE];
+    loop{
+        let n = match reader.read(&mut buffer).await{ Ok(0) | Err(_) => break, Ok(n) => n };
+        match network.deliver(&from, &to){
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => break,
+        }
+        if writer.write_all(&buffer[..n]).await.is_err(){ break }
+    }
+    let _ = writer.shutdown().await;
+}
+
+struct SimListener(mpsc::Receiver<Link>);
+#[tonic::async_trait]
+impl Listener for SimListener{
+    async fn accept(&mut self) -> io::Result<Link>{
+        self.0.recv().await.ok_or(io::Error::new(io::ErrorKind::NotConnected, "SIMULATED LISTENER CLOSED"))
+    }
+}
+
+#[tonic::async_trait]
+impl Transport for SimNetwork{
+    async fn dial(&self, source:&str, host:&str, port:u16) -> io::Result<Link>{
+        let addr:SocketAddr = format!("{}:{}", format_host(host), port).parse().map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let (target, listener, source_addr) = {
+            let mut state = self.state.lock().unwrap();
+            // traced by node ID, ports are picked by the OS and change from run to run
+            let (target, listener) = match state.listeners.get(&addr).cloned(){
+                Some(listener) => listener,
+                None => {
+                    state.trace.push(format!("REFUSED {}", source));
+                    return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+                }
+            };
+            state.trace.push(format!("DIAL {} -> {}", source, target));
+            (target, listener, state.addresses.get(source).copied())
+        };
+        // the connection attempt itself can be lost or cut
+        if self.deliver(source, &target).is_none(){ return Err(io::Error::from(io::ErrorKind::ConnectionRefused)) }
+        let (client, client_relay) = tokio::io::duplex(PIPE_SIZE);
+        let (server, server_relay) = tokio::io::duplex(PIPE_SIZE);
+        let (client_reader, client_writer) = tokio::io::split(client_relay);
+        let (server_reader, server_writer) = tokio::io::split(server_relay);
+        tokio::spawn(relay(self.clone(), source.to_string(), target.clone(), client_reader, server_writer));
+        tokio::spawn(relay(self.clone(), target, source.to_string(), server_reader, client_writer));
+        listener.send(Link::new(server, Some(addr), source_addr)).await.map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+        Ok(Link::new(client, source_addr, Some(addr)))
+    }
+    async fn listen(&self, node:&str, addr:SocketAddr) -> io::Result<Box<dyn Listener>>{
+        let (sender, receiver) = mpsc::channel(BACKLOG);
+        let mut state = self.state.lock().unwrap();
+        state.listeners.insert(addr, (node.to_string(), sender));
+        state.addresses.entry(node.to_string()).or_insert(addr);
+        Ok(Box::new(SimListener(receiver)))
+    }
+}
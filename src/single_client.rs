@@ -1,60 +1,31 @@
-use endpoint::*;
 use util::*;
-use rand::Rng;
 use requests::*;
-use std::error::Error;
-use std::fmt::format;
 use std::io;
-use core::net::SocketAddr;
 use std::time::Duration;
-use tokio::task;
-use tokio::signal;
-use tonic::{Response, Status};
-use tonic::transport::{Endpoint, Server};
-use node::*;
-use proto::miner_server::*;
-use crate::proto::{NodeInfo, BucketNode, KBucket, Node, JoinResponse};
-use crate::proto::endpoint_server::EndpointServer;
-use crate::signatures::*;
 use tokio_util::sync::CancellationToken;
-use blockchain::*;
 use nodes_init::*;
-mod endpoint;
-mod util;
-mod node;
-mod proto {
-    tonic::include_proto!("kademlia");
-}
-mod blockchain;
-mod requests;
-mod signatures;
-mod nodes_init;
+use ssd::{util, requests, nodes_init, keystore, storage, bootstrap, config, metrics, admin, logging};
 
 fn operations()->Vec<String>{
-    let mut operations = Vec::new();
-    operations.push("Show Neighbours".to_string());
-    operations.push("Make Transaction".to_string());
-    operations.push("List My Transactions".to_string());
-    operations.push("Start New Bid".to_string());
-    operations.push("Participate on Existing Bid".to_string());
-    operations.push("List Participating Bids".to_string());
-    operations.push("List All Bids".to_string());
-    operations
+    ["Show Neighbours", "Make Transaction", "List My Transactions", "Start New Bid", "Participate on Existing Bid", "List Participating Bids", "List All Bids"]
+        .iter().map(|i| i.to_string()).collect()
 }
-async fn menu(){
+async fn menu(config:std::sync::Arc<config::Config>){
     println!("Initialize Miner Node (1) or Client Node (2)? ");
     let opt=parse_input();
     let ops = operations();
-    let client;
+    let passphrase = read_passphrase("Keystore passphrase? ");
+    let identity = keystore::Identity::load_or_create(&format!("{}/node.key", KEYSTORE_DIR), &passphrase, config.dht.id_size).expect("FAILURE LOADING IDENTITY");
+    let store = Box::new(storage::DiskStore::open(&format!("{}/node", DATA_DIR)).expect("FAILURE OPENING STORAGE"));
     if opt==1{
-        client = create_client(None, true).await.expect("FAILURE CREATING CLIENT NODE").unwrap();
+        let client = create_client(config.clone(), None, true, identity, store, Some(format!("{}/node/routes.bin", DATA_DIR))).await.expect("FAILURE CREATING CLIENT NODE").unwrap();
         println!("Node Started -> Id: {}", client.clone().info.unwrap().id);
         loop {
             println!("What to do? ");
             for i in 0..ops.len(){println!("\t{}({i})", ops.get(i).unwrap())}
             let op_n = parse_input();
             if op_n==0{
-                let n = neighbours_request(client.clone(), format_url(client.clone().info.unwrap().ip, client.clone().info.unwrap().port.to_string())).await;
+                let n = neighbours_request(client.clone(), client.clone().info.unwrap()).await;
                 for i in n{
                     println!("\t{}", i.id)
                 }
@@ -66,21 +37,17 @@ async fn menu(){
                 let mut destination = String::new();
                 io::stdin().read_line(&mut destination).expect("Failed to read line");
                 destination = destination.trim().to_string();
-                let n = neighbours_request(client.clone(), format_url(client.clone().info.unwrap().ip, client.clone().info.unwrap().port.to_string())).await;
+                let n = neighbours_request(client.clone(), client.clone().info.unwrap()).await;
+                let nonce = new_nonce();
                 for i in n{
-                    let url = format_url(i.ip, i.port.to_string());
-                    transaction_request(client.clone(), client.info.clone().unwrap().id, val, destination.clone(), url).await;
+                    transaction_request(client.clone(), client.info.clone().unwrap().id, val, destination.clone(), nonce, i).await;
                 }
             }
-            else if op_n==2 {  }
-            else if op_n==3 {  }
-            else if op_n==4 {  }
-            else if op_n==5 {  }
-            else if op_n==6 {  }
+            // the other operations are not implemented yet
         }
     }
     else if opt==2 {
-        client = create_client(None, false).await.expect("FAILURE CREATING CLIENT NODE").unwrap();
+        create_client(config.clone(), None, false, identity, store, Some(format!("{}/node/routes.bin", DATA_DIR))).await.expect("FAILURE CREATING CLIENT NODE").unwrap();
         println!("What to do? ");
         for i in 0..ops.len(){println!("\t{}({i})", ops.get(i).unwrap())}
     }
@@ -89,6 +56,11 @@ async fn menu(){
 
 #[tokio::main]
 async fn main(){
+    let config = std::sync::Arc::new(config::Config::from_args());
+    logging::init(&config.log);
+    bootstrap::configure(config.bootstrap_peers().unwrap()).expect("FAILURE CONFIGURING BOOTSTRAPS");
+    metrics::expose(&config.metrics).await;
+    admin::expose(&config.admin).await;
     let token = CancellationToken::new();
     let cloned_token = token.clone();
     tokio::select! {
@@ -96,14 +68,13 @@ async fn main(){
             println!("CLOSING");
         }
         _ = tokio::time::sleep(Duration::from_secs(0)) => {tokio::spawn(async move{
-            loop{menu().await;}
+            loop{menu(config.clone()).await;}
         });}
     }
     let test = tokio::spawn(async move {
-        match signal::ctrl_c().await {
-            Ok(()) => {token.cancel();},
-            Err(err) => { eprintln!("Unable to listen for shutdown signal: {}", err); },
-        }
+        shutdown_requested().await;
+        token.cancel();
     });
     tokio::try_join!(test).expect("FAILURE INITIALIZING CLIENTS");
+    shutdown().await;
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use openssl::sha::sha256;
+use prost::Message;
+use tracing::{info, warn};
+use crate::blockchain::{hash_block, parse_transaction, transfer_of, Transaction};
+use crate::signatures::verify_transfer;
+use crate::config::ChainConfig;
+use crate::proto::{Account, BanList, Bid, Block, BlockBody, ChainState, ClosedAuctions, RoutingSnapshot};
+use crate::util::*;
+
+//Where a node keeps its chain. Tests and throwaway nodes use MemoryStore, long-lived nodes DiskStore.
+pub trait BlockStore: Debug + Send + Sync{
+    fn append(&mut self, block:&Block) -> io::Result<()>;
+    fn blocks(&self) -> Vec<Block>;
+    fn get(&self, hash:&[u8]) -> Option<Block>;
+    fn height(&self) -> usize;
+    fn state(&self) -> ChainState;
+    fn save_state(&mut self, state:ChainState) -> io::Result<()>;
+    //Bodies are only kept by full nodes, light clients hold the headers alone
+    fn save_body(&mut self, hash:&[u8], transactions:&[String]) -> io::Result<()>;
+    fn body(&self, hash:&[u8]) -> Option<Vec<String>>;
+    //Auctions are held by one node and never mined, so the ones it closed are kept apart from the state derived from the chain
+    fn closed_auctions(&self) -> Vec<Bid>;
+    fn close_auction(&mut self, bid:Bid) -> io::Result<()>;
+    //Block holding the transaction, with its body, searched from the tip down
+    fn find_transaction(&self, transaction:&str) -> Option<(Block, Vec<String>)>{
+        self.blocks().into_iter().rev().find_map(|i| {
+            let body = self.body(&hash_block(i.clone()))?;
+            body.iter().any(|k| transfer_of(k) == transfer_of(transaction)).then_some((i, body))
+        })
+    }
+}
+impl Default for Box<dyn BlockStore>{
+    fn default() -> Self {
+        Box::new(MemoryStore::default())
+    }
+}
+
+impl ChainState{
+    //Balances every chain starts from
+    pub fn genesis(chain:&ChainConfig) -> ChainState{
+        let mut state = ChainState::default();
+        for (id, value) in &chain.allocations{ state.credit(id.clone(), *value as i64) }
+        state
+    }
+    //Moves the balances by the transfers in the block body, the miner's reward first, or fails leaving the state as it was
+    //when one is not signed by its sender, overdraws it or repeats a nonce it already spent, or the reward is not the one the chain pays. Light clients pass no body, only the height and tip move.
+    pub fn apply_block(&mut self, block:&Block, transactions:&[String], reward:u32) -> Result<(), String>{
+        let mut next = self.clone();
+        for (index, i) in transactions.iter().enumerate(){
+            let Transaction{ sender, value, destination, nonce, key, sign } = parse_transaction(i).ok_or_else(|| format!("MALFORMED TRANSACTION {}", i))?;
+            if sender == COINBASE{
+                if index > 0 || value > reward{ return Err(format!("INVALID REWARD {}", i)) }
+                next.credit(destination, value as i64);
+                continue
+            }
+            if !owns_id(&sender, &key) || !verify_transfer(transfer_of(i), &sign, &key){ return Err(format!("TRANSFER NOT SIGNED BY {}", sender)) }
+            if next.balance(&sender) < value as i64{ return Err(format!("OVERDRAFT BY {}", sender)) }
+            if next.is_spent(&sender, nonce){ return Err(format!("REPLAYED TRANSACTION {}", i)) }
+            next.account(sender.clone()).spent.push(nonce);
+            next.apply_transaction(sender, value, destination);
+        }
+        next.height += 1;
+        next.tip = hash_block(block.clone());
+        *self = next;
+        Ok(())
+    }
+    pub fn apply_transaction(&mut self, sender:String, value:u32, destination:String){
+        self.credit(sender, -(value as i64));
+        self.credit(destination, value as i64);
+    }
+    fn account(&mut self, id:String) -> &mut Account{
+        match self.accounts.iter().position(|i| i.id == id){
+            Some(index) => &mut self.accounts[index],
+            None => { self.accounts.push(Account{ id, ..Default::default() }); self.accounts.last_mut().unwrap() }
+        }
+    }
+    fn credit(&mut self, id:String, value:i64){
+        self.account(id).balance += value
+    }
+    //Whether a transfer from the sender with this nonce is already on chain
+    pub fn is_spent(&self, sender:&str, nonce:u64) -> bool{
+        self.accounts.iter().find(|i| i.id == sender).is_some_and(|i| i.spent.contains(&nonce))
+    }
+    pub fn balance(&self, id:&str) -> i64{
+        self.accounts.iter().find(|i| i.id == id).map(|i| i.balance).unwrap_or(0)
+    }
+}
+
+#[derive(Debug,Default)]
+pub struct MemoryStore{
+    blocks: Vec<Block>,
+    state: ChainState,
+    bodies: HashMap<Vec<u8>, Vec<String>>,
+    auctions: Vec<Bid>,
+}
+impl BlockStore for MemoryStore{
+    fn append(&mut self, block:&Block) -> io::Result<()>{
+        self.blocks.push(block.clone());
+        Ok(())
+    }
+    fn blocks(&self) -> Vec<Block>{ self.blocks.clone() }
+    fn get(&self, hash:&[u8]) -> Option<Block>{
+        self.blocks.iter().find(|i| hash_block((*i).clone()) == hash).cloned()
+    }
+    fn height(&self) -> usize{ self.blocks.len() }
+    fn state(&self) -> ChainState{ self.state.clone() }
+    fn save_state(&mut self, state:ChainState) -> io::Result<()>{
+        self.state = state;
+        Ok(())
+    }
+    fn save_body(&mut self, hash:&[u8], transactions:&[String]) -> io::Result<()>{
+        self.bodies.insert(hash.to_vec(), transactions.to_vec());
+        Ok(())
+    }
+    fn body(&self, hash:&[u8]) -> Option<Vec<String>>{ self.bodies.get(hash).cloned() }
+    fn closed_auctions(&self) -> Vec<Bid>{ self.auctions.clone() }
+    fn close_auction(&mut self, bid:Bid) -> io::Result<()>{
+        self.auctions.push(bid);
+        Ok(())
+    }
+}
+
+//Append-only log split in segments of at most SEGMENT_SIZE bytes. Each record is
+//  u32be(length) | first 4 bytes of sha256(block) | protobuf encoded block
+//so a write torn by a crash is detected on the next start and cut off. Block bodies go to bodies/<hash>.bin, one file each.
+#[derive(Debug)]
+pub struct DiskStore{
+    dir: PathBuf,
+    segment: usize,
+    blocks: Vec<Block>,
+    index: HashMap<Vec<u8>, (usize, u64)>,
+    state: ChainState,
+    auctions: Vec<Bid>,
+}
+const RECORD_HEADER: u64 = 8;
+
+impl DiskStore{
+    fn segment_path(&self, segment:usize) -> PathBuf{
+        self.dir.join(format!("segment-{:05}.log", segment))
+    }
+    fn state_path(&self) -> PathBuf{
+        self.dir.join("state.bin")
+    }
+    fn auctions_path(&self) -> PathBuf{
+        self.dir.join("auctions.bin")
+    }
+    fn body_path(&self, hash:&[u8]) -> PathBuf{
+        self.dir.join("bodies").join(format!("{}.bin", String::from_utf8_lossy(hash)))
+    }
+    pub fn open(dir:&str) -> io::Result<DiskStore>{
+        fs::create_dir_all(dir)?;
+        let mut store = DiskStore{ dir:PathBuf::from(dir), segment:0, blocks:Vec::new(), index:HashMap::new(), state:ChainState::default(), auctions:Vec::new() };
+        store.recover()?;
+        if let Ok(state) = fs::read(store.state_path()){
+            store.state = ChainState::decode(state.as_slice()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        if let Ok(auctions) = fs::read(store.auctions_path()){
+            store.auctions = ClosedAuctions::decode(auctions.as_slice()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?.bids;
+        }
+        info!(blocks = store.blocks.len(), dir, "LOADED BLOCKS");
+        Ok(store)
+    }
+    //Replays every segment into memory, truncating at the first incomplete or corrupted record
+    fn recover(&mut self) -> io::Result<()>{
+        let mut segment = 0;
+        while self.segment_path(segment).exists(){
+            let path = self.segment_path(segment);
+            let mut file = File::open(&path)?;
+            let len = file.metadata()?.len();
+            let mut offset = 0u64;
+            while offset < len{
+                match Self::read_record(&mut file, offset, len){
+                    Some((block, size)) => {
+                        self.index.insert(hash_block(block.clone()), (segment, offset));
+                        self.blocks.push(block);
+                        offset += RECORD_HEADER + size;
+                    }
+                    None => {
+                        warn!(path = %path.display(), offset, "TRUNCATING TORN WRITE");
+                        OpenOptions::new().write(true).open(&path)?.set_len(offset)?;
+                        let mut later = segment + 1;
+                        while self.segment_path(later).exists(){
+                            fs::remove_file(self.segment_path(later))?;
+                            later += 1;
+                        }
+                        break
+                    }
+                }
+            }
+            self.segment = segment;
+            segment += 1;
+        }
+        Ok(())
+    }
+    //The block and the size its header gives, which a re-encoding of the block need not match
+    fn read_record(file:&mut File, offset:u64, len:u64) -> Option<(Block, u64)>{
+        if offset + RECORD_HEADER > len{ return None }
+        let mut header = [0u8; RECORD_HEADER as usize];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut header).ok()?;
+        let size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        if offset + RECORD_HEADER + size > len{ return None }
+        let mut body = vec![0u8; size as usize];
+        file.read_exact(&mut body).ok()?;
+        if sha256(&body)[0..4] != header[4..8]{ return None }
+        Some((Block::decode(body.as_slice()).ok()?, size))
+    }
+}
+impl BlockStore for DiskStore{
+    fn append(&mut self, block:&Block) -> io::Result<()>{
+        let body = block.encode_to_vec();
+        let mut path = self.segment_path(self.segment);
+        if path.exists() && fs::metadata(&path)?.len() + RECORD_HEADER + body.len() as u64 > SEGMENT_SIZE{
+            self.segment += 1;
+            path = self.segment_path(self.segment);
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let offset = file.metadata()?.len();
+        let mut record = (body.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(&sha256(&body)[0..4]);
+        record.extend_from_slice(&body);
+        file.write_all(&record)?;
+        file.sync_data()?;
+        self.index.insert(hash_block(block.clone()), (self.segment, offset));
+        self.blocks.push(block.clone());
+        Ok(())
+    }
+    fn blocks(&self) -> Vec<Block>{ self.blocks.clone() }
+    fn get(&self, hash:&[u8]) -> Option<Block>{
+        let (segment, offset) = self.index.get(hash)?;
+        let mut file = File::open(self.segment_path(*segment)).ok()?;
+        let len = file.metadata().ok()?.len();
+        Self::read_record(&mut file, *offset, len).map(|(block, _)| block)
+    }
+    fn height(&self) -> usize{ self.blocks.len() }
+    fn state(&self) -> ChainState{ self.state.clone() }
+    //Written to a temporary file and renamed over the old snapshot, so a crash leaves one or the other
+    fn save_state(&mut self, state:ChainState) -> io::Result<()>{
+        let tmp = self.dir.join("state.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&state.encode_to_vec())?;
+        file.sync_all()?;
+        fs::rename(tmp, self.state_path())?;
+        self.state = state;
+        Ok(())
+    }
+    fn save_body(&mut self, hash:&[u8], transactions:&[String]) -> io::Result<()>{
+        fs::create_dir_all(self.dir.join("bodies"))?;
+        replace_file(&self.body_path(hash).to_string_lossy(), &BlockBody{ transactions: transactions.to_vec() })
+    }
+    fn body(&self, hash:&[u8]) -> Option<Vec<String>>{
+        Some(BlockBody::decode(fs::read(self.body_path(hash)).ok()?.as_slice()).ok()?.transactions)
+    }
+    fn closed_auctions(&self) -> Vec<Bid>{ self.auctions.clone() }
+    fn close_auction(&mut self, bid:Bid) -> io::Result<()>{
+        let mut bids = self.auctions.clone();
+        bids.push(bid);
+        replace_file(&self.auctions_path().to_string_lossy(), &ClosedAuctions{ bids: bids.clone() })?;
+        self.auctions = bids;
+        Ok(())
+    }
+}
+
+//Brings the state snapshot up to the block log, which is written first: a crash in between leaves blocks the snapshot does not count.
+//A snapshot that does not match the log at all is rebuilt from genesis.
+pub fn reconcile(store:&mut dyn BlockStore, chain:&ChainConfig) -> io::Result<()>{
+    let blocks = store.blocks();
+    let mut state = store.state();
+    let covered = state.height as usize;
+    let matches = covered <= blocks.len() && (covered == 0 || state.tip == hash_block(blocks[covered - 1].clone()));
+    let start = match matches && state != ChainState::default(){
+        true => covered,
+        false => { state = ChainState::genesis(chain); 0 }
+    };
+    if !matches{ warn!(snapshot = covered, blocks = blocks.len(), "CHAIN STATE DOES NOT MATCH THE BLOCK LOG, REPLAYING") }
+    for i in &blocks[start..]{
+        let body = store.body(&hash_block(i.clone())).unwrap_or_default();
+        state.apply_block(i, &body, chain.reward).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if state != store.state(){
+        info!(replayed = blocks.len() - start, "RECONCILED CHAIN STATE");
+        store.save_state(state)?;
+    }
+    Ok(())
+}
+
+//Written to a temporary file and renamed over the old one
+fn replace_file(path:&str, message:&impl Message) -> io::Result<()>{
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(&message.encode_to_vec())?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
+pub fn save_routes(path:&str, snapshot:RoutingSnapshot) -> io::Result<()>{
+    replace_file(path, &snapshot)
+}
+pub fn load_routes(path:&str) -> Option<RoutingSnapshot>{
+    RoutingSnapshot::decode(fs::read(path).ok()?.as_slice()).ok()
+}
+pub fn save_bans(path:&str, bans:BanList) -> io::Result<()>{
+    replace_file(path, &bans)
+}
+pub fn load_bans(path:&str) -> Option<BanList>{
+    BanList::decode(fs::read(path).ok()?.as_slice()).ok()
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tracing::{debug, warn};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey};
+use openssl::x509::{X509, X509NameBuilder};
+use tokio::sync::mpsc;
+use tokio_rustls::{client, TlsAcceptor, TlsConnector};
+use tokio_rustls::rustls::{CertificateError, ClientConfig, DigitallySignedStruct, DistinguishedName, ServerConfig, SignatureScheme};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use tokio_rustls::rustls::crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::http::Uri;
+use tonic::transport::server::{Connected, Router};
+use crate::proto::Node;
+use crate::util::*;
+use crate::transport::{transport, Link, Transport};
+
+//Node links run TLS 1.2/1.3 with self-signed certificates over the node's identity key. Trust does not come from a CA:
+//clients pin the key of the NodeInfo they dial and servers compare the client key with the one signing each request.
+//URLs keep the http scheme because the handshake is done here, below tonic.
+
+//Public key PEM of a DER certificate, in the same encoding as NodeInfo.pkey
+pub fn certificate_key(der:&[u8]) -> Option<Vec<u8>>{
+    X509::from_der(der).ok()?.public_key().ok()?.public_key_to_pem().ok()
+}
+
+type Certificate = (CertificateDer<'static>, Vec<u8>);
+fn certificates() -> &'static Mutex<HashMap<Vec<u8>, Certificate>>{
+    static CERTIFICATES: OnceLock<Mutex<HashMap<Vec<u8>, Certificate>>> = OnceLock::new();
+    CERTIFICATES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+//Self-signed certificate for the node's identity key, generated once per key
+fn certificate(node:&Node) -> (CertificateDer<'static>, PrivateKeyDer<'static>){
+    let info = node.info.clone().unwrap();
+    let id = info.id;
+    let mut cache = certificates().lock().unwrap();
+    let (cert, key) = cache.entry(info.pkey).or_insert_with(|| {
+        let key = PKey::private_key_from_pem(&node.skey).expect("INVALID NODE KEY");
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, &id).unwrap();
+        let name = name.build();
+        let mut serial = BigNum::new().unwrap();
+        serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&serial.to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(CERTIFICATE_DAYS).unwrap()).unwrap();
+        // Ed25519 signs the certificate as a whole, without a separate digest
+        let digest = if key.id() == Id::ED25519{ MessageDigest::null() } else { MessageDigest::sha256() };
+        builder.sign(&key, digest).unwrap();
+        (CertificateDer::from(builder.build().to_der().unwrap()), key.private_key_to_pkcs8().unwrap())
+    }).clone();
+    (cert, PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)))
+}
+
+//Accepts any well formed certificate, or with a fingerprint only one carrying that key.
+//Possession of the key is still proven by the handshake signature checks below.
+#[derive(Debug)]
+struct PeerVerifier{
+    expected: Option<String>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+impl PeerVerifier{
+    fn new(expected:Option<String>) -> PeerVerifier{
+        PeerVerifier{ expected, algorithms: tokio_rustls::rustls::crypto::ring::default_provider().signature_verification_algorithms }
+    }
+    fn check(&self, end_entity:&CertificateDer<'_>) -> Result<(), tokio_rustls::rustls::Error>{
+        let key = certificate_key(end_entity.as_ref()).ok_or(tokio_rustls::rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        match &self.expected{
+            Some(fingerprint) if *fingerprint != key_fingerprint(&key) => Err(tokio_rustls::rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure)),
+            _ => Ok(()),
+        }
+    }
+}
+impl ServerCertVerifier for PeerVerifier{
+    fn verify_server_cert(&self, end_entity:&CertificateDer<'_>, _intermediates:&[CertificateDer<'_>], _server_name:&ServerName<'_>, _ocsp_response:&[u8], _now:UnixTime) -> Result<ServerCertVerified, tokio_rustls::rustls::Error>{
+        self.check(end_entity).map(|_| ServerCertVerified::assertion())
+    }
+    fn verify_tls12_signature(&self, message:&[u8], cert:&CertificateDer<'_>, dss:&DigitallySignedStruct) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error>{
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+    fn verify_tls13_signature(&self, message:&[u8], cert:&CertificateDer<'_>, dss:&DigitallySignedStruct) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error>{
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme>{
+        self.algorithms.supported_schemes()
+    }
+}
+impl ClientCertVerifier for PeerVerifier{
+    fn root_hint_subjects(&self) -> &[DistinguishedName]{
+        &[]
+    }
+    fn verify_client_cert(&self, end_entity:&CertificateDer<'_>, _intermediates:&[CertificateDer<'_>], _now:UnixTime) -> Result<ClientCertVerified, tokio_rustls::rustls::Error>{
+        self.check(end_entity).map(|_| ClientCertVerified::assertion())
+    }
+    fn verify_tls12_signature(&self, message:&[u8], cert:&CertificateDer<'_>, dss:&DigitallySignedStruct) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error>{
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+    fn verify_tls13_signature(&self, message:&[u8], cert:&CertificateDer<'_>, dss:&DigitallySignedStruct) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error>{
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme>{
+        self.algorithms.supported_schemes()
+    }
+}
+
+//Dials peers over TLS as `source`, only completing the handshake with the key whose fingerprint is expected
+#[derive(Clone)]
+pub struct Connector{
+    config: Arc<ClientConfig>,
+    source: String,
+    transport: Arc<dyn Transport>,
+}
+impl Connector{
+    pub fn new(source:&Node, expected:Option<String>) -> Connector{
+        let id = source.info.clone().unwrap().id;
+        let (cert, key) = certificate(source);
+        let mut config = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PeerVerifier::new(expected)))
+            .with_client_auth_cert(vec![cert], key)
+            .expect("FAILURE CONFIGURING TLS CLIENT");
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        Connector{ config: Arc::new(config), transport: transport(&id), source: id }
+    }
+}
+impl tower_service::Service<Uri> for Connector{
+    type Response = client::TlsStream<Link>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx:&mut Context<'_>) -> Poll<Result<(), Self::Error>>{
+        Poll::Ready(Ok(()))
+    }
+    fn call(&mut self, uri:Uri) -> Self::Future{
+        let connector = TlsConnector::from(self.config.clone());
+        let (transport, source) = (self.transport.clone(), self.source.clone());
+        Box::pin(async move {
+            let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_string();
+            let stream = transport.dial(&source, &host, uri.port_u16().unwrap_or(80)).await?;
+            let name = ServerName::try_from(host).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            connector.connect(name, stream).await
+        })
+    }
+}
+
+//Serves the router on addr, handshaking every connection with the node's certificate and requiring one from the client
+pub async fn serve(router:Router, addr:SocketAddr, node:&Node) -> Result<(), Box<dyn Error>>{
+    let (cert, key) = certificate(node);
+    let mut config = ServerConfig::builder()
+        .with_client_cert_verifier(Arc::new(PeerVerifier::new(None)))
+        .with_single_cert(vec![cert], key)?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let mut listener = transport(&node.info.clone().unwrap().id).listen(&node.info.clone().unwrap().id, addr).await?;
+    let (sender, receiver) = mpsc::channel(128);
+    tokio::spawn(async move {
+        loop{
+            let stream = match listener.accept().await{
+                Ok(stream) => stream,
+                Err(e) if e.kind() == io::ErrorKind::NotConnected => break,
+                // running out of file descriptors and the like pass, the listener is still there
+                Err(e) => {
+                    warn!(%addr, error = %e, "FAILURE ACCEPTING CONNECTION");
+                    tokio::time::sleep(Duration::from_millis(ACCEPT_BACKOFF_MS)).await;
+                    continue
+                }
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+            let peer = stream.connect_info().remote_addr;
+            // handshake off the accept loop so a slow peer cannot hold up the others
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await{
+                    Ok(stream) => { let _ = sender.send(Ok::<_, io::Error>(stream)).await; }
+                    Err(e) => debug!(peer = ?peer, error = %e, "TLS HANDSHAKE FAILED"),
+                }
+            });
+        }
+    });
+    router.serve_with_incoming(ReceiverStream::new(receiver)).await?;
+    Ok(())
+}
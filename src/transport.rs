@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use crate::util::*;
+
+//Byte streams between nodes, below TLS. Nodes use TCP unless another transport is assigned to their ID,
+//which is how the simulator runs whole networks in memory next to real ones.
+
+pub trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+//A connection and the addresses of both ends, reported by tonic as the remote address of requests
+pub struct Link{
+    io: Box<dyn Io>,
+    local: Option<SocketAddr>,
+    remote: Option<SocketAddr>,
+}
+impl Link{
+    pub fn new(io:impl Io + 'static, local:Option<SocketAddr>, remote:Option<SocketAddr>) -> Link{
+        Link{ io: Box::new(io), local, remote }
+    }
+}
+impl AsyncRead for Link{
+    fn poll_read(mut self: Pin<&mut Self>, cx:&mut Context<'_>, buf:&mut ReadBuf<'_>) -> Poll<io::Result<()>>{
+        Pin::new(&mut *self.io).poll_read(cx, buf)
+    }
+}
+impl AsyncWrite for Link{
+    fn poll_write(mut self: Pin<&mut Self>, cx:&mut Context<'_>, buf:&[u8]) -> Poll<io::Result<usize>>{
+        Pin::new(&mut *self.io).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<io::Result<()>>{
+        Pin::new(&mut *self.io).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<io::Result<()>>{
+        Pin::new(&mut *self.io).poll_shutdown(cx)
+    }
+}
+impl Connected for Link{
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo{
+        TcpConnectInfo{ local_addr: self.local, remote_addr: self.remote }
+    }
+}
+
+#[tonic::async_trait]
+pub trait Listener: Send{
+    //Fails with NotConnected once the listener is closed for good, any other error may pass
+    async fn accept(&mut self) -> io::Result<Link>;
+}
+#[tonic::async_trait]
+pub trait Transport: Send + Sync{
+    //Opens a connection from the node with ID source to host:port
+    async fn dial(&self, source:&str, host:&str, port:u16) -> io::Result<Link>;
+    //Accepts connections for the node with ID node on addr
+    async fn listen(&self, node:&str, addr:SocketAddr) -> io::Result<Box<dyn Listener>>;
+}
+
+pub struct Tcp;
+struct TcpAcceptor(TcpListener);
+#[tonic::async_trait]
+impl Listener for TcpAcceptor{
+    async fn accept(&mut self) -> io::Result<Link>{
+        let (stream, peer) = self.0.accept().await?;
+        let local = stream.local_addr().ok();
+        Ok(Link::new(stream, local, Some(peer)))
+    }
+}
+#[tonic::async_trait]
+impl Transport for Tcp{
+    async fn dial(&self, _source:&str, host:&str, port:u16) -> io::Result<Link>{
+        let stream = TcpStream::connect((host, port)).await?;
+        let (local, remote) = (stream.local_addr().ok(), stream.peer_addr().ok());
+        Ok(Link::new(stream, local, remote))
+    }
+    async fn listen(&self, _node:&str, addr:SocketAddr) -> io::Result<Box<dyn Listener>>{
+        let socket = bind(addr.to_string()).map_err(|e| io::Error::other(e.to_string()))?.ok_or(io::Error::other("FAILURE BINDING SOCKET"))?;
+        Ok(Box::new(TcpAcceptor(socket.listen(1024)?)))
+    }
+}
+
+fn transports() -> &'static RwLock<HashMap<String, Arc<dyn Transport>>>{
+    static TRANSPORTS: OnceLock<RwLock<HashMap<String, Arc<dyn Transport>>>> = OnceLock::new();
+    TRANSPORTS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+//Must happen before the node with this ID is started
+pub fn assign(node:&str, transport:Arc<dyn Transport>){
+    transports().write().unwrap().insert(node.to_string(), transport);
+}
+pub fn transport(node:&str) -> Arc<dyn Transport>{
+    transports().read().unwrap().get(node).cloned().unwrap_or_else(|| Arc::new(Tcp))
+}
@@ -1,24 +1,91 @@
+use std::cell::RefCell;
 use std::error::Error;
 use std::io;
-use std::net::{SocketAddr};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::pkey::PKey;
 use openssl::rsa::Rsa;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use tokio::net::TcpSocket;
-use crate::proto::NodeInfo;
+use crate::proto::{KeyAlgorithm, NodeInfo};
 
-pub const ID_SIZE: usize = 10; //Size of the NODE_ID (truncate hash output, see node.rs)
-pub const N_BUCKETS: usize = ID_SIZE * 4; //For each bit of the NODE_ID, add one bucket (if ID_SIZE is in bytes, bytes*8 = bits)
-pub const K_SIZE: usize = 5; //How many nodes per bucket
-pub const BOOTSTRAP_PORTS: &'static[&str] = &["55555", "55556", "55557"]; // Static ports for server initialization
-pub const REFRESH_PERIOD: i32 = 5;
-pub const PREFIX_LENGTH: i32= 2;
+pub const BOOTSTRAP_PORTS: &[&str] = &["55555", "55556", "55557"]; // Local bootstrap ports used when no bootstraps are configured
+pub const BOOTSTRAP_FILE: &str = "bootstraps.conf"; //One "host:port[@fingerprint]" bootstrap per line
+pub const BOOTSTRAPS_ENV: &str = "BLOCC_BOOTSTRAPS"; //When set, comma separated bootstraps used instead of the configuration file
+pub const COINBASE: &str = "coinbase"; //Sender of mining rewards
 pub const GENESIS: &str = "00f151242e0010e58cde0d6644d9db53a8552f0e2d26628c9a72199005b5a76e";
-pub const TRANSACTION_NUMBER: i32 = 10;
+pub const ANNOUNCEMENT_CAPACITY: usize = 256; //How many announcements a slow subscriber may lag behind before dropping them
+pub const CONNECT_TIMEOUT_MS: u64 = 1000; //Deadline for dialing a peer
+pub const ACCEPT_BACKOFF_MS: u64 = 100; //Pause after a listener fails to accept a connection, before it tries again
+pub const RPC_TIMEOUT_MS: u64 = 5000; //Deadline for a single unary RPC
+pub const DIAL_BACK_TIMEOUT_MS: u64 = 500; //Deadline for the one ping a bootstrap sends back to a joining node
+pub const MAX_RETRIES: u32 = 3; //Retries after the first attempt of a failed RPC
+pub const BACKOFF_BASE_MS: u64 = 100; //First retry delay, doubled on each retry
+pub const BACKOFF_MAX_MS: u64 = 2000;
+pub const BREAKER_THRESHOLD: u32 = 3; //Consecutive failed calls before a peer is considered unhealthy
+pub const BREAKER_COOLDOWN_MS: u64 = 30000; //How long an unhealthy peer is skipped before being tried again
+pub const MAX_MISSED_PINGS: u32 = 2; //Consecutive unanswered pings before a contact is dropped from the routing table
+pub const KEY_ALGORITHM: KeyAlgorithm = KeyAlgorithm::Ed25519; //Algorithm of newly generated node keys
+pub const RSA_KEY_BITS: u32 = 2048; //Size of generated RSA keys, also the smallest RSA key accepted from peers
+pub const KEYSTORE_DIR: &str = "keystore"; //Where nodes keep their encrypted identities
+pub const WALLET_FILE: &str = "keystore/wallet.key"; //Identity the wallet binary signs with unless given another keystore
+pub const KEYSTORE_ITERATIONS: u32 = 200_000; //PBKDF2 rounds protecting a keystore passphrase
+pub const PASSPHRASE_ENV: &str = "BLOCC_PASSPHRASE"; //When set, used instead of prompting for the keystore passphrase
+pub const DATA_DIR: &str = "data"; //Where nodes keep their block log and chain state
+pub const SEGMENT_SIZE: u64 = 1 << 20; //Block log segments roll over past this many bytes
+pub const ROUTES_SNAPSHOT_PERIOD: u64 = 60; //Seconds between routing table snapshots
+pub const BANS_FILE: &str = "bans.bin"; //Ban list, kept in the same directory as the routing table snapshot
+pub const MAX_TRACKED_PEERS: usize = 4096; //Per-peer rate limit buckets and replay windows kept, the least recently used is dropped for a new one
+pub const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0]; //Upper bounds in seconds of the RPC latency histogram buckets
+pub const ADMIN_SHUTDOWN_GRACE_MS: u64 = 200; //Time the Shutdown admin RPC leaves for its reply to be sent before the process stops
+pub const MAX_METRICS_REQUEST_BYTES: usize = 8192; //Headers read from a metrics scrape before answering it
+pub const MAX_REPUTATION: f64 = 20.0; //Cap on a peer's score, so a long good record cannot shield it from a ban
+pub const CONTACT_TTL_MS: u64 = 24 * 60 * 60 * 1000; //Saved contacts not seen for longer than this are not retried on restart
+pub const WARM_RESTART_CONTACTS: usize = 2; //Saved contacts that must answer to skip the bootstraps on restart
+pub const CERTIFICATE_DAYS: u32 = 3650; //Validity of the self-signed TLS certificates derived from node keys
+pub const SIGNING_DOMAIN: &str = "bloccChainz/signature/v1"; //Prefix of every signed envelope, keeps these signatures from being valid in any other protocol
+pub const REPLAY_WINDOW_MS: u64 = 30000; //Signed messages older (or further in the future) than this are rejected
+pub const SEEN_TTL_MS: u64 = 10 * 60 * 1000; //How long a relayed block or transaction is remembered, so it is not relayed again
+pub const MAX_ORPHANS: usize = 64; //Blocks kept while waiting for their parent, the oldest are dropped first
+pub const CLIENT_FUNDS: u32 = 100; //Balance the test harness allocates to each client it starts
+pub const MAX_SEEN: usize = 65536; //Relayed items remembered at most, the oldest are forgotten first
 
+//Unix millis, advanced by the tokio clock from the wall time of the first call so a paused simulated run sees its own time
+pub fn now_millis() -> u64{
+    static ANCHOR: OnceLock<(u64, tokio::time::Instant)> = OnceLock::new();
+    let (wall, instant) = ANCHOR.get_or_init(|| {
+        (SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64, tokio::time::Instant::now())
+    });
+    wall + tokio::time::Instant::now().saturating_duration_since(*instant).as_millis() as u64
+}
+thread_local!{
+    //Source of every random choice that changes what a node does, seeded by the simulator so that a run replays
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+pub fn seed_rng(seed:u64){
+    RNG.with(|i| *i.borrow_mut() = StdRng::seed_from_u64(seed))
+}
+pub fn with_rng<T>(f:impl FnOnce(&mut StdRng) -> T) -> T{
+    RNG.with(|i| f(&mut i.borrow_mut()))
+}
+//Nonce a sender gives a new transaction
+pub fn new_nonce() -> u64{
+    with_rng(|i| i.gen())
+}
+//IPv6 literals need brackets wherever a port follows them
+pub fn format_host(ip:&str) -> String{
+    match ip.parse::<IpAddr>(){
+        Ok(IpAddr::V6(_)) => format!("[{}]", ip),
+        _ => ip.to_string(),
+    }
+}
 pub fn format_url(ip:String, port:String) -> String{
-    format!("http://{}:{}", ip, port)
+    format!("http://{}:{}", format_host(&ip), port)
 }
 pub fn format_addr(ip:String, port:String) -> SocketAddr{
-    format!("{}:{}", ip, port).parse().unwrap()
+    format!("{}:{}", format_host(&ip), port).parse().unwrap()
 }
 pub fn parse_input() -> i32{
     let mut t = String::new();
@@ -32,26 +99,66 @@ pub fn parse_input() -> i32{
         }
     };
 }
-pub fn new_key() -> (Vec<u8>, Vec<u8>){
-    let keypair = Rsa::generate(1024).unwrap();
-    (keypair.private_key_to_pem().unwrap(), keypair.public_key_to_pem().unwrap())
+pub fn prompt_line(prompt:&str) -> String{
+    println!("{}", prompt);
+    let mut t = String::new();
+    io::stdin()
+        .read_line(&mut t)
+        .expect("Failed to read line");
+    t.trim_end_matches(['\r', '\n']).to_string()
+}
+//Restores the terminal settings it was made from when dropped
+struct EchoOff(libc::termios);
+impl Drop for EchoOff{
+    fn drop(&mut self){
+        unsafe{ libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+pub fn read_passphrase(prompt:&str) -> String{
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV){ return passphrase }
+    let mut termios = unsafe{ std::mem::zeroed::<libc::termios>() };
+    // piped input is not echoed anyway
+    if unsafe{ libc::isatty(libc::STDIN_FILENO) } != 1 || unsafe{ libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0{ return prompt_line(prompt) }
+    let _restore = EchoOff(termios);
+    termios.c_lflag &= !libc::ECHO;
+    unsafe{ libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) };
+    let passphrase = prompt_line(prompt);
+    println!();
+    passphrase
+}
+//Hex SHA-256 of a public key, what bootstrap pins are compared against
+pub fn key_fingerprint(pkey:&[u8]) -> String{
+    sha256::digest(pkey)
+}
+//IDs are the first hex digits of their key's fingerprint, so only that key signs for a node or an account
+pub fn owns_id(id:&str, pkey:&[u8]) -> bool{
+    !id.is_empty() && key_fingerprint(pkey).starts_with(id)
+}
+pub fn new_key(algorithm:KeyAlgorithm) -> (Vec<u8>, Vec<u8>){
+    let keypair = match algorithm{
+        // drawn from the node's generator, so a seeded simulation gets the same keys, and so IDs, on every run
+        KeyAlgorithm::Ed25519 => PKey::private_key_from_raw_bytes(&with_rng(|i| i.gen::<[u8; 32]>()), openssl::pkey::Id::ED25519).unwrap(),
+        KeyAlgorithm::Rsa => PKey::from_rsa(Rsa::generate(RSA_KEY_BITS).unwrap()).unwrap(),
+    };
+    (keypair.private_key_to_pem_pkcs8().unwrap(), keypair.public_key_to_pem().unwrap())
 }
 pub async fn get_ip_address() -> String{
     local_ip_address::local_ip().unwrap().to_string()
 }
-pub async fn known_bootstrap_addresses() -> Vec<String>{
-    let mut res: Vec<String> = Vec::new();
-    res.push(get_ip_address().await);
+//The local IPv4 address, followed by the local IPv6 one when the host has it
+pub async fn get_ip_addresses() -> Vec<String>{
+    let mut res = vec![get_ip_address().await];
+    if let Ok(ip) = local_ip_address::local_ipv6(){ res.push(ip.to_string()) }
     res
 }
 
 pub fn bind(destination:String) -> Result<Option<TcpSocket>, Box<dyn Error>>{
-        let socket = TcpSocket::new_v4();
-        if socket.is_ok(){
-            let res=socket.unwrap();
+        let addr:SocketAddr = destination.parse()?;
+        let socket = if addr.is_ipv6(){ TcpSocket::new_v6() } else { TcpSocket::new_v4() };
+        if let Ok(res) = socket{
             res.set_reuseaddr(true).unwrap(); // allow to reuse the addr both for connect and listen
             res.set_reuseport(true).unwrap(); // same for the port
-            res.bind(destination.parse().unwrap()).unwrap();
+            res.bind(addr)?;
             return Ok(Option::from(res))
         }
         Ok(None)
@@ -0,0 +1,168 @@
+use std::path::Path;
+use std::process::exit;
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use tonic::Status;
+use util::*;
+use requests::*;
+use blockchain::{genesis, hash_block};
+use bootstrap::{bootstrap_peers, BootstrapPeer};
+use keystore::Identity;
+use proto::{Bid, Node, NodeInfo};
+use ssd::{util, proto, blockchain, requests, keystore, bootstrap, config, logging};
+
+//Scriptable wallet: each run signs one request with the identity in its keystore and sends it to a running node.
+//Results go to stdout as "KEY: value" lines, or as one JSON object with --json; failures exit with status 1.
+
+#[derive(Debug,Parser)]
+#[command(about = "bloccChainz wallet")]
+struct Cli{
+    #[arg(long, default_value = WALLET_FILE, help = "Keystore holding the wallet identity")]
+    keystore: String,
+    #[arg(long, help = "Node to send requests to as host:port[@fingerprint], the first bootstrap peer when not given")]
+    node: Option<String>,
+    #[arg(long, help = "Print results as JSON")]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+#[derive(Debug,Subcommand)]
+enum Command{
+    #[command(about = "Create the wallet identity")]
+    Keygen{
+        #[arg(long, help = "Hex digits of the wallet ID, must match the network's")]
+        id_size: Option<usize>,
+    },
+    #[command(about = "Balance of the wallet, or of another account")]
+    Balance{ account: Option<String> },
+    #[command(about = "Transfer value to another account")]
+    Send{ destination: String, value: u32 },
+    #[command(about = "Whether a transfer from the wallet is pending, relayed or confirmed in a block")]
+    TxStatus{ destination: String, value: u32, #[arg(help = "Nonce printed when the transfer was sent")] nonce: u64 },
+    #[command(subcommand, about = "Auctions held by the node, which no other node knows of")]
+    Auction(AuctionCommand),
+    #[command(subcommand, about = "Blocks of the node's chain")]
+    Chain(ChainCommand),
+}
+#[derive(Debug,Subcommand)]
+enum AuctionCommand{
+    #[command(about = "Open an auction, named by the node when no name is given")]
+    Create{ name: Option<String> },
+    #[command(about = "Bid on an open auction")]
+    Bid{ name: String, value: u32 },
+    #[command(about = "List open and closed auctions")]
+    List,
+    #[command(about = "Close an auction opened by this wallet, naming its winner")]
+    Close{ name: String },
+    #[command(about = "Pay the owner of a closed auction this wallet won")]
+    Pay{ name: String },
+}
+#[derive(Debug,Subcommand)]
+enum ChainCommand{
+    #[command(about = "Height and hash of the last block")]
+    Tip,
+    #[command(about = "Block with the given hash")]
+    Block{ hash: String },
+}
+
+fn fail(json:bool, message:String) -> !{
+    match json{
+        true => println!("{}", json!({ "error": message })),
+        false => eprintln!("ERROR: {}", message),
+    }
+    exit(1)
+}
+fn print(json:bool, value:Value){
+    if json{ return println!("{}", value) }
+    for (key, value) in value.as_object().unwrap(){
+        match value{
+            Value::String(i) => println!("{}: {}", key.to_uppercase(), i),
+            Value::Array(items) => for i in items{ println!("{}: {}", key.to_uppercase(), i) },
+            i => println!("{}: {}", key.to_uppercase(), i),
+        }
+    }
+}
+fn bid(bid:&Bid) -> Value{
+    json!({ "name": String::from_utf8_lossy(&bid.name), "owner": bid.owner, "highest_bidder": bid.highest_bidder, "value": bid.value, "participants": bid.participants })
+}
+
+//The node's own contact, learned from the address it is configured by
+async fn connect(source:&Node, node:Option<String>) -> Result<NodeInfo, Status>{
+    let peer = match node{
+        Some(entry) => BootstrapPeer::parse(&entry).map_err(Status::invalid_argument)?,
+        None => bootstrap_peers().await.into_iter().next().ok_or_else(|| Status::invalid_argument("NO NODE GIVEN AND NO BOOTSTRAP CONFIGURED"))?,
+    };
+    identify_request(source, &peer).await
+}
+
+async fn run(cli:Cli) -> Result<Value, Status>{
+    if let Command::Keygen{ id_size } = cli.command{
+        if Path::new(&cli.keystore).exists(){ return Err(Status::already_exists(format!("{} ALREADY EXISTS", cli.keystore))) }
+        let identity = Identity::generate(KEY_ALGORITHM, id_size.unwrap_or(config::DhtConfig::default().id_size));
+        identity.save(&cli.keystore, &read_passphrase("New wallet passphrase? ")).map_err(|e| Status::internal(format!("FAILURE SAVING WALLET: {}", e)))?;
+        return Ok(json!({ "id": identity.id, "fingerprint": key_fingerprint(&identity.pkey), "keystore": cli.keystore }))
+    }
+    let identity = Identity::load(&cli.keystore, &read_passphrase("Wallet passphrase? ")).map_err(|e| Status::invalid_argument(format!("FAILURE LOADING WALLET {}: {}", cli.keystore, e)))?;
+    let id = identity.id.clone();
+    let source = Node::client(identity);
+    let node = connect(&source, cli.node).await?;
+    let value = match cli.command{
+        Command::Keygen{ .. } => unreachable!(),
+        Command::Balance{ account } => {
+            let account = account.unwrap_or(id);
+            let response = balance_request(&source, account.clone(), &node).await?;
+            json!({ "account": account, "balance": response.balance, "height": response.height })
+        }
+        Command::Send{ destination, value } => {
+            let nonce = new_nonce();
+            let state = try_transaction_request(&source, id.clone(), value, destination.clone(), nonce, &node).await?;
+            json!({ "sender": id, "destination": destination, "value": value, "nonce": nonce, "state": if state.is_empty(){ "relayed".to_string() } else { state } })
+        }
+        Command::TxStatus{ destination, value, nonce } => {
+            let state = transaction_status_request(&source, id.clone(), value, destination.clone(), nonce, &node).await?;
+            json!({ "sender": id, "destination": destination, "value": value, "nonce": nonce, "state": state })
+        }
+        Command::Auction(AuctionCommand::Create{ name }) => json!({ "name": create_bid_request(&source, name.unwrap_or_default(), &node).await? }),
+        Command::Auction(AuctionCommand::Bid{ name, value }) => {
+            let highest = bid_value_request(&source, name.clone(), value, &node).await?;
+            json!({ "name": name, "value": value, "highest": highest })
+        }
+        Command::Auction(AuctionCommand::List) => {
+            let response = auctions_request(&source, &node).await?;
+            json!({ "open": response.open.iter().map(bid).collect::<Vec<_>>(), "closed": response.closed.iter().map(bid).collect::<Vec<_>>() })
+        }
+        Command::Auction(AuctionCommand::Close{ name }) => bid(&close_bid_request(&source, name, &node).await?),
+        Command::Auction(AuctionCommand::Pay{ name }) => {
+            let closed = auctions_request(&source, &node).await?.closed;
+            let won = closed.iter().find(|i| i.name == name.as_bytes()).ok_or_else(|| Status::not_found(format!("NO CLOSED AUCTION {}", name)))?;
+            if won.highest_bidder != id{ return Err(Status::failed_precondition(format!("AUCTION {} WAS NOT WON BY THIS WALLET", name))) }
+            let nonce = new_nonce();
+            let state = try_transaction_request(&source, id.clone(), won.value, won.owner.clone(), nonce, &node).await?;
+            json!({ "name": name, "destination": won.owner, "value": won.value, "nonce": nonce, "state": state })
+        }
+        Command::Chain(ChainCommand::Tip) => {
+            let blocks = try_retrieve_blockchain_request(&source, &node).await?;
+            let tip = blocks.last().cloned().unwrap_or_else(genesis);
+            json!({ "height": blocks.len(), "hash": String::from_utf8_lossy(&hash_block(tip)) })
+        }
+        Command::Chain(ChainCommand::Block{ hash }) => {
+            let blocks = try_retrieve_blockchain_request(&source, &node).await?;
+            let (height, block) = blocks.into_iter().enumerate().find(|(_, i)| hash_block(i.clone()) == hash.as_bytes())
+                .ok_or_else(|| Status::not_found(format!("NO BLOCK {}", hash)))?;
+            json!({ "height": height + 1, "hash": hash, "prev_hash": String::from_utf8_lossy(&block.prev_hash), "nonce": block.nonce, "merkle_root": String::from_utf8_lossy(&block.merkle_root) })
+        }
+    };
+    Ok(value)
+}
+
+#[tokio::main]
+async fn main(){
+    let cli = Cli::parse();
+    let json = cli.json;
+    // requests that fail are reported once as the command's result, not also logged
+    logging::init(&config::LogConfig{ level: "error".to_string(), ..Default::default() });
+    match run(cli).await{
+        Ok(value) => print(json, value),
+        Err(status) => fail(json, status.message().to_string()),
+    }
+}
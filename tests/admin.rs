@@ -0,0 +1,58 @@
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tonic::Code;
+use ssd::admin::serve;
+use ssd::harness::*;
+use ssd::keystore::Identity;
+use ssd::proto::admin_client::AdminClient;
+use ssd::proto::{BanPeerRequest, ChainRequest, Node, NodeInfo, PeersRequest, RoutingTableRequest, UnbanPeerRequest};
+use ssd::signatures::seal;
+use ssd::util::*;
+
+fn signer(identity:&Identity) -> Node{
+    let info = NodeInfo{ id: identity.id.clone(), pkey: identity.pkey.clone(), algorithm: identity.algorithm as i32, ..Default::default() };
+    Node{ info: Some(info), skey: identity.skey.clone(), ..Default::default() }
+}
+
+async fn admin(key:Option<String>) -> AdminClient<tonic::transport::Channel>{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve(listener, key));
+    AdminClient::connect(url).await.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_admin_inspects_and_bans_peers_with_its_key_only(){
+    let network = Network::start(test_config(), 1, 1, 1).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    let key = Identity::generate(KEY_ALGORITHM, 10);
+    let mut client = admin(Some(key_fingerprint(&key.pkey))).await;
+    let admin_node = signer(&key);
+    let miner = network.miners[0].node().await;
+    let id = miner.info.clone().unwrap().id;
+
+    let table = client.routing_table(seal(RoutingTableRequest{ node_id: id.clone(), ..Default::default() }, id.clone(), &admin_node)).await.unwrap().into_inner();
+    assert_eq!(table.node.unwrap().id, id);
+    assert_eq!(table.buckets.len(), network.config.dht.buckets());
+    let chain = client.chain(seal(ChainRequest{ node_id: id.clone(), ..Default::default() }, id.clone(), &admin_node)).await.unwrap().into_inner();
+    assert!(chain.state.is_some());
+
+    let peer = miner.get_neighbours()[0].clone();
+    let request = BanPeerRequest{ node_id: id.clone(), peer: peer.id.clone(), seconds: 60, ..Default::default() };
+    let banned = client.ban_peer(seal(request, id.clone(), &admin_node)).await.unwrap().into_inner();
+    assert_eq!(banned.fingerprint, key_fingerprint(&peer.pkey));
+    assert!(network.miners[0].node().await.get_neighbour(peer.id.clone()).is_none());
+    let peers = client.peers(seal(PeersRequest{ node_id: id.clone(), ..Default::default() }, id.clone(), &admin_node)).await.unwrap().into_inner();
+    assert!(peers.bans.iter().any(|i| i.fingerprint == banned.fingerprint));
+    let request = UnbanPeerRequest{ node_id: id.clone(), peer: banned.fingerprint.clone(), ..Default::default() };
+    assert!(client.unban_peer(seal(request, id.clone(), &admin_node)).await.unwrap().into_inner().lifted);
+    assert!(!network.miners[0].reputation().is_banned(&banned.fingerprint));
+
+    let intruder = signer(&Identity::generate(KEY_ALGORITHM, 10));
+    let refused = client.chain(seal(ChainRequest{ node_id: id.clone(), ..Default::default() }, id.clone(), &intruder)).await.unwrap_err();
+    assert_eq!(refused.code(), Code::PermissionDenied);
+    let unsigned = client.chain(ChainRequest{ node_id: id.clone(), ..Default::default() }).await.unwrap_err();
+    assert_eq!(unsigned.code(), Code::Unauthenticated);
+    let unknown = client.chain(seal(ChainRequest{ node_id: "0".repeat(10), ..Default::default() }, "0".repeat(10), &admin_node)).await.unwrap_err();
+    assert_eq!(unknown.code(), Code::NotFound);
+}
@@ -0,0 +1,26 @@
+use ssd::bootstrap::{configure, trusts, BootstrapPeer};
+use ssd::proto::NodeInfo;
+use ssd::util::key_fingerprint;
+
+fn announced(ip:&str, port:u32, pkey:&[u8]) -> NodeInfo{
+    NodeInfo{ id: "boot".to_string(), ip: ip.to_string(), port, pkey: pkey.to_vec(), bootstrap: true, ..Default::default() }
+}
+
+#[test]
+fn learned_bootstraps_need_a_pinned_key(){
+    let pinned = BootstrapPeer::parse(&format!("10.0.0.1:55555@{}", key_fingerprint(b"pinned"))).unwrap();
+    let open = BootstrapPeer::parse("10.0.0.2:55555").unwrap();
+    let peers = vec![pinned, open];
+    assert!(trusts(&peers, &announced("10.0.0.1", 55555, b"pinned")));
+    assert!(!trusts(&peers, &announced("10.0.0.1", 55555, b"impostor")));
+    assert!(trusts(&peers, &announced("10.0.0.2", 55555, b"anyone")));
+    assert!(trusts(&peers, &announced("10.0.0.9", 55555, b"pinned")));
+    assert!(!trusts(&peers, &announced("10.0.0.9", 55555, b"impostor")));
+    assert!(trusts(&peers[1..], &announced("10.0.0.9", 55555, b"anyone")));
+}
+
+#[test]
+fn configuring_the_bootstraps_twice_is_an_error(){
+    assert!(configure(vec![BootstrapPeer::parse("10.0.0.1:55555").unwrap()]).is_ok());
+    assert!(configure(vec![BootstrapPeer::parse("10.0.0.2:55555").unwrap()]).is_err());
+}
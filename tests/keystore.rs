@@ -0,0 +1,18 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use ssd::keystore::Identity;
+use ssd::util::*;
+
+#[test]
+fn keystores_are_private_and_keep_their_iterations(){
+    let dir = std::env::temp_dir().join(format!("ssd-keystore-{}", std::process::id()));
+    let path = dir.join("node.key").to_string_lossy().to_string();
+    let identity = Identity::generate(KEY_ALGORITHM, 10);
+    identity.save(&path, "secret").unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(Identity::load(&path, "secret").unwrap().id, identity.id);
+    let mut weakened = identity.seal("secret");
+    weakened.iterations = 1;
+    assert!(Identity::unseal(weakened, "secret").is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+use ssd::blockchain::{hash_block, hash_transactions, merkle_proof, verify_headers, verify_proof};
+use ssd::harness::*;
+use ssd::keystore::Identity;
+use ssd::nodes_init::start_node;
+use ssd::storage::MemoryStore;
+use ssd::util::*;
+
+#[test]
+fn merkle_proofs_lead_each_transaction_and_no_other_to_the_root(){
+    for count in 1..8{
+        let transactions:Vec<String> = (0..count).map(|i| format!("a->{}->b", i)).collect();
+        let root = hash_transactions(transactions.clone());
+        for (index, transaction) in transactions.iter().enumerate(){
+            let steps = merkle_proof(&transactions, index);
+            assert!(verify_proof(transaction, &steps, &root));
+            assert!(!verify_proof("a->99->b", &steps, &root));
+        }
+    }
+    assert!(merkle_proof(&["a->1->b".to_string()], 1).is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_light_client_keeps_headers_and_counts_its_own_transactions_once_proven(){
+    let mut config = test_config();
+    config.chain.light = true;
+    let network = Network::start(config, 1, 1, 2).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    let (light, other) = (&network.clients[0], &network.clients[1]);
+    assert!(light.light().is_some() && network.miners[0].light().is_none() && network.bootstraps[0].light().is_none());
+    let light_id = light.node().await.info.unwrap().id;
+    let other_id = other.node().await.info.unwrap().id;
+    let miner_id = network.miners[0].node().await.info.unwrap().id;
+
+    let transaction = network.transfer(light, 5, miner_id.clone()).await;
+    assert!(network.wait_for_height(1, Duration::from_secs(10)).await);
+    assert!(wait_for(Duration::from_secs(10), || async { light.balance(&light_id).await == CLIENT_FUNDS as i64 - 5 }).await);
+    assert!(light.is_confirmed(&transaction).await);
+    let tip = hash_block(light.headers().await.last().cloned().unwrap());
+    assert_eq!(network.bootstraps[0].body(&tip).await, Some(vec![transaction]));
+    assert!(light.body(&tip).await.is_none());
+
+    network.transfer(other, 3, miner_id.clone()).await;
+    assert!(network.wait_for_height(2, Duration::from_secs(10)).await);
+    assert!(wait_for(Duration::from_secs(10), || async { network.bootstraps[0].balance(&other_id).await == CLIENT_FUNDS as i64 - 3 }).await);
+    assert_eq!(light.balance(&other_id).await, CLIENT_FUNDS as i64);
+
+    // a client started later takes the headers from the bootstrap, checking them first
+    let late = start_node(network.config.clone(), None, false, Identity::generate(KEY_ALGORITHM, network.config.dht.id_size), Box::new(MemoryStore::default()), None).await;
+    let headers = late.headers().await;
+    assert_eq!(headers, network.bootstraps[0].headers().await);
+    assert!(verify_headers(&headers, network.config.chain.difficulty));
+    let mut forged = headers.clone();
+    forged[0].nonce += 1;
+    assert!(!verify_headers(&forged, network.config.chain.difficulty));
+}
@@ -0,0 +1,19 @@
+use ssd::config::LimitsConfig;
+use ssd::limits::RateLimiter;
+use ssd::util::MAX_TRACKED_PEERS;
+
+#[test]
+fn a_flood_of_fresh_keys_does_not_forget_an_active_peer(){
+    let config = LimitsConfig{ peer_rate: 0, peer_burst: 1, global_rate: 0, global_burst: 1_000_000, ..Default::default() };
+    let limits = RateLimiter::new(config);
+    assert!(limits.check("FindNode", "busy").is_ok());
+    assert!(limits.check("FindNode", "busy").is_err());
+    for i in 0..MAX_TRACKED_PEERS * 2{
+        assert!(limits.check("FindNode", &format!("sybil-{}", i)).is_ok());
+        if i % 100 == 0{
+            // still refused, its drained bucket was not evicted
+            assert!(limits.check("FindNode", "busy").is_err());
+        }
+        assert!(limits.tracked() <= MAX_TRACKED_PEERS);
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use ssd::harness::*;
+use ssd::metrics::serve;
+
+async fn get(addr:std::net::SocketAddr, path:&str) -> String{
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_scrape_reports_every_node(){
+    let network = Network::start(test_config(), 1, 1, 2).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    network.mined_transfer(Duration::from_secs(20)).await.expect("TRANSFER NOT MINED");
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener));
+    let response = get(addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    let miner = network.miners[0].node().await.info.unwrap().id;
+    assert!(response.contains(&format!("blocc_chain_height{{node=\"{}\"}} 1\n", miner)), "{}", response);
+    assert!(response.contains("method=\"Transaction\",code=\"Ok\"}"), "{}", response);
+    assert!(response.contains("method=\"Transaction\",le=\"+Inf\"}"), "{}", response);
+    assert!(response.contains(&format!("blocc_routing_table_contacts{{node=\"{}\",bucket=\"0\"}}", miner)), "{}", response);
+    assert!(!response.contains(&format!("blocc_mining_hashes_total{{node=\"{}\"}} 0\n", miner)), "{}", response);
+    assert_eq!(response.matches("# TYPE blocc_chain_height gauge").count(), 1);
+    for i in network.nodes(){
+        let id = i.node().await.info.unwrap().id;
+        assert!(response.contains(&format!("blocc_mempool_transactions{{node=\"{}\"}}", id)), "{}", response);
+    }
+    assert!(get(addr, "/").await.starts_with("HTTP/1.1 404 Not Found"));
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+use tonic::Code;
+use ssd::blockchain::{genesis, signed_line, transaction_line, MinerService};
+use ssd::endpoint::BlockOutcome;
+use ssd::harness::*;
+use ssd::bootstrap::BootstrapPeer;
+use ssd::keystore::Identity;
+use ssd::proto::{Block, JoinRequest, Node, RemoveRequest, UpdateBlockchainRequest};
+use ssd::requests::{join_request, ping_request, pool, signed_transfer, try_transaction_request};
+use ssd::signatures::seal;
+use ssd::util::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nodes_join_and_find_each_other(){
+    let network = Network::start(test_config(), 2, 0, 4).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    for i in &network.clients{
+        let id = i.node().await.info.unwrap().id;
+        let found = network.find(&network.clients[0], id.clone()).await;
+        assert_eq!(found.map(|i| i.id), Some(id));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transactions_are_mined_and_reach_every_node(){
+    let mut config = test_config();
+    config.chain.reward = 3;
+    let network = Network::start(config, 1, 1, 2).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    let sender = network.clients[0].node().await.info.unwrap().id;
+    let miner = network.miners[0].node().await.info.unwrap().id;
+    let destination = network.mined_transfer(Duration::from_secs(20)).await.expect("TRANSFER NOT MINED");
+    assert!(wait_for(Duration::from_secs(10), || async {
+        for i in network.nodes(){
+            if i.balance(&destination).await != CLIENT_FUNDS as i64 + 5 || i.balance(&sender).await != CLIENT_FUNDS as i64 - 5{ return false }
+            // the reward is credited from the block body, not by the miner alone
+            if i.balance(&miner).await != 3{ return false }
+        }
+        true
+    }).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_transfer_beyond_the_balance_is_refused(){
+    let network = Network::start(test_config(), 1, 1, 2).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    let client = network.clients[0].node().await;
+    let sender = client.info.clone().unwrap().id;
+    let destination = network.clients[1].node().await.info.unwrap().id;
+    let miner = network.miners[0].node().await.info.unwrap();
+    let refused = try_transaction_request(&client, sender.clone(), CLIENT_FUNDS + 1, destination.clone(), new_nonce(), &miner).await;
+    assert_eq!(refused.unwrap_err().code(), Code::FailedPrecondition);
+    assert_eq!(try_transaction_request(&client, sender.clone(), CLIENT_FUNDS, destination, new_nonce(), &miner).await.unwrap(), "queued");
+    assert!(network.wait_for_height(1, Duration::from_secs(20)).await);
+    assert!(wait_for(Duration::from_secs(10), || async { network.miners[0].balance(&sender).await == 0 }).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocks_arriving_out_of_order_are_kept_until_their_parent_comes(){
+    let network = Network::start(test_config(), 1, 0, 1).await;
+    let node = &network.clients[0];
+    let mut miner = MinerService::default();
+    miner.init_source("test".to_string(), network.config.chain.clone()).await;
+    let first = miner.generate_block(Some(genesis()), Vec::new()).await;
+    let second = miner.generate_block(Some(first.clone()), Vec::new()).await;
+    assert_eq!(node.append_block(second.clone(), Vec::new()).await, BlockOutcome::Orphan);
+    assert_eq!(node.append_block(first.clone(), Vec::new()).await, BlockOutcome::Appended);
+    assert_eq!(node.height().await, 2);
+    assert_eq!(node.append_block(second, Vec::new()).await, BlockOutcome::Known);
+
+    let body = vec![signed_line(&transaction_line(COINBASE, 0, "miner", 1), &[], &[])];
+    let competing = miner.generate_block(Some(genesis()), body.clone()).await;
+    assert_eq!(node.append_block(competing, body).await, BlockOutcome::Fork);
+    let unmined = (0..).map(|nonce| Block{ nonce, ..first.clone() }).find(|i| !String::from_utf8_lossy(&ssd::blockchain::hash_block(i.clone())).starts_with('0')).unwrap();
+    assert_eq!(node.append_block(unmined, Vec::new()).await, BlockOutcome::Invalid);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_missing_a_field_are_refused_without_crashing_the_handler(){
+    let network = Network::start(test_config(), 1, 1, 0).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    let source = network.miners[0].node().await;
+    let source_id = source.info.clone().unwrap().id;
+    let boot = network.bootstraps[0].node().await.info.unwrap();
+    let mut client = pool().get(boot.urls()[0].clone(), &source, Some(key_fingerprint(&boot.pkey))).unwrap();
+    let join = client.join(seal(JoinRequest::default(), String::new(), &source)).await.unwrap_err();
+    assert_eq!(join.code(), Code::InvalidArgument);
+    let remove = client.remove_node(seal(RemoveRequest{ source_id: source_id.clone(), ..Default::default() }, boot.id.clone(), &source)).await.unwrap_err();
+    assert_eq!(remove.code(), Code::InvalidArgument);
+    let update = client.update_blockchain(seal(UpdateBlockchainRequest{ source_id, ..Default::default() }, boot.id.clone(), &source)).await.unwrap_err();
+    assert_eq!(update.code(), Code::InvalidArgument);
+    // the handlers still answer afterwards
+    assert!(ping_request(&source, &boot).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_joiner_nothing_answers_for_is_told_it_cannot_be_dialed_back(){
+    let network = Network::start(test_config(), 1, 0, 0).await;
+    let boot = network.bootstraps[0].node().await.info.unwrap();
+    let config = test_config();
+    // the port is bound only while the node is built, nothing listens on it afterwards
+    let joiner = Node::new(vec!["127.0.0.1".to_string()], None, false, Identity::generate(KEY_ALGORITHM, config.dht.id_size), &config.dht);
+    let peer = BootstrapPeer{ host: "127.0.0.1".to_string(), port: boot.port, fingerprint: Some(key_fingerprint(&boot.pkey)) };
+    let (neighbours, observed) = join_request(joiner, &[peer]).await;
+    assert!(neighbours.iter().any(|i| i.id == boot.id));
+    let observed = observed.unwrap();
+    assert_eq!(observed.ip, "127.0.0.1");
+    assert!(!observed.reachable);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_miner_without_bootstraps_keeps_the_block_it_mined(){
+    let mut config = test_config();
+    let payer = Identity::generate(KEY_ALGORITHM, config.dht.id_size);
+    config.chain.allocations.insert(payer.id.clone(), CLIENT_FUNDS);
+    let network = Network::start(config, 0, 1, 0).await;
+    let miner = &network.miners[0];
+    let destination = miner.node().await.info.unwrap().id;
+    let payer = Node::client(payer);
+    let request = signed_transfer(&payer, payer.info.clone().unwrap().id, 5, destination.clone(), new_nonce());
+    assert!(miner.process_transaction(request).await.is_ok());
+    assert!(network.wait_for_height(1, Duration::from_secs(10)).await);
+    assert_eq!(miner.balance(&destination).await, 5 + network.config.chain.reward as i64);
+}
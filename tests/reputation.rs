@@ -0,0 +1,35 @@
+use std::fs;
+use ssd::config::ReputationConfig;
+use ssd::proto::NodeInfo;
+use ssd::reputation::{Event, Reputation};
+use ssd::util::key_fingerprint;
+
+fn peer(id:&str) -> NodeInfo{
+    NodeInfo{ id: id.to_string(), pkey: id.as_bytes().to_vec(), ..Default::default() }
+}
+
+#[test]
+fn bans_survive_a_restart(){
+    let dir = std::env::temp_dir().join(format!("ssd-reputation-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bans.bin").to_string_lossy().to_string();
+    let fingerprint = key_fingerprint(b"forger");
+    let reputation = Reputation::new(ReputationConfig::default(), Some(path.clone()));
+    assert!(!reputation.record(&fingerprint, Event::BadSignature));
+    assert!(!reputation.record(&fingerprint, Event::BadSignature));
+    assert!(reputation.record(&fingerprint, Event::BadSignature));
+    assert!(Reputation::new(ReputationConfig::default(), Some(path)).is_banned(&fingerprint));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn misbehaving_peers_are_tried_last_and_banned_ones_not_at_all(){
+    let reputation = Reputation::default();
+    let (good, slow, banned) = (peer("a1"), peer("a2"), peer("a3"));
+    reputation.record(&key_fingerprint(&good.pkey), Event::Success);
+    reputation.record(&key_fingerprint(&slow.pkey), Event::Timeout);
+    for _ in 0..3{ reputation.record(&key_fingerprint(&banned.pkey), Event::InvalidBlock); }
+    let mut nodes = vec![banned, slow.clone(), good.clone()];
+    reputation.prefer(&mut nodes);
+    assert_eq!(nodes.iter().map(|i| i.id.clone()).collect::<Vec<_>>(), vec![good.id, slow.id]);
+}
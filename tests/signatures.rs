@@ -0,0 +1,38 @@
+use std::collections::HashSet;
+use ssd::proto::*;
+use ssd::signatures::{Rejection, ReplayCache, Signed};
+use ssd::util::*;
+
+#[test]
+fn the_replay_window_refuses_timestamps_at_either_end_of_the_clock(){
+    let replay = ReplayCache::default();
+    for timestamp in [0, u64::MAX, now_millis() + 2 * REPLAY_WINDOW_MS]{
+        let sign = Signature{ timestamp, nonce: 1, ..Default::default() };
+        assert_eq!(replay.check(&sign), Err(Rejection::Stale));
+    }
+    let sign = Signature{ timestamp: now_millis(), nonce: 1, ..Default::default() };
+    assert!(replay.check(&sign).is_ok());
+    assert_eq!(replay.check(&sign), Err(Rejection::Replayed));
+}
+
+#[test]
+fn each_subscription_is_signed_and_rate_limited_as_its_own_rpc(){
+    let methods = [SubscribeBlocksRequest::METHOD, SubscribeTransactionsRequest::METHOD, UpdateBlockchainRequest::METHOD, TransactionRequest::METHOD];
+    assert_eq!(methods.iter().collect::<HashSet<_>>().len(), methods.len());
+    assert_eq!(SubscribeBlocksRequest::METHOD, "/kademlia.Endpoint/SubscribeBlocks");
+}
+
+#[test]
+fn a_flood_of_fresh_keys_is_capped_and_cannot_reopen_a_replay(){
+    let replay = ReplayCache::default();
+    let honest = Signature{ pkey: b"honest".to_vec(), timestamp: now_millis(), nonce: 1, ..Default::default() };
+    assert!(replay.check(&honest).is_ok());
+    for i in 0..MAX_TRACKED_PEERS * 2{
+        let sign = Signature{ pkey: format!("sybil-{}", i).into_bytes(), timestamp: now_millis(), nonce: 1, ..Default::default() };
+        assert!(replay.check(&sign).is_ok());
+        assert!(replay.tracked() <= MAX_TRACKED_PEERS);
+    }
+    // its window was evicted, the replay is still refused
+    assert_eq!(replay.check(&honest), Err(Rejection::Replayed));
+    assert!(!Rejection::Replayed.is_forgery() && Rejection::OtherKey.is_forgery());
+}
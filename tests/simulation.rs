@@ -0,0 +1,149 @@
+use std::future::Future;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ssd::config::LogConfig;
+use ssd::harness::*;
+use ssd::logging::subscriber;
+use ssd::scenarios::{report, Attack, Verdict};
+use ssd::simulation::{Faults, SimNetwork};
+use ssd::requests::ping_request;
+use ssd::util::{now_millis, seed_rng, CLIENT_FUNDS};
+
+//Collects what a test logs
+#[derive(Clone,Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+impl Write for Captured{
+    fn write(&mut self, buf:&[u8]) -> std::io::Result<usize>{
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()>{ Ok(()) }
+}
+
+//Single threaded with the clock paused, so that a seed decides everything that happens
+fn simulate<F:Future>(seed:u64, test:F) -> F::Output{
+    seed_rng(seed);
+    tokio::runtime::Builder::new_current_thread().enable_all().start_paused(true).build().unwrap().block_on(test)
+}
+
+#[test]
+fn blocks_propagate_under_latency_and_loss(){
+    simulate(7, async {
+        let sim = SimNetwork::new(7, Faults{ latency_ms: (5, 80), drop: 0.0 });
+        let network = Network::simulate(test_config(), 1, 1, 2, &sim).await;
+        assert!(network.wait_for_neighbours(1, Duration::from_secs(30)).await);
+        sim.set_faults(Faults{ latency_ms: (5, 80), drop: 0.01 });
+        let destination = network.mined_transfer(Duration::from_secs(120)).await.expect("TRANSFER NOT MINED");
+        assert_eq!(network.miners[0].balance(&destination).await, CLIENT_FUNDS as i64 + 5);
+    });
+}
+
+#[test]
+fn node_time_follows_the_simulated_clock(){
+    simulate(5, async {
+        let start = now_millis();
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+        assert!(now_millis() - start >= 3600 * 1000);
+    });
+}
+
+#[test]
+fn crashed_nodes_are_dropped_from_routing_tables(){
+    simulate(11, async {
+        let sim = SimNetwork::new(11, Faults::default());
+        let network = Network::simulate(test_config(), 1, 0, 3, &sim).await;
+        assert!(network.wait_for_neighbours(2, Duration::from_secs(30)).await);
+        let crashed = network.clients[2].node().await.info.unwrap().id;
+        sim.crash(&crashed);
+        let forgotten = wait_for(Duration::from_secs(600), || async {
+            for i in &network.clients[..2]{
+                if i.node().await.get_neighbour(crashed.clone()).is_some(){ return false }
+            }
+            true
+        }).await;
+        assert!(forgotten);
+    });
+}
+
+#[test]
+fn a_seed_replays_the_same_run(){
+    let run = |seed:u64| simulate(seed, async move {
+        let sim = SimNetwork::new(seed, Faults{ latency_ms: (1, 50), drop: 0.02 });
+        let network = Network::simulate(test_config(), 1, 0, 3, &sim).await;
+        network.wait_for_neighbours(2, Duration::from_secs(30)).await;
+        sim.trace()
+    });
+    let trace = run(3);
+    assert!(trace.iter().any(|i| i.starts_with("DIAL")));
+    assert_eq!(trace, run(3));
+}
+
+#[test]
+fn every_attack_gets_a_verdict(){
+    let report = simulate(5, report(5));
+    for i in report.iter(){ println!("{}", i) }
+    assert_eq!(report.iter().map(|i| i.attack).collect::<Vec<_>>(), Attack::ALL);
+    let expected = [
+        // a node ID is its key's fingerprint, so an answer naming the target with another key is dropped
+        (Attack::ForgedFindNode, Verdict::Detected),
+        // contacts are pinged with their known key, so answers signed with another one get the node dropped
+        (Attack::WrongKey, Verdict::Detected),
+        (Attack::WithheldBlocks, Verdict::Tolerated),
+        // balances only move with mined blocks, and a spend beyond the balance is refused
+        (Attack::DoubleSpend, Verdict::Detected),
+        // the attacker's key holds only its own ID, the one contact it keeps in the tables
+        (Attack::SybilFlood, Verdict::Tolerated),
+        (Attack::Eclipse, Verdict::Tolerated),
+    ];
+    for (attack, verdict) in expected{
+        let outcome = report.iter().find(|i| i.attack == attack).unwrap();
+        assert_eq!(outcome.verdict, verdict, "{}", outcome);
+    }
+}
+
+#[test]
+fn no_attack_subverts_the_honest_nodes(){
+    for i in simulate(5, report(5)){
+        assert_ne!(i.verdict, Verdict::Subverted, "{}", i);
+    }
+}
+
+#[test]
+fn a_flooding_peer_is_held_to_its_burst(){
+    simulate(13, async {
+        let mut config = test_config();
+        config.limits.peer_rate = 1;
+        config.limits.peer_burst = 5;
+        let sim = SimNetwork::new(13, Faults::default());
+        let network = Network::simulate(config, 1, 0, 1, &sim).await;
+        let client = network.clients[0].node().await;
+        let bootstrap = network.bootstraps[0].node().await.info.unwrap();
+        let mut answered = 0;
+        for _ in 0..20{
+            if ping_request(&client, &bootstrap).await{ answered += 1 }
+        }
+        // the burst, plus what refilled while the pings were in flight
+        assert!((5..=7).contains(&answered), "{} PINGS ANSWERED", answered);
+    });
+}
+
+#[test]
+fn rpcs_are_logged_with_node_peer_and_method(){
+    let log = Captured::default();
+    let writer = log.clone();
+    let config = LogConfig{ level: "debug".to_string(), format: "json".to_string(), ..Default::default() };
+    let (client, bootstrap) = tracing::subscriber::with_default(subscriber(&config, move || writer.clone()), || simulate(17, async {
+        let sim = SimNetwork::new(17, Faults::default());
+        let network = Network::simulate(test_config(), 1, 0, 1, &sim).await;
+        let client = network.clients[0].node().await;
+        let bootstrap = network.bootstraps[0].node().await.info.unwrap();
+        assert!(ping_request(&client, &bootstrap).await);
+        (client.info.unwrap().id, bootstrap.id)
+    }));
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    let in_span = |line:&str, node:&str, peer:&str, outgoing:bool| [format!("\"node\":\"{}\"", node), format!("\"peer\":\"{}\"", peer),
+        "\"method\":\"Ping\"".to_string(), format!("\"outgoing\":{}", outgoing)].iter().all(|i| line.contains(i.as_str()));
+    assert!(log.lines().any(|i| i.contains("RPC SERVED") && in_span(i, &bootstrap, &client, false)), "{}", log);
+    assert!(log.lines().any(|i| i.contains("REQUEST ANSWERED") && in_span(i, &client, &bootstrap, true)), "{}", log);
+}
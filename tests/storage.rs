@@ -0,0 +1,123 @@
+use ssd::blockchain::{hash_block, request_line, signed_line, transaction_line};
+use ssd::config::ChainConfig;
+use ssd::keystore::Identity;
+use ssd::proto::{Bid, Block, Node};
+use ssd::requests::signed_transfer;
+use ssd::storage::{reconcile, BlockStore, DiskStore};
+use ssd::util::{COINBASE, KEY_ALGORITHM};
+
+fn account() -> (String, Node){
+    let identity = Identity::generate(KEY_ALGORITHM, 10);
+    (identity.id.clone(), Node::client(identity))
+}
+fn transfer(from:&(String, Node), value:u32, destination:&str, nonce:u64) -> String{
+    request_line(&signed_transfer(&from.1, from.0.clone(), value, destination.to_string(), nonce))
+}
+fn reward(value:u32, miner:&str) -> String{
+    signed_line(&transaction_line(COINBASE, value, miner, 1), &[], &[])
+}
+
+fn dir(name:&str) -> String{
+    let dir = std::env::temp_dir().join(format!("blocc-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir.to_string_lossy().to_string()
+}
+
+#[test]
+fn a_reopened_store_replays_the_blocks_its_state_missed(){
+    let dir = dir("reconcile");
+    let a = account();
+    let mut chain = ChainConfig::default();
+    chain.allocations.insert(a.0.clone(), 10);
+    let block = Block{ prev_hash: b"prev".to_vec(), nonce: 1, merkle_root: b"root".to_vec() };
+    {
+        let mut store = DiskStore::open(&dir).unwrap();
+        reconcile(&mut store, &chain).unwrap();
+        assert_eq!(store.state().balance(&a.0), 10);
+        // a crash after the block was logged, before the state was saved
+        store.append(&block).unwrap();
+        store.save_body(&hash_block(block.clone()), &[transfer(&a, 4, "b", 1)]).unwrap();
+    }
+    let mut store = DiskStore::open(&dir).unwrap();
+    assert_eq!(store.state().height, 0);
+    reconcile(&mut store, &chain).unwrap();
+    let state = store.state();
+    assert_eq!((state.height, state.tip.clone()), (1, hash_block(block)));
+    assert_eq!((state.balance(&a.0), state.balance("b")), (6, 4));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn a_block_overdrawing_its_sender_leaves_the_state_as_it_was(){
+    let a = account();
+    let mut chain = ChainConfig::default();
+    chain.allocations.insert(a.0.clone(), 10);
+    let mut state = ssd::proto::ChainState::genesis(&chain);
+    let block = Block::default();
+    let body = [transfer(&a, 6, "b", 1), transfer(&a, 6, "c", 2)];
+    assert!(state.apply_block(&block, &body, 0).is_err());
+    assert_eq!((state.height, state.balance(&a.0), state.balance("b")), (0, 10, 0));
+    assert!(state.apply_block(&block, &body[..1], 0).is_ok());
+    assert_eq!((state.height, state.balance(&a.0), state.balance("b")), (1, 4, 6));
+}
+
+#[test]
+fn every_node_credits_the_reward_heading_a_block_and_no_other(){
+    let miner = account();
+    let mut state = ssd::proto::ChainState::default();
+    let block = Block::default();
+    let paid = reward(3, &miner.0);
+    for body in [vec![reward(4, &miner.0)], vec![paid.clone(), paid.clone()], vec![transfer(&account(), 0, "b", 1), paid.clone()]]{
+        assert!(state.apply_block(&block, &body, 3).is_err());
+    }
+    assert!(state.apply_block(&block, &[paid, transfer(&miner, 2, "b", 2)], 3).is_ok());
+    assert_eq!((state.balance(&miner.0), state.balance("b")), (1, 2));
+}
+
+#[test]
+fn a_transfer_already_on_chain_is_not_applied_again(){
+    let a = account();
+    let mut chain = ChainConfig::default();
+    chain.allocations.insert(a.0.clone(), 10);
+    let mut state = ssd::proto::ChainState::genesis(&chain);
+    let block = Block::default();
+    let line = transfer(&a, 4, "b", 1);
+    assert!(state.apply_block(&block, &[line.clone(), line.clone()], 0).is_err());
+    assert!(state.apply_block(&block, std::slice::from_ref(&line), 0).is_ok());
+    assert!(state.apply_block(&block, &[line], 0).is_err());
+    assert!(state.apply_block(&block, &[transfer(&a, 2, "c", 1)], 0).is_err());
+    assert_eq!((state.height, state.balance(&a.0), state.balance("b")), (1, 6, 4));
+}
+
+#[test]
+fn only_the_sender_key_spends_from_its_account(){
+    let (a, thief) = (account(), account());
+    let mut chain = ChainConfig::default();
+    chain.allocations.insert(a.0.clone(), 10);
+    let mut state = ssd::proto::ChainState::genesis(&chain);
+    let block = Block::default();
+    // signed by another key, or the signature of another transfer
+    let forged = request_line(&signed_transfer(&thief.1, a.0.clone(), 5, thief.0.clone(), 1));
+    let honest = signed_transfer(&a.1, a.0.clone(), 1, thief.0.clone(), 1);
+    let swapped = request_line(&ssd::proto::TransactionRequest{ value: 5, ..honest });
+    let unsigned = signed_line(&transaction_line(&a.0, 5, &thief.0, 1), &[], &[]);
+    for line in [forged, swapped, unsigned]{
+        assert!(state.apply_block(&block, &[line], 0).is_err());
+    }
+    assert_eq!((state.height, state.balance(&a.0), state.balance(&thief.0)), (0, 10, 0));
+}
+
+#[test]
+fn closed_auctions_survive_rebuilding_the_chain_state(){
+    let dir = dir("auctions");
+    let bid = Bid{ name: b"lamp".to_vec(), highest_bidder: "b".to_string(), value: 7, ..Default::default() };
+    {
+        let mut store = DiskStore::open(&dir).unwrap();
+        store.close_auction(bid.clone()).unwrap();
+    }
+    // no snapshot matches the log, so the state is rebuilt from genesis
+    let mut store = DiskStore::open(&dir).unwrap();
+    reconcile(&mut store, &ChainConfig::default()).unwrap();
+    assert_eq!(store.closed_auctions(), vec![bid]);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+use tonic::Code;
+use ssd::bootstrap::BootstrapPeer;
+use ssd::harness::*;
+use ssd::keystore::Identity;
+use ssd::proto::Node;
+use ssd::requests::*;
+use ssd::util::*;
+
+fn wallet() -> (String, Node){
+    let identity = Identity::generate(KEY_ALGORITHM, 10);
+    (identity.id.clone(), Node::client(identity))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_wallet_pays_and_runs_an_auction_knowing_only_the_node_address(){
+    let (owner_id, owner) = wallet();
+    let (bidder_id, bidder) = wallet();
+    let mut config = test_config();
+    config.chain.block_size = 2;
+    config.chain.allocations.insert(owner_id.clone(), 40);
+    let network = Network::start(config, 1, 1, 0).await;
+    assert!(network.wait_for_neighbours(1, Duration::from_secs(10)).await);
+    let miner = network.miners[0].node().await.info.unwrap();
+    let peer = BootstrapPeer{ host: "127.0.0.1".to_string(), port: miner.port, fingerprint: Some(key_fingerprint(&miner.pkey)) };
+
+    let node = identify_request(&owner, &peer).await.unwrap();
+    assert_eq!(node.id, miner.id);
+    let impostor = BootstrapPeer{ fingerprint: Some(key_fingerprint(b"another key")), ..peer.clone() };
+    assert!(identify_request(&owner, &impostor).await.is_err());
+
+    assert_eq!(try_transaction_request(&owner, owner_id.clone(), 10, bidder_id.clone(), 1, &node).await.unwrap(), "processed");
+    assert_eq!(transaction_status_request(&owner, owner_id.clone(), 10, bidder_id.clone(), 1, &node).await.unwrap(), "pending");
+    assert_eq!(transaction_status_request(&owner, owner_id.clone(), 10, bidder_id.clone(), 2, &node).await.unwrap(), "unknown");
+    assert_eq!(transaction_status_request(&owner, owner_id.clone(), 11, bidder_id.clone(), 1, &node).await.unwrap(), "unknown");
+    assert_eq!(balance_request(&owner, bidder_id.clone(), &node).await.unwrap().balance, 0);
+    // the same payment again is another transfer, and fills the block
+    assert_eq!(try_transaction_request(&owner, owner_id.clone(), 10, bidder_id.clone(), 2, &node).await.unwrap(), "queued");
+    assert!(wait_for(Duration::from_secs(10), || async { balance_request(&owner, bidder_id.clone(), &node).await.unwrap().balance == 20 }).await);
+    assert_eq!(transaction_status_request(&owner, owner_id.clone(), 10, bidder_id.clone(), 1, &node).await.unwrap(), "confirmed");
+    // once mined the transfer is refused again, however long ago it was relayed
+    assert_eq!(try_transaction_request(&owner, owner_id.clone(), 10, bidder_id.clone(), 1, &node).await.unwrap_err().code(), Code::AlreadyExists);
+    // only the bidder's key spends from its account, whether sent as the bidder or relayed by another node
+    let impostor = Node::client(Identity{ id: bidder_id.clone(), ..Identity::generate(KEY_ALGORITHM, 10) });
+    assert_eq!(try_transaction_request(&impostor, bidder_id.clone(), 5, owner_id.clone(), 4, &node).await.unwrap_err().code(), Code::PermissionDenied);
+    assert_eq!(try_transaction_request(&owner, bidder_id.clone(), 5, owner_id.clone(), 4, &node).await.unwrap_err().code(), Code::PermissionDenied);
+    assert_eq!(balance_request(&owner, bidder_id.clone(), &node).await.unwrap().balance, 20);
+
+    let name = create_bid_request(&owner, String::new(), &node).await.unwrap();
+    assert!(!name.is_empty());
+    assert_eq!(create_bid_request(&owner, name.clone(), &node).await.unwrap_err().code(), Code::AlreadyExists);
+    assert_eq!(bid_value_request(&bidder, name.clone(), 21, &node).await.unwrap_err().code(), Code::FailedPrecondition);
+    assert!(bid_value_request(&bidder, name.clone(), 7, &node).await.unwrap());
+    // nor bid under it
+    assert_eq!(bid_value_request(&impostor, name.clone(), 9, &node).await.unwrap_err().code(), Code::PermissionDenied);
+    assert!(!bid_value_request(&bidder, name.clone(), 5, &node).await.unwrap());
+    assert_eq!(close_bid_request(&bidder, name.clone(), &node).await.unwrap_err().code(), Code::PermissionDenied);
+
+    let closed = close_bid_request(&owner, name.clone(), &node).await.unwrap();
+    assert_eq!((closed.highest_bidder.as_str(), closed.value), (bidder_id.as_str(), 7));
+    let auctions = auctions_request(&bidder, &node).await.unwrap();
+    assert!(auctions.open.is_empty());
+    assert_eq!(auctions.closed.len(), 1);
+    // closing charges no one, the winner pays with a transfer of their own, credited once mined
+    assert!(network.miners[0].mempool().await.is_empty());
+    assert_eq!(try_transaction_request(&bidder, bidder_id.clone(), 7, owner_id.clone(), 3, &node).await.unwrap(), "processed");
+    assert_eq!(balance_request(&owner, String::new(), &node).await.unwrap().balance, 20);
+    assert!(network.miners[0].mempool().await.iter().any(|i| i.starts_with(&format!("{}->7->{}->", bidder_id, owner_id))));
+    assert_eq!(bid_value_request(&bidder, name, 2, &node).await.unwrap_err().code(), Code::NotFound);
+}